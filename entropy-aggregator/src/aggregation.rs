@@ -23,7 +23,7 @@ pub fn sort_and_concatenate_secrets(reveals: HashMap<NodeId, RevealPayload>) ->
     // Concatenate the secrets in the sorted order
     let mut concatenated_secrets = Vec::new();
     for (_, reveal_payload) in sorted_map {
-        concatenated_secrets.extend_from_slice(&reveal_payload.secret);
+        concatenated_secrets.extend_from_slice(reveal_payload.secret.expose());
     }
     
     concatenated_secrets
@@ -39,15 +39,18 @@ mod tests {
         let mut reveals = HashMap::new();
         reveals.insert("node3".to_string(), RevealPayload {
             round_id: 1,
-            secret: [3u8; 32],
+            secret: [3u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals.insert("node1".to_string(), RevealPayload {
             round_id: 1,
-            secret: [1u8; 32],
+            secret: [1u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals.insert("node2".to_string(), RevealPayload {
             round_id: 1,
-            secret: [2u8; 32],
+            secret: [2u8; 32].into(),
+            signature: Vec::new(),
         });
 
         // Call the function multiple times to ensure deterministic output
@@ -74,29 +77,35 @@ mod tests {
         let mut reveals1 = HashMap::new();
         reveals1.insert("node3".to_string(), RevealPayload {
             round_id: 1,
-            secret: [3u8; 32],
+            secret: [3u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals1.insert("node1".to_string(), RevealPayload {
             round_id: 1,
-            secret: [1u8; 32],
+            secret: [1u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals1.insert("node2".to_string(), RevealPayload {
             round_id: 1,
-            secret: [2u8; 32],
+            secret: [2u8; 32].into(),
+            signature: Vec::new(),
         });
 
         let mut reveals2 = HashMap::new();
         reveals2.insert("node2".to_string(), RevealPayload {
             round_id: 1,
-            secret: [2u8; 32],
+            secret: [2u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals2.insert("node1".to_string(), RevealPayload {
             round_id: 1,
-            secret: [1u8; 32],
+            secret: [1u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals2.insert("node3".to_string(), RevealPayload {
             round_id: 1,
-            secret: [3u8; 32],
+            secret: [3u8; 32].into(),
+            signature: Vec::new(),
         });
 
         // Both should produce the same result since they are sorted
@@ -111,7 +120,8 @@ mod tests {
         let mut reveals = HashMap::new();
         reveals.insert("node1".to_string(), RevealPayload {
             round_id: 1,
-            secret: [42u8; 32],
+            secret: [42u8; 32].into(),
+            signature: Vec::new(),
         });
 
         let result = sort_and_concatenate_secrets(reveals);
@@ -134,15 +144,18 @@ mod tests {
         let mut reveals = HashMap::new();
         reveals.insert("node10".to_string(), RevealPayload {
             round_id: 1,
-            secret: [10u8; 32],
+            secret: [10u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals.insert("node2".to_string(), RevealPayload {
             round_id: 1,
-            secret: [2u8; 32],
+            secret: [2u8; 32].into(),
+            signature: Vec::new(),
         });
         reveals.insert("node1".to_string(), RevealPayload {
             round_id: 1,
-            secret: [1u8; 32],
+            secret: [1u8; 32].into(),
+            signature: Vec::new(),
         });
 
         let result = sort_and_concatenate_secrets(reveals);
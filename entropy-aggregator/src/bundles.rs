@@ -0,0 +1,242 @@
+//! Import of offline contribution bundles.
+//!
+//! Workers on air-gapped machines hand in their commitments and reveals as
+//! signed bundle files. A bundle is checked against the key its node had
+//! registered for the bundle's round and then processed exactly like the
+//! message it carries would be when received over the network.
+//!
+//! Operators upload bundles by copying them into the import directory. Each
+//! scan moves processed files to `imported/` or `rejected/`; bundles for a
+//! round or phase the aggregator has not reached yet stay until it has.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use entropy_types::bundle::{BundleContent, OfflineBundle};
+use log::{info, debug, warn};
+
+use crate::aggregator::Aggregator;
+
+/// How often the import directory is scanned
+pub const IMPORT_SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Subdirectory processed bundles are moved to
+pub const IMPORTED_DIR: &str = "imported";
+
+/// Subdirectory refused bundles are moved to
+pub const REJECTED_DIR: &str = "rejected";
+
+/// Result of importing one bundle
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportOutcome {
+    /// The aggregator accepted the bundle's message
+    Accepted,
+    /// The bundle or its message was refused
+    Rejected(String),
+    /// The round or phase the message belongs to has not started yet
+    Deferred,
+}
+
+/// Verify a bundle and hand its message to the aggregator
+pub async fn import_bundle(aggregator: &Aggregator, bundle: OfflineBundle) -> ImportOutcome {
+    let Some(public_key) = aggregator.committee.public_key_for_round(&bundle.node_id, bundle.round_id) else {
+        return ImportOutcome::Rejected(format!("node {} is not in the committee", bundle.node_id));
+    };
+    if let Err(e) = bundle.verify(&public_key) {
+        return ImportOutcome::Rejected(e.to_string());
+    }
+    if is_early(aggregator, &bundle.content) {
+        return ImportOutcome::Deferred;
+    }
+
+    let kind = bundle.content.kind();
+    let processed = match bundle.content {
+        BundleContent::Commitment(msg) => aggregator.process_commitment(msg).await,
+        BundleContent::Reveal(msg) => aggregator.process_reveal(msg).await,
+    };
+    match processed {
+        Ok(true) => ImportOutcome::Accepted,
+        Ok(false) => ImportOutcome::Rejected(format!("{} refused for round {}", kind, bundle.round_id)),
+        Err(e) => ImportOutcome::Rejected(e.to_string()),
+    }
+}
+
+/// Whether the message belongs to a round or phase the aggregator has not reached
+fn is_early(aggregator: &Aggregator, content: &BundleContent) -> bool {
+    let current_round = aggregator.get_round_id();
+    match content {
+        BundleContent::Commitment(msg) => msg.round_id > current_round,
+        BundleContent::Reveal(msg) => {
+            msg.round_id > current_round
+                || (msg.round_id == current_round && aggregator.get_state().is_collecting_commitments())
+        }
+    }
+}
+
+/// Imports the bundles dropped into a directory
+pub struct BundleImporter {
+    aggregator: Arc<Aggregator>,
+    dir: PathBuf,
+}
+
+impl BundleImporter {
+    /// Create an importer for `dir`, creating it and its subdirectories if needed
+    pub fn new(aggregator: Arc<Aggregator>, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for subdir in [IMPORTED_DIR, REJECTED_DIR] {
+            fs::create_dir_all(dir.join(subdir)).map_err(|e| {
+                anyhow::Error::msg(format!("Failed to create import directory {}: {}", dir.join(subdir).display(), e))
+            })?;
+        }
+        Ok(BundleImporter { aggregator, dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Import every `.json` file in the directory, in file name order
+    ///
+    /// Returns the outcome for each file looked at.
+    pub async fn scan(&self) -> Result<Vec<(PathBuf, ImportOutcome)>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let mut outcomes = Vec::with_capacity(paths.len());
+        for path in paths {
+            let outcome = match read_bundle(&path) {
+                Ok(bundle) => import_bundle(&self.aggregator, bundle).await,
+                Err(e) => ImportOutcome::Rejected(e.to_string()),
+            };
+
+            match &outcome {
+                ImportOutcome::Accepted => {
+                    info!("Imported bundle {}", path.display());
+                    self.move_to(&path, IMPORTED_DIR)?;
+                }
+                ImportOutcome::Rejected(reason) => {
+                    warn!("Rejected bundle {}: {}", path.display(), reason);
+                    self.move_to(&path, REJECTED_DIR)?;
+                }
+                ImportOutcome::Deferred => {
+                    debug!("Bundle {} is for a later round or phase, keeping it", path.display());
+                }
+            }
+            outcomes.push((path, outcome));
+        }
+        Ok(outcomes)
+    }
+
+    fn move_to(&self, path: &Path, subdir: &str) -> Result<()> {
+        let Some(name) = path.file_name() else { return Ok(()) };
+        fs::rename(path, self.dir.join(subdir).join(name))?;
+        Ok(())
+    }
+}
+
+/// Read and decode a bundle file
+pub fn read_bundle(path: &Path) -> Result<OfflineBundle> {
+    let bytes = fs::read(path)?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| anyhow::Error::msg(format!("Malformed bundle {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::AggregatorConfig;
+    use crate::committee::CommitteeMember;
+    use entropy_worker::Worker;
+
+    fn worker(aggregator: &Aggregator, node_id: &str) -> Worker {
+        let worker = Worker::new(node_id.to_string()).unwrap();
+        aggregator
+            .committee
+            .add_member(CommitteeMember::new(node_id.to_string(), worker.get_public_key().serialize().to_vec()))
+            .unwrap();
+        worker
+    }
+
+    fn aggregator() -> Arc<Aggregator> {
+        let config = AggregatorConfig {
+            committee_size: 2,
+            threshold: 2,
+            ..Default::default()
+        };
+        Arc::new(Aggregator::new(config).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_import_bundles() {
+        let aggregator = aggregator();
+        let mut worker1 = worker(&aggregator, "node1");
+        let mut worker2 = worker(&aggregator, "node2");
+
+        // Bundles for a round that has not started are kept for later
+        let commitment1 = worker1.export_commitment(1).unwrap();
+        assert_eq!(import_bundle(&aggregator, commitment1.clone()).await, ImportOutcome::Deferred);
+
+        aggregator.start_new_round(1, vec!["node1".to_string(), "node2".to_string()]).await.unwrap();
+        assert_eq!(import_bundle(&aggregator, commitment1).await, ImportOutcome::Accepted);
+
+        // So are reveals that arrive during the commitment phase
+        let reveal1 = worker1.export_reveal(1).unwrap();
+        assert_eq!(import_bundle(&aggregator, reveal1.clone()).await, ImportOutcome::Deferred);
+
+        let commitment2 = worker2.export_commitment(1).unwrap();
+        assert_eq!(import_bundle(&aggregator, commitment2).await, ImportOutcome::Accepted);
+        assert!(aggregator.get_state().is_collecting_reveals());
+        assert_eq!(import_bundle(&aggregator, reveal1).await, ImportOutcome::Accepted);
+        assert_eq!(aggregator.get_reveal_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forged_bundles_are_rejected() {
+        let aggregator = aggregator();
+        aggregator.start_new_round(1, vec!["node1".to_string(), "node2".to_string()]).await.unwrap();
+        let _registered = worker(&aggregator, "node1");
+
+        // A bundle signed by another key than the one registered for the node
+        let mut impostor = Worker::new("node1".to_string()).unwrap();
+        let outcome = import_bundle(&aggregator, impostor.export_commitment(1).unwrap()).await;
+        assert!(matches!(outcome, ImportOutcome::Rejected(reason) if reason.contains("signature")));
+
+        let mut stranger = Worker::new("node3".to_string()).unwrap();
+        let outcome = import_bundle(&aggregator, stranger.export_commitment(1).unwrap()).await;
+        assert!(matches!(outcome, ImportOutcome::Rejected(reason) if reason.contains("not in the committee")));
+        assert_eq!(aggregator.get_commitment_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scan_moves_processed_files() {
+        let dir = std::env::temp_dir().join(format!("aggregator-import-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let aggregator = aggregator();
+        let importer = BundleImporter::new(aggregator.clone(), &dir).unwrap();
+        let mut worker1 = worker(&aggregator, "node1");
+
+        let commitment = worker1.export_commitment(1).unwrap();
+        fs::write(dir.join("node1-round-1-commitment.json"), serde_json::to_vec(&commitment).unwrap()).unwrap();
+        fs::write(dir.join("garbage.json"), b"not a bundle").unwrap();
+
+        // The round has not started: the commitment waits, the garbage is rejected
+        let outcomes = importer.scan().await.unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(dir.join(REJECTED_DIR).join("garbage.json").exists());
+        assert!(dir.join("node1-round-1-commitment.json").exists());
+
+        aggregator.start_new_round(1, vec!["node1".to_string(), "node2".to_string()]).await.unwrap();
+        let outcomes = importer.scan().await.unwrap();
+        assert_eq!(outcomes, vec![(dir.join("node1-round-1-commitment.json"), ImportOutcome::Accepted)]);
+        assert!(dir.join(IMPORTED_DIR).join("node1-round-1-commitment.json").exists());
+        assert_eq!(aggregator.get_commitment_count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
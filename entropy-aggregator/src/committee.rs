@@ -0,0 +1,478 @@
+//! Committee registry mapping node IDs to their secp256k1 public keys.
+//!
+//! The registry is loaded from a JSON file of the form
+//!
+//! ```json
+//! {
+//!   "members": [
+//!     { "node_id": "worker-1", "public_key": "02ab…", "metadata": { "operator": "acme" } }
+//!   ]
+//! }
+//! ```
+//!
+//! and can be changed at runtime, either member by member or by reloading the file.
+//! Runtime changes, such as approved registrations, are kept by saving the
+//! registry back to the file.
+//!
+//! A member's key can be rotated. The new key is recorded as `next_public_key`
+//! and takes over when the next round starts; the replaced key moves to
+//! `retired_keys` with the rounds it was valid for, so commitments from earlier
+//! rounds can still be checked against the key that signed them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::RwLock;
+
+use entropy_types::NodeId;
+use log::info;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AggregatorError;
+
+/// A committee member and the key its commitments must be signed with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CommitteeMember {
+    pub node_id: NodeId,
+    /// SEC1-encoded public key, hex encoded in the config file
+    #[serde(with = "hex_bytes")]
+    pub public_key: Vec<u8>,
+    /// Free-form operator information (contact, region, ...)
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    /// First round `public_key` is valid for
+    #[serde(default)]
+    pub key_valid_from: u64,
+    /// Key that replaces `public_key` when the next round starts
+    #[serde(default, with = "hex_bytes_opt", skip_serializing_if = "Option::is_none")]
+    pub next_public_key: Option<Vec<u8>>,
+    /// Keys the member used before `public_key`, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retired_keys: Vec<RetiredKey>,
+}
+
+/// A key a member rotated away from, with the rounds it was valid for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RetiredKey {
+    #[serde(with = "hex_bytes")]
+    pub public_key: Vec<u8>,
+    pub valid_from: u64,
+    pub valid_until: u64,
+}
+
+impl CommitteeMember {
+    pub fn new(node_id: NodeId, public_key: Vec<u8>) -> Self {
+        Self {
+            node_id,
+            public_key,
+            metadata: BTreeMap::new(),
+            key_valid_from: 0,
+            next_public_key: None,
+            retired_keys: Vec::new(),
+        }
+    }
+
+    /// The key that was valid for a round
+    pub fn public_key_for_round(&self, round_id: u64) -> Option<&[u8]> {
+        if round_id >= self.key_valid_from {
+            return Some(&self.public_key);
+        }
+        self.retired_keys
+            .iter()
+            .find(|key| key.valid_from <= round_id && round_id <= key.valid_until)
+            .map(|key| key.public_key.as_slice())
+    }
+
+    /// Whether the member has used or is about to use this key
+    fn uses_key(&self, public_key: &[u8]) -> bool {
+        self.public_key == public_key
+            || self.next_public_key.as_deref() == Some(public_key)
+            || self.retired_keys.iter().any(|key| key.public_key == public_key)
+    }
+
+    /// Check that the public key is a valid secp256k1 point
+    fn validate(&self) -> Result<(), AggregatorError> {
+        let keys = std::iter::once(&self.public_key)
+            .chain(self.next_public_key.as_ref())
+            .chain(self.retired_keys.iter().map(|key| &key.public_key));
+        for key in keys {
+            PublicKey::from_slice(key).map_err(|e| AggregatorError::ConfigError {
+                message: format!("Invalid public key for node {}: {}", self.node_id, e),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// On-disk layout of the committee file
+#[derive(Serialize, Deserialize, Debug)]
+struct CommitteeFile {
+    members: Vec<CommitteeMember>,
+}
+
+/// Thread-safe set of committee members
+#[derive(Debug, Default)]
+pub struct CommitteeRegistry {
+    members: RwLock<HashMap<NodeId, CommitteeMember>>,
+}
+
+impl CommitteeRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry from a list of members, rejecting invalid keys and duplicates
+    pub fn from_members(members: Vec<CommitteeMember>) -> Result<Self, AggregatorError> {
+        Ok(Self {
+            members: RwLock::new(Self::index(members)?),
+        })
+    }
+
+    /// Load the registry from a JSON committee file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AggregatorError> {
+        Self::from_members(Self::read_file(path.as_ref())?)
+    }
+
+    /// Parse the registry from the contents of a committee file
+    pub fn from_json(json: &str) -> Result<Self, AggregatorError> {
+        Self::from_members(Self::parse(json)?)
+    }
+
+    /// Replace the membership with the contents of the committee file
+    ///
+    /// The file is fully validated first; on error the current membership is kept.
+    pub fn reload(&self, path: impl AsRef<Path>) -> Result<(), AggregatorError> {
+        let members = Self::index(Self::read_file(path.as_ref())?)?;
+        let count = members.len();
+        *self.members.write().unwrap() = members;
+        info!("Reloaded committee from {}: {} members", path.as_ref().display(), count);
+        Ok(())
+    }
+
+    /// Write the membership to a committee file, sorted by node ID
+    ///
+    /// The file is replaced atomically, so a crash never leaves a partial committee.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AggregatorError> {
+        let path = path.as_ref();
+        let mut members: Vec<CommitteeMember> = self.members.read().unwrap().values().cloned().collect();
+        members.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+
+        let json = serde_json::to_vec_pretty(&CommitteeFile { members }).map_err(|e| AggregatorError::InternalError {
+            message: format!("Failed to encode committee: {}", e),
+        })?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, json)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| AggregatorError::ConfigError {
+                message: format!("Failed to write committee file {}: {}", path.display(), e),
+            })
+    }
+
+    /// Add a member at runtime
+    pub fn add_member(&self, member: CommitteeMember) -> Result<(), AggregatorError> {
+        member.validate()?;
+
+        let mut members = self.members.write().unwrap();
+        if members.contains_key(&member.node_id) {
+            return Err(AggregatorError::ConfigError {
+                message: format!("Node {} is already a committee member", member.node_id),
+            });
+        }
+        if let Some(owner) = members.values().find(|existing| existing.uses_key(&member.public_key)) {
+            return Err(AggregatorError::ConfigError {
+                message: format!("Key of node {} is already registered for node {}", member.node_id, owner.node_id),
+            });
+        }
+        info!("Added node {} to the committee", member.node_id);
+        members.insert(member.node_id.clone(), member);
+        Ok(())
+    }
+
+    /// Schedule a member's key to be replaced when the next round starts
+    ///
+    /// Scheduling the same key again is a no-op. A key that any member has used
+    /// before is refused, so an old rotation cannot be replayed.
+    pub fn schedule_rotation(&self, node_id: &str, new_public_key: Vec<u8>) -> Result<(), AggregatorError> {
+        PublicKey::from_slice(&new_public_key).map_err(|e| AggregatorError::ConfigError {
+            message: format!("Invalid public key for node {}: {}", node_id, e),
+        })?;
+
+        let mut members = self.members.write().unwrap();
+        let Some(member) = members.get(node_id) else {
+            return Err(AggregatorError::ConfigError {
+                message: format!("Node {} is not a committee member", node_id),
+            });
+        };
+        if member.next_public_key.as_ref() == Some(&new_public_key) {
+            return Ok(());
+        }
+        if members.values().any(|member| member.uses_key(&new_public_key)) {
+            return Err(AggregatorError::ConfigError {
+                message: format!("Key for node {} has been registered before", node_id),
+            });
+        }
+
+        let member = members.get_mut(node_id).expect("member checked above");
+        member.next_public_key = Some(new_public_key);
+        info!("Scheduled key rotation for node {}", node_id);
+        Ok(())
+    }
+
+    /// Switch every member with a scheduled rotation to its new key, starting at `round_id`
+    ///
+    /// All rotations are applied under one lock, so no round sees a partly rotated
+    /// committee. Returns the rotated node IDs.
+    pub fn apply_rotations(&self, round_id: u64) -> Vec<NodeId> {
+        let mut rotated = Vec::new();
+        for member in self.members.write().unwrap().values_mut() {
+            let Some(next_public_key) = member.next_public_key.take() else { continue };
+            let retired = RetiredKey {
+                public_key: std::mem::replace(&mut member.public_key, next_public_key),
+                valid_from: member.key_valid_from,
+                valid_until: round_id.saturating_sub(1),
+            };
+            member.retired_keys.push(retired);
+            member.key_valid_from = round_id;
+            info!("Rotated key of node {}, the new key is valid from round {}", member.node_id, round_id);
+            rotated.push(member.node_id.clone());
+        }
+        rotated.sort();
+        rotated
+    }
+
+    /// Remove a member at runtime, returning it if it was present
+    pub fn remove_member(&self, node_id: &str) -> Option<CommitteeMember> {
+        let removed = self.members.write().unwrap().remove(node_id);
+        if removed.is_some() {
+            info!("Removed node {} from the committee", node_id);
+        }
+        removed
+    }
+
+    /// Look up a member
+    pub fn get(&self, node_id: &str) -> Option<CommitteeMember> {
+        self.members.read().unwrap().get(node_id).cloned()
+    }
+
+    /// The public key registered for a node
+    pub fn public_key(&self, node_id: &str) -> Option<Vec<u8>> {
+        self.members.read().unwrap().get(node_id).map(|m| m.public_key.clone())
+    }
+
+    /// The public key a node signed a round with
+    pub fn public_key_for_round(&self, node_id: &str, round_id: u64) -> Option<Vec<u8>> {
+        let members = self.members.read().unwrap();
+        members.get(node_id)?.public_key_for_round(round_id).map(<[u8]>::to_vec)
+    }
+
+    /// The key a node rotates to when the next round starts, if any
+    pub fn next_public_key(&self, node_id: &str) -> Option<Vec<u8>> {
+        self.members.read().unwrap().get(node_id)?.next_public_key.clone()
+    }
+
+    pub fn contains(&self, node_id: &str) -> bool {
+        self.members.read().unwrap().contains_key(node_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Node IDs of all members, sorted
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.members.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn read_file(path: &Path) -> Result<Vec<CommitteeMember>, AggregatorError> {
+        let json = std::fs::read_to_string(path).map_err(|e| AggregatorError::ConfigError {
+            message: format!("Failed to read committee file {}: {}", path.display(), e),
+        })?;
+        Self::parse(&json)
+    }
+
+    fn parse(json: &str) -> Result<Vec<CommitteeMember>, AggregatorError> {
+        let file: CommitteeFile = serde_json::from_str(json).map_err(|e| AggregatorError::ConfigError {
+            message: format!("Invalid committee file: {}", e),
+        })?;
+        Ok(file.members)
+    }
+
+    fn index(members: Vec<CommitteeMember>) -> Result<HashMap<NodeId, CommitteeMember>, AggregatorError> {
+        let mut index = HashMap::with_capacity(members.len());
+        for member in members {
+            member.validate()?;
+            if index.contains_key(&member.node_id) {
+                return Err(AggregatorError::ConfigError {
+                    message: format!("Duplicate committee member {}", member.node_id),
+                });
+            }
+            index.insert(member.node_id.clone(), member);
+        }
+        Ok(index)
+    }
+}
+
+/// Serialize optional byte vectors as hex strings
+mod hex_bytes_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => super::hex_bytes::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => hex::decode(s.trim_start_matches("0x")).map(Some).map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Serialize byte vectors as hex strings
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entropy_worker::crypto::generate_keypair;
+
+    fn member(node_id: &str) -> CommitteeMember {
+        let (_, public_key) = generate_keypair().unwrap();
+        CommitteeMember::new(node_id.to_string(), public_key.serialize().to_vec())
+    }
+
+    #[test]
+    fn test_load_from_json() {
+        let (_, public_key) = generate_keypair().unwrap();
+        let json = format!(
+            r#"{{"members": [{{"node_id": "worker-1", "public_key": "{}", "metadata": {{"operator": "acme"}}}}]}}"#,
+            hex::encode(public_key.serialize())
+        );
+
+        let registry = CommitteeRegistry::from_json(&json).unwrap();
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.public_key("worker-1"), Some(public_key.serialize().to_vec()));
+        assert_eq!(registry.get("worker-1").unwrap().metadata["operator"], "acme");
+        assert!(!registry.contains("worker-2"));
+    }
+
+    #[test]
+    fn test_invalid_key_is_rejected() {
+        let json = r#"{"members": [{"node_id": "worker-1", "public_key": "0011"}]}"#;
+        assert!(matches!(CommitteeRegistry::from_json(json), Err(AggregatorError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn test_duplicate_member_is_rejected() {
+        let result = CommitteeRegistry::from_members(vec![member("worker-1"), member("worker-1")]);
+        assert!(matches!(result, Err(AggregatorError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn test_add_and_remove_at_runtime() {
+        let registry = CommitteeRegistry::new();
+        registry.add_member(member("worker-1")).unwrap();
+        registry.add_member(member("worker-2")).unwrap();
+        assert!(registry.add_member(member("worker-1")).is_err());
+        assert_eq!(registry.node_ids(), vec!["worker-1".to_string(), "worker-2".to_string()]);
+
+        assert!(registry.remove_member("worker-1").is_some());
+        assert!(registry.remove_member("worker-1").is_none());
+        assert_eq!(registry.node_ids(), vec!["worker-2".to_string()]);
+    }
+
+    #[test]
+    fn test_reload_replaces_membership() {
+        let dir = std::env::temp_dir().join(format!("committee-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("committee.json");
+
+        let registry = CommitteeRegistry::from_members(vec![member("worker-1")]).unwrap();
+
+        let file = CommitteeFile { members: vec![member("worker-2"), member("worker-3")] };
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        registry.reload(&path).unwrap();
+        assert_eq!(registry.node_ids(), vec!["worker-2".to_string(), "worker-3".to_string()]);
+
+        // A broken file leaves the current membership in place
+        std::fs::write(&path, "not json").unwrap();
+        assert!(registry.reload(&path).is_err());
+        assert_eq!(registry.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_round_trips() {
+        let dir = std::env::temp_dir().join(format!("committee-save-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("committee.json");
+
+        let registry = CommitteeRegistry::from_members(vec![member("worker-2")]).unwrap();
+        let mut added = member("worker-1");
+        added.metadata.insert("operator".to_string(), "acme".to_string());
+        registry.add_member(added.clone()).unwrap();
+        registry.save(&path).unwrap();
+
+        let loaded = CommitteeRegistry::load(&path).unwrap();
+        assert_eq!(loaded.node_ids(), vec!["worker-1".to_string(), "worker-2".to_string()]);
+        assert_eq!(loaded.get("worker-1"), Some(added));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_keeps_key_history() {
+        let dir = std::env::temp_dir().join(format!("committee-rotation-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("committee.json");
+
+        let registry = CommitteeRegistry::from_members(vec![member("worker-1"), member("worker-2")]).unwrap();
+        let first_key = registry.public_key("worker-1").unwrap();
+        let (_, second_key) = generate_keypair().unwrap();
+        let second_key = second_key.serialize().to_vec();
+
+        registry.schedule_rotation("worker-1", second_key.clone()).unwrap();
+        registry.schedule_rotation("worker-1", second_key.clone()).unwrap();
+        // Nobody can take over a key that is or was in use
+        assert!(registry.schedule_rotation("worker-2", second_key.clone()).is_err());
+        assert!(registry.schedule_rotation("worker-2", first_key.clone()).is_err());
+        assert!(registry.schedule_rotation("worker-3", member("worker-3").public_key).is_err());
+
+        // The current key stays valid until the rotation is applied
+        assert_eq!(registry.public_key("worker-1"), Some(first_key.clone()));
+        assert_eq!(registry.apply_rotations(5), vec!["worker-1".to_string()]);
+        assert!(registry.apply_rotations(6).is_empty());
+        assert_eq!(registry.public_key("worker-1"), Some(second_key.clone()));
+
+        // Earlier rounds still resolve to the key that signed them, also after a restart
+        registry.save(&path).unwrap();
+        let registry = CommitteeRegistry::load(&path).unwrap();
+        assert_eq!(registry.public_key_for_round("worker-1", 4), Some(first_key.clone()));
+        assert_eq!(registry.public_key_for_round("worker-1", 5), Some(second_key));
+        assert!(registry.schedule_rotation("worker-1", first_key).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
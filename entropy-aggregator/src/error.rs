@@ -20,6 +20,10 @@ pub enum AggregatorError {
     TEEError { message: String },
     /// Configuration error
     ConfigError { message: String },
+    /// Too few committee members are live to start a round
+    NotEnoughLiveMembers { live: usize, threshold: usize },
+    /// A round with this ID or a later one was started before
+    StaleRoundId { round_id: u64, last_round_id: u64 },
 }
 
 impl fmt::Display for AggregatorError {
@@ -52,6 +56,12 @@ impl fmt::Display for AggregatorError {
             AggregatorError::ConfigError { message } => {
                 write!(f, "Configuration error: {}", message)
             }
+            AggregatorError::NotEnoughLiveMembers { live, threshold } => {
+                write!(f, "Only {} live committee members, {} needed to start a round", live, threshold)
+            }
+            AggregatorError::StaleRoundId { round_id, last_round_id } => {
+                write!(f, "Round {} cannot be started, round {} was started before", round_id, last_round_id)
+            }
         }
     }
 }
@@ -0,0 +1,215 @@
+//! Events the aggregator emits as rounds progress.
+//!
+//! Every change worth reacting to is broadcast as an [`AggregatorEvent`] to all
+//! subscriptions taken with [`crate::aggregator::Aggregator::subscribe`]. A
+//! subscription only sees events emitted after it was taken.
+//!
+//! Events are kept in a bounded buffer. A subscriber that falls more than
+//! [`EVENT_CAPACITY`] events behind loses the oldest ones; its subscription
+//! skips ahead, logs how many it missed and counts them in
+//! [`EventSubscription::missed`], so consumers that need every event can tell
+//! they have to resynchronise, e.g. from `Aggregator::get_state`.
+
+use entropy_types::NodeId;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+
+/// Events buffered for subscribers that are behind
+///
+/// A round emits about two events per committee member, so this covers a
+/// round of a 500 member committee.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Why a commitment was refused
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    /// The round is not in the phase the message belongs to
+    WrongPhase,
+    /// The message is for another round
+    WrongRound,
+    /// The node is not in the round's committee or has no key for it
+    NotInCommittee,
+    /// The signature does not verify under the node's key
+    InvalidSignature,
+    /// The node already sent a different commitment for the round
+    Conflicting,
+}
+
+/// Why a round ended without an output
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "reason")]
+pub enum AbortReason {
+    /// Too few commitments arrived before the commitment deadline
+    CommitmentTimeout { commitments: usize, threshold: usize },
+    /// Too few reveals arrived before the reveal deadline
+    RevealTimeout { reveals: usize, threshold: usize },
+    /// The output could not be derived or submitted
+    AggregationFailed { error: String },
+    /// A new round was started while this one was running
+    Superseded { by: u64 },
+    /// The operator gave up on the round, e.g. at shutdown
+    Abandoned,
+    /// The aggregator restarted before the round had all its reveals
+    Restarted,
+}
+
+/// Something that happened to a round
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum AggregatorEvent {
+    /// Commitments are being collected from `committee`
+    RoundStarted { round_id: u64, committee: Vec<NodeId> },
+    CommitmentAccepted { round_id: u64, node_id: NodeId },
+    /// `round_id` is the round the commitment claimed to be for
+    CommitmentRejected { round_id: u64, node_id: NodeId, reason: Rejection },
+    /// The commitment threshold was reached and `committed` were asked to reveal
+    RevealPhase { round_id: u64, committed: Vec<NodeId> },
+    /// The round ended without an output
+    RoundAborted { round_id: u64, reason: AbortReason },
+    /// The round's output was derived from the reveals
+    Aggregated {
+        round_id: u64,
+        #[serde(with = "hex_array")]
+        output: [u8; 32],
+    },
+    /// The round's output was submitted to the beacon microchain
+    Submitted { round_id: u64, tx_hash: String },
+}
+
+impl AggregatorEvent {
+    pub fn round_id(&self) -> u64 {
+        match self {
+            AggregatorEvent::RoundStarted { round_id, .. }
+            | AggregatorEvent::CommitmentAccepted { round_id, .. }
+            | AggregatorEvent::CommitmentRejected { round_id, .. }
+            | AggregatorEvent::RevealPhase { round_id, .. }
+            | AggregatorEvent::RoundAborted { round_id, .. }
+            | AggregatorEvent::Aggregated { round_id, .. }
+            | AggregatorEvent::Submitted { round_id, .. } => *round_id,
+        }
+    }
+}
+
+/// Sending side of the event stream, cheap to clone
+#[derive(Debug, Clone)]
+pub struct EventSender {
+    sender: broadcast::Sender<AggregatorEvent>,
+}
+
+impl Default for EventSender {
+    fn default() -> Self {
+        Self::new(EVENT_CAPACITY)
+    }
+}
+
+impl EventSender {
+    /// Create an event stream buffering up to `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Send an event to the current subscribers; dropped if there are none
+    pub fn emit(&self, event: AggregatorEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> EventSubscription {
+        EventSubscription {
+            receiver: self.sender.subscribe(),
+            missed: 0,
+        }
+    }
+}
+
+/// Receiving side of the event stream
+#[derive(Debug)]
+pub struct EventSubscription {
+    receiver: broadcast::Receiver<AggregatorEvent>,
+    missed: u64,
+}
+
+impl EventSubscription {
+    /// Wait for the next event; `None` once the aggregator is gone
+    ///
+    /// Events lost because the subscriber fell behind are skipped.
+    pub async fn recv(&mut self) -> Option<AggregatorEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(skipped)) => self.lagged(skipped),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The next event if one is waiting, without blocking
+    pub fn try_recv(&mut self) -> Option<AggregatorEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Lagged(skipped)) => self.lagged(skipped),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Events this subscription lost by falling behind
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    fn lagged(&mut self, skipped: u64) {
+        warn!("Event subscriber fell behind and missed {} events", skipped);
+        self.missed += skipped;
+    }
+}
+
+/// Serialize the output as a hex string
+mod hex_array {
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(round_id: u64) -> AggregatorEvent {
+        AggregatorEvent::RoundStarted { round_id, committee: Vec::new() }
+    }
+
+    #[test]
+    fn test_lagging_subscriber_skips_ahead() {
+        let events = EventSender::new(2);
+        let mut subscription = events.subscribe();
+        for round_id in 1..=5 {
+            events.emit(started(round_id));
+        }
+
+        assert_eq!(subscription.try_recv(), Some(started(4)));
+        assert_eq!(subscription.missed(), 3);
+        assert_eq!(subscription.try_recv(), Some(started(5)));
+        assert_eq!(subscription.try_recv(), None);
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = AggregatorEvent::RoundAborted {
+            round_id: 3,
+            reason: AbortReason::RevealTimeout { reveals: 1, threshold: 2 },
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"event": "round_aborted", "round_id": 3, "reason": {"reason": "reveal_timeout", "reveals": 1, "threshold": 2}})
+        );
+
+        let event = AggregatorEvent::Aggregated { round_id: 3, output: [0xab; 32] };
+        assert_eq!(serde_json::to_value(&event).unwrap()["output"], "ab".repeat(32));
+    }
+}
@@ -0,0 +1,96 @@
+//! Freshness checks on the timestamps of worker messages.
+//!
+//! Commitments and reveals carry the worker's send time in seconds since the
+//! Unix epoch. A message older than the freshness window is refused as stale,
+//! and one stamped further ahead than the tolerated clock skew is refused as
+//! coming from the future. The skew is granted in both directions, so a worker
+//! whose clock lags behind still gets the whole window.
+//!
+//! Only messages received over the network are checked; offline bundles are
+//! imported long after they were written.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How far message timestamps may be from the aggregator's clock
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreshnessConfig {
+    /// Maximum age of a message
+    pub window: Duration,
+    /// Tolerated difference between the worker's and the aggregator's clocks
+    pub max_clock_skew: Duration,
+}
+
+impl Default for FreshnessConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            max_clock_skew: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Why a timestamp was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FreshnessError {
+    /// The message is older than the window allows
+    Stale { age_secs: u64 },
+    /// The message is stamped further ahead than the clock skew allows
+    FromFuture { ahead_secs: u64 },
+}
+
+impl fmt::Display for FreshnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreshnessError::Stale { age_secs } => write!(f, "message is stale, sent {} seconds ago", age_secs),
+            FreshnessError::FromFuture { ahead_secs } => {
+                write!(f, "message is from the future, stamped {} seconds ahead", ahead_secs)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FreshnessError {}
+
+impl FreshnessConfig {
+    /// Check a timestamp against the time `now`, both in seconds since the Unix epoch
+    pub fn check(&self, timestamp: u64, now: u64) -> Result<(), FreshnessError> {
+        let skew = self.max_clock_skew.as_secs();
+        if timestamp > now.saturating_add(skew) {
+            return Err(FreshnessError::FromFuture { ahead_secs: timestamp - now });
+        }
+        let age = now.saturating_sub(timestamp);
+        if age > self.window.as_secs().saturating_add(skew) {
+            return Err(FreshnessError::Stale { age_secs: age });
+        }
+        Ok(())
+    }
+
+    /// Check a timestamp against the current time
+    pub fn check_now(&self, timestamp: u64) -> Result<(), FreshnessError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.check(timestamp, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freshness_bounds() {
+        let config = FreshnessConfig {
+            window: Duration::from_secs(60),
+            max_clock_skew: Duration::from_secs(5),
+        };
+        let now = 1_700_000_000;
+
+        assert_eq!(config.check(now, now), Ok(()));
+        assert_eq!(config.check(now + 5, now), Ok(()));
+        assert_eq!(config.check(now - 65, now), Ok(()));
+
+        assert_eq!(config.check(now + 6, now), Err(FreshnessError::FromFuture { ahead_secs: 6 }));
+        assert_eq!(config.check(now - 66, now), Err(FreshnessError::Stale { age_secs: 66 }));
+        assert_eq!(config.check(0, now), Err(FreshnessError::Stale { age_secs: now }));
+    }
+}
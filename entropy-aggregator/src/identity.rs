@@ -0,0 +1,135 @@
+//! The aggregator's identity key.
+//!
+//! The aggregator signs its round control messages (see
+//! `entropy_types::crypto::ControlMessage`) so workers can tell them from
+//! messages injected by anyone else who can reach them. Workers pin the public
+//! key in their configuration.
+//!
+//! The secret key is stored hex-encoded in a file readable only by the
+//! aggregator's user, and generated on first start.
+
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use entropy_types::crypto::ControlMessage;
+use log::info;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use zeroize::Zeroizing;
+
+/// Identity file used when none is given on the command line
+pub const DEFAULT_IDENTITY_PATH: &str = "aggregator-identity.key";
+
+/// Key pair the aggregator signs control messages with
+pub struct AggregatorIdentity {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+}
+
+impl AggregatorIdentity {
+    /// Generate a fresh random identity
+    pub fn generate() -> Self {
+        loop {
+            let bytes = Zeroizing::new(rand::random::<[u8; 32]>());
+            if let Ok(secret_key) = SecretKey::from_slice(bytes.as_ref()) {
+                return Self::from_secret_key(secret_key);
+            }
+        }
+    }
+
+    pub fn from_secret_key(secret_key: SecretKey) -> Self {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        Self { secret_key, public_key }
+    }
+
+    /// Load the identity from `path`, generating and saving a new one if the file does not exist
+    pub fn load_or_generate(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let text = Zeroizing::new(fs::read_to_string(path).map_err(|e| {
+                anyhow::Error::msg(format!("Failed to read identity file {}: {}", path.display(), e))
+            })?);
+            let bytes = Zeroizing::new(hex::decode(text.trim()).map_err(|e| {
+                anyhow::Error::msg(format!("Malformed identity file {}: {}", path.display(), e))
+            })?);
+            let secret_key = SecretKey::from_slice(&bytes).map_err(|e| {
+                anyhow::Error::msg(format!("Malformed identity file {}: {}", path.display(), e))
+            })?;
+            return Ok(Self::from_secret_key(secret_key));
+        }
+
+        let identity = Self::generate();
+        let encoded = Zeroizing::new(hex::encode(identity.secret_key.secret_bytes()));
+        write_private_file(path, encoded.as_bytes())
+            .map_err(|e| anyhow::Error::msg(format!("Failed to write identity file {}: {}", path.display(), e)))?;
+        info!("Generated aggregator identity in {}", path.display());
+        Ok(identity)
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Sign a control message with the identity key
+    pub fn sign<M: ControlMessage>(&self, message: &mut M) {
+        message.sign(&self.secret_key);
+    }
+}
+
+impl fmt::Debug for AggregatorIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AggregatorIdentity")
+            .field("public_key", &hex::encode(self.public_key.serialize()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AggregatorIdentity {
+    fn drop(&mut self) {
+        self.secret_key.non_secure_erase();
+    }
+}
+
+/// Create a file readable only by the current user, refusing to overwrite one
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entropy_types::StartRevealMsg;
+
+    #[test]
+    fn test_identity_is_generated_once() {
+        let path = std::env::temp_dir().join(format!("aggregator-identity-test-{}.key", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let identity = AggregatorIdentity::load_or_generate(&path).unwrap();
+        let reloaded = AggregatorIdentity::load_or_generate(&path).unwrap();
+        assert_eq!(identity.public_key(), reloaded.public_key());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let mut message = StartRevealMsg { round_id: 1, signature: Vec::new() };
+        reloaded.sign(&mut message);
+        assert_eq!(message.verify(&identity.public_key().serialize()), Ok(true));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
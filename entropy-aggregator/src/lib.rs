@@ -1,8 +1,19 @@
 pub mod tee;
 pub mod state_machine;
 pub mod aggregator;
+pub mod bundles;
+pub mod committee;
+pub mod freshness;
+pub mod identity;
+pub mod liveness;
 pub mod network;
+pub mod registration;
+pub mod round;
+pub mod scheduler;
+pub mod sessions;
+pub mod store;
 pub mod error;
+pub mod events;
 pub mod aggregation;
 pub mod linera_client;
 
@@ -6,6 +6,7 @@ use anyhow::Result;
 use log::{info, warn};
 use tokio::time::timeout;
 use beacon_microchain::{BeaconOperation, RandomnessEvent};
+use zeroize::Zeroizing;
 
 /// Configuration for Linera client
 #[derive(Debug, Clone)]
@@ -114,9 +115,10 @@ impl RealLineraProvider {
         let client = reqwest::Client::new();
         
         // Load the aggregator's private key from file
-        let key_data = std::fs::read_to_string(&config.aggregator_key_path)?;
+        let key_data = Zeroizing::new(std::fs::read_to_string(&config.aggregator_key_path)?);
         let private_key_hex = key_data.trim();
         let private_key_bytes = hex::decode(private_key_hex)
+            .map(Zeroizing::new)
             .map_err(|e| anyhow::anyhow!("Failed to decode private key: {}", e))?;
         
         if private_key_bytes.len() != 32 {
@@ -201,6 +203,12 @@ impl RealLineraProvider {
     }
 }
 
+impl Drop for RealLineraProvider {
+    fn drop(&mut self) {
+        self.private_key.non_secure_erase();
+    }
+}
+
 #[async_trait::async_trait]
 impl LineraProvider for RealLineraProvider {
     async fn submit_randomness(&self, event: RandomnessEvent) -> Result<String> {
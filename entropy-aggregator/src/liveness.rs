@@ -0,0 +1,228 @@
+//! Liveness table built from worker heartbeats.
+//!
+//! Every accepted heartbeat refreshes its node's entry. A node counts as live
+//! while its last heartbeat is younger than the configured timeout; rounds are
+//! only started with live committee members.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use entropy_types::{HeartbeatMsg, NodeId};
+use serde::Serialize;
+
+/// Liveness settings
+#[derive(Debug, Clone)]
+pub struct LivenessConfig {
+    /// How long a node stays live after its last heartbeat
+    pub timeout: Duration,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            // Three missed heartbeats at the worker's default 10 second interval
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Operator view of a node's liveness
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NodeLiveness {
+    pub node_id: NodeId,
+    pub live: bool,
+    /// Arrival time of the last heartbeat, in milliseconds since the Unix epoch
+    pub last_seen_ms: u64,
+    /// Milliseconds since the last heartbeat
+    pub age_ms: u64,
+    /// One-way delay of the last heartbeat; includes any clock skew between the hosts
+    pub latency_ms: u64,
+    pub status: String,
+    pub version: String,
+    pub heartbeats: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    last_seen: Instant,
+    last_seen_ms: u64,
+    sent_at_ms: u64,
+    latency_ms: u64,
+    status: String,
+    version: String,
+    heartbeats: u64,
+}
+
+/// Thread-safe table of the last heartbeat seen from each node
+#[derive(Debug, Default)]
+pub struct LivenessTable {
+    config: LivenessConfig,
+    entries: RwLock<HashMap<NodeId, Entry>>,
+}
+
+impl LivenessTable {
+    pub fn new(config: LivenessConfig) -> Self {
+        Self {
+            config,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &LivenessConfig {
+        &self.config
+    }
+
+    /// Record an authenticated heartbeat
+    ///
+    /// Returns `false` without updating the table if the heartbeat is not newer
+    /// than the last one recorded for the node, so replays cannot keep it alive.
+    pub fn record(&self, heartbeat: &HeartbeatMsg) -> bool {
+        let now_ms = unix_millis();
+        let mut entries = self.entries.write().unwrap();
+
+        if let Some(entry) = entries.get(&heartbeat.node_id) {
+            if heartbeat.sent_at_ms <= entry.sent_at_ms {
+                return false;
+            }
+        }
+
+        let heartbeats = entries.get(&heartbeat.node_id).map_or(0, |entry| entry.heartbeats);
+        entries.insert(heartbeat.node_id.clone(), Entry {
+            last_seen: Instant::now(),
+            last_seen_ms: now_ms,
+            sent_at_ms: heartbeat.sent_at_ms,
+            latency_ms: now_ms.saturating_sub(heartbeat.sent_at_ms),
+            status: heartbeat.status.clone(),
+            version: heartbeat.version.clone(),
+            heartbeats: heartbeats + 1,
+        });
+        true
+    }
+
+    /// Whether the node sent a heartbeat within the timeout
+    pub fn is_live(&self, node_id: &str) -> bool {
+        self.entries
+            .read()
+            .unwrap()
+            .get(node_id)
+            .is_some_and(|entry| entry.last_seen.elapsed() <= self.config.timeout)
+    }
+
+    /// Node IDs of all live nodes, sorted
+    pub fn live_nodes(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self
+            .entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.last_seen.elapsed() <= self.config.timeout)
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Liveness of a single node, if it ever sent a heartbeat
+    pub fn get(&self, node_id: &str) -> Option<NodeLiveness> {
+        self.entries.read().unwrap().get(node_id).map(|entry| self.view(node_id, entry))
+    }
+
+    /// Liveness of every node that ever sent a heartbeat, sorted by node ID
+    pub fn snapshot(&self) -> Vec<NodeLiveness> {
+        let mut nodes: Vec<NodeLiveness> = self
+            .entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(node_id, entry)| self.view(node_id, entry))
+            .collect();
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        nodes
+    }
+
+    /// Forget a node, e.g. after it left the committee
+    pub fn remove(&self, node_id: &str) -> bool {
+        self.entries.write().unwrap().remove(node_id).is_some()
+    }
+
+    fn view(&self, node_id: &str, entry: &Entry) -> NodeLiveness {
+        let age = entry.last_seen.elapsed();
+        NodeLiveness {
+            node_id: node_id.to_string(),
+            live: age <= self.config.timeout,
+            last_seen_ms: entry.last_seen_ms,
+            age_ms: age.as_millis() as u64,
+            latency_ms: entry.latency_ms,
+            status: entry.status.clone(),
+            version: entry.version.clone(),
+            heartbeats: entry.heartbeats,
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(node_id: &str, sent_at_ms: u64) -> HeartbeatMsg {
+        HeartbeatMsg {
+            node_id: node_id.to_string(),
+            timestamp: sent_at_ms / 1000,
+            status: "idle".to_string(),
+            version: "0.1.0".to_string(),
+            sent_at_ms,
+            signature: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let table = LivenessTable::new(LivenessConfig::default());
+        let sent_at_ms = unix_millis() - 40;
+        assert!(table.record(&heartbeat("worker-2", sent_at_ms)));
+        assert!(table.record(&heartbeat("worker-1", sent_at_ms)));
+        assert!(table.record(&heartbeat("worker-1", sent_at_ms + 1)));
+
+        assert!(table.is_live("worker-1"));
+        assert!(!table.is_live("worker-3"));
+        assert_eq!(table.live_nodes(), vec!["worker-1".to_string(), "worker-2".to_string()]);
+
+        let node = table.get("worker-1").unwrap();
+        assert!(node.live);
+        assert_eq!(node.heartbeats, 2);
+        assert_eq!(node.status, "idle");
+        assert!(node.latency_ms >= 39);
+        assert_eq!(table.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_replayed_heartbeat_is_ignored() {
+        let table = LivenessTable::new(LivenessConfig::default());
+        let sent_at_ms = unix_millis();
+        assert!(table.record(&heartbeat("worker-1", sent_at_ms)));
+
+        assert!(!table.record(&heartbeat("worker-1", sent_at_ms)));
+        assert!(!table.record(&heartbeat("worker-1", sent_at_ms - 1)));
+        assert_eq!(table.get("worker-1").unwrap().heartbeats, 1);
+    }
+
+    #[test]
+    fn test_nodes_expire_after_timeout() {
+        let table = LivenessTable::new(LivenessConfig { timeout: Duration::from_millis(20) });
+        table.record(&heartbeat("worker-1", unix_millis()));
+        assert!(table.is_live("worker-1"));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!table.is_live("worker-1"));
+        assert!(table.live_nodes().is_empty());
+        assert!(!table.get("worker-1").unwrap().live);
+    }
+}
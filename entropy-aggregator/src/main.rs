@@ -1,12 +1,24 @@
-use log::{info, debug, error};
+use log::{info, debug, error, warn};
 use env_logger::Env;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use clap::Parser;
 use tokio::signal;
 
 use entropy_aggregator::tee::{create_tee_enclave, TEEConfig};
 use entropy_aggregator::aggregator::{Aggregator, AggregatorConfig};
+use entropy_aggregator::bundles::{BundleImporter, IMPORT_SCAN_INTERVAL};
+use entropy_aggregator::committee::CommitteeRegistry;
+use entropy_aggregator::freshness::FreshnessConfig;
+use entropy_aggregator::identity::{AggregatorIdentity, DEFAULT_IDENTITY_PATH};
+use entropy_aggregator::liveness::{LivenessConfig, LivenessTable, NodeLiveness};
 use entropy_aggregator::network::NetworkHandler;
+use entropy_aggregator::registration::{Registrar, RegistrationMode, APPROVAL_SCAN_INTERVAL};
+use entropy_aggregator::scheduler::RoundScheduler;
+use entropy_aggregator::sessions::{DeliveryConfig, SessionRegistry};
+use entropy_aggregator::store::{FileRoundStore, DEFAULT_SEGMENT_RECORDS, DEFAULT_STORE_PATH};
+use entropy_types::RoundSchedule;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -22,8 +34,74 @@ struct Args {
     /// Port to listen on
     #[arg(long, default_value_t = 900)]
     port: u16,
+    
+    /// Start time of round 1 in seconds since the Unix epoch; rounds are only scheduled when set
+    #[arg(long)]
+    genesis_time: Option<u64>,
+    
+    /// Seconds between the starts of consecutive rounds
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u64).range(1..))]
+    round_period_secs: u64,
+    
+    /// File holding the key control messages are signed with, created on first start
+    #[arg(long, default_value = DEFAULT_IDENTITY_PATH)]
+    identity_file: PathBuf,
+    
+    /// Append-only file the rounds are recorded in and recovered from after a restart
+    #[arg(long, default_value = DEFAULT_STORE_PATH)]
+    round_store: PathBuf,
+    
+    /// Records after which the round store file is kept aside and a new one begun
+    #[arg(long, default_value_t = DEFAULT_SEGMENT_RECORDS)]
+    round_store_segment_records: usize,
+    
+    /// JSON file listing committee members and their public keys
+    #[arg(long)]
+    committee_file: Option<PathBuf>,
+    
+    /// Seconds without a heartbeat after which a worker is no longer live
+    #[arg(long, default_value_t = 30)]
+    liveness_timeout_secs: u64,
+    
+    /// Seconds after which a worker's commitment or reveal is refused as stale
+    #[arg(long, default_value_t = 60)]
+    freshness_window_secs: u64,
+    
+    /// Seconds the workers' clocks may be ahead of or behind the aggregator's
+    #[arg(long, default_value_t = 10)]
+    max_clock_skew_secs: u64,
+    
+    /// Seconds to wait for a worker to answer a start message before sending it again
+    #[arg(long, default_value_t = 5)]
+    delivery_retry_secs: u64,
+    
+    /// Times a start message is sent before a worker that does not answer is marked unreachable
+    #[arg(long, default_value_t = 3)]
+    delivery_attempts: u32,
+    
+    /// File the liveness table is periodically written to as JSON
+    #[arg(long)]
+    liveness_file: Option<PathBuf>,
+    
+    /// Directory offline workers' commitment and reveal bundles are imported from
+    #[arg(long)]
+    import_dir: Option<PathBuf>,
+    
+    /// How registrations of new worker keys are handled
+    #[arg(long, value_enum, default_value_t = RegistrationMode::Closed)]
+    registration: RegistrationMode,
+    
+    /// Approval queue for registrations in manual mode
+    #[arg(long, default_value = "registrations")]
+    registration_dir: PathBuf,
 }
 
+/// How often the liveness table is reported
+const LIVENESS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often unanswered start messages are checked for a retry
+const DELIVERY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
@@ -40,8 +118,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         ..Default::default()
     };
     
+    // Load the committee registry
+    let committee = match &args.committee_file {
+        Some(path) => {
+            let registry = CommitteeRegistry::load(path)?;
+            info!("Loaded {} committee members from {}", registry.len(), path.display());
+            registry
+        }
+        None => {
+            warn!("No committee file given, all commitments will be rejected until members are added");
+            CommitteeRegistry::new()
+        }
+    };
+    
     // Create the aggregator
-    let aggregator = Arc::new(Aggregator::new(config)?);
+    let committee = Arc::new(committee);
+    let store = FileRoundStore::open(&args.round_store)?.with_segment_records(args.round_store_segment_records);
+    info!("Recording rounds in {}", store.path().display());
+    let mut aggregator = Aggregator::with_store(config, committee.clone(), Arc::new(store))?;
+    aggregator.liveness = Arc::new(LivenessTable::new(LivenessConfig {
+        timeout: Duration::from_secs(args.liveness_timeout_secs),
+    }));
+    aggregator.sessions = Arc::new(SessionRegistry::new(DeliveryConfig {
+        retry_interval: Duration::from_secs(args.delivery_retry_secs),
+        max_attempts: args.delivery_attempts,
+    }));
+    
+    // Workers only follow start messages signed with this key
+    let identity = AggregatorIdentity::load_or_generate(&args.identity_file)?;
+    info!(
+        "Signing control messages with key {}; pin it in the workers' aggregator.public_keys",
+        hex::encode(identity.public_key().serialize())
+    );
+    aggregator.identity = Arc::new(identity);
+    
+    // Approved registrations are saved to the committee file, if there is one
+    let mut registrar = Registrar::new(committee, args.registration);
+    match &args.committee_file {
+        Some(path) => registrar = registrar.with_committee_file(path),
+        None if registrar.is_open() => warn!("No committee file given, approved registrations are lost on restart"),
+        None => {}
+    }
+    if args.registration == RegistrationMode::Manual {
+        registrar = registrar.with_queue_dir(&args.registration_dir)?;
+        info!("Registrations wait for approval in {}", args.registration_dir.display());
+    }
+    aggregator.registrar = Arc::new(registrar);
+    let aggregator = Arc::new(aggregator);
     
     // Create TEE enclave based on configuration
     let tee_config = TEEConfig::default();
@@ -58,7 +181,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("TEE generated attestation with code measurement: {:?}", &attestation_report.code_measurement[..8]);
     
     // Create network handler and start listening
-    let network_handler = NetworkHandler::new(aggregator.clone());
+    let network_handler = NetworkHandler::new(aggregator.clone()).with_freshness(FreshnessConfig {
+        window: Duration::from_secs(args.freshness_window_secs),
+        max_clock_skew: Duration::from_secs(args.max_clock_skew_secs),
+    });
     let addr = format!("0.0.0.0:{}", args.port);
     
     // Start the TCP listener in a background task
@@ -72,30 +198,125 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Aggregator initial state: {:?}", aggregator.get_state());
     info!("Listening on port {}", args.port);
     
-    // Start the aggregator with timeout handling in a background task
-    let aggregator_clone = aggregator.clone();
-    let aggregator_handle = tokio::spawn(async move {
-        if let Err(e) = aggregator_clone.run_with_timeout().await {
-            error!("Aggregator error: {}", e);
+    // Finish a round that had all its reveals when the aggregator stopped
+    if let Err(e) = aggregator.resume_round().await {
+        error!("Could not resume the recovered round: {}", e);
+    }
+    
+    // Start rounds on the schedule
+    let scheduler_handle = match args.genesis_time {
+        Some(genesis) => {
+            let schedule = RoundSchedule::new(genesis, Duration::from_secs(args.round_period_secs));
+            let round_limit = aggregator.config.commitment_timeout + aggregator.config.reveal_timeout;
+            if schedule.period() < round_limit {
+                warn!(
+                    "The round period of {} seconds is shorter than the commitment and reveal timeouts, \
+                     rounds that run into a timeout cause the next one to be skipped",
+                    args.round_period_secs
+                );
+            }
+            let scheduler = RoundScheduler::new(aggregator.clone(), schedule);
+            Some(tokio::spawn(scheduler.run()))
+        }
+        None => {
+            warn!("No genesis time given, rounds are not scheduled");
+            None
+        }
+    };
+    
+    // Send start messages again until the workers answer
+    let sessions = aggregator.sessions.clone();
+    let delivery_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DELIVERY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let unreachable = sessions.retry();
+            if !unreachable.is_empty() {
+                warn!("Workers not answering start messages: {}", unreachable.join(", "));
+            }
+        }
+    });
+    
+    // Periodically report which workers are live
+    let liveness = aggregator.liveness.clone();
+    let liveness_file = args.liveness_file.clone();
+    let liveness_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LIVENESS_REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let snapshot = liveness.snapshot();
+            let live = snapshot.iter().filter(|node| node.live).count();
+            info!("{} of {} known workers are live", live, snapshot.len());
+            
+            if let Some(path) = &liveness_file {
+                if let Err(e) = write_liveness_file(path, &snapshot) {
+                    warn!("Failed to write liveness file {}: {}", path.display(), e);
+                }
+            }
         }
     });
     
+    // Import bundles handed in by air-gapped workers
+    let import_handle = match &args.import_dir {
+        Some(dir) => {
+            let importer = BundleImporter::new(aggregator.clone(), dir)?;
+            info!("Importing offline bundles from {}", importer.dir().display());
+            Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(IMPORT_SCAN_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = importer.scan().await {
+                        warn!("Failed to import bundles from {}: {}", importer.dir().display(), e);
+                    }
+                }
+            }))
+        }
+        None => None,
+    };
+    
+    // Admit the registrations operators approved
+    let approval_handle = match args.registration {
+        RegistrationMode::Manual => {
+            let registrar = aggregator.registrar.clone();
+            Some(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(APPROVAL_SCAN_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = registrar.process_approvals() {
+                        warn!("Failed to process approved registrations: {}", e);
+                    }
+                }
+            }))
+        }
+        _ => None,
+    };
+    
     // Wait for shutdown signal
     info!("Press Ctrl+C to shutdown gracefully...");
     signal::ctrl_c().await?;
     info!("Received shutdown signal, cleaning up...");
     
     // Perform cleanup - transition aggregator to idle state
-    {
-        let mut state_guard = aggregator.state.lock().unwrap();
-        *state_guard = entropy_aggregator::state_machine::AggregatorState::Idle;
-    }
+    aggregator.abandon_round().await?;
     
     info!("Aggregator shutdown complete");
     
     // Cancel the spawned tasks
     network_handle.abort();
-    aggregator_handle.abort();
+    liveness_handle.abort();
+    delivery_handle.abort();
+    for handle in [scheduler_handle, import_handle, approval_handle].into_iter().flatten() {
+        handle.abort();
+    }
     
+    Ok(())
+}
+
+/// Replace the liveness file with the current snapshot
+fn write_liveness_file(path: &Path, snapshot: &[NodeLiveness]) -> Result<(), Box<dyn std::error::Error>> {
+    // Write to a temporary file first so readers never see a partial table
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(snapshot)?)?;
+    std::fs::rename(&tmp_path, path)?;
     Ok(())
 }
\ No newline at end of file
@@ -1,23 +1,59 @@
 use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use serde_json;
+use tokio_util::codec::Framed;
+use futures::{SinkExt, StreamExt};
 use log::{info, warn, error, debug};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
-use entropy_types::{CommitmentMsg};
+use entropy_types::codec::CodecError;
+use entropy_types::crypto as protocol_crypto;
+use entropy_types::protocol::{
+    error_codes, negotiate, Negotiated, RegisterChallengeMsg, SUPPORTED_CAPABILITIES, SUPPORTED_PROTOCOL_VERSIONS,
+};
+use entropy_types::{
+    AckMsg, Capability, CommitmentMsg, ErrorMessage, HelloAckMsg, HelloMsg, KeyRotationResultMsg, NodeId, ProtocolCodec,
+    ProtocolEnvelope, ProtocolMessage, RegisterResultMsg, RegistrationStatus, SessionProofMsg,
+};
 use crate::aggregator::Aggregator;
 use crate::error::AggregatorError;
+use crate::freshness::{FreshnessConfig, FreshnessError};
 use anyhow::Result;
 
+/// Node ID the aggregator uses as sender when none is configured
+pub const DEFAULT_AGGREGATOR_NODE_ID: &str = "aggregator";
+
+/// Capabilities a worker must offer to be accepted
+const REQUIRED_CAPABILITIES: &[Capability] = &[Capability::CommitReveal];
+
+/// How long a registration challenge can be answered
+pub const REGISTRATION_CHALLENGE_TTL: Duration = Duration::from_secs(60);
+
 pub struct NetworkHandler {
     aggregator: Arc<Aggregator>,
+    node_id: NodeId,
+    freshness: FreshnessConfig,
 }
 
 impl NetworkHandler {
     pub fn new(aggregator: Arc<Aggregator>) -> Self {
-        Self { aggregator }
+        Self::with_node_id(aggregator, DEFAULT_AGGREGATOR_NODE_ID.to_string())
+    }
+
+    /// Create a handler that identifies itself to workers with the given node ID
+    pub fn with_node_id(aggregator: Arc<Aggregator>, node_id: NodeId) -> Self {
+        Self {
+            aggregator,
+            node_id,
+            freshness: FreshnessConfig::default(),
+        }
+    }
+
+    /// Use the given bounds on the timestamps of commitments and reveals
+    pub fn with_freshness(mut self, freshness: FreshnessConfig) -> Self {
+        self.freshness = freshness;
+        self
     }
 
     /// Start the TCP listener on the specified address
@@ -29,8 +65,9 @@ impl NetworkHandler {
             match listener.accept().await {
                 Ok((stream, peer_addr)) => {
                     let aggregator = self.aggregator.clone();
+                    let session = Session::new(self.node_id.clone()).with_freshness(self.freshness.clone());
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, aggregator, peer_addr).await {
+                        if let Err(e) = handle_connection(stream, aggregator, session, peer_addr).await {
                             let error_msg = format!("{}", e);
                             let error_str = error_msg.as_str();
                             error!("Error handling connection from {}: {}", peer_addr, error_str);
@@ -47,100 +84,560 @@ impl NetworkHandler {
     }
 }
 
+/// Protocol state of a single worker connection
+///
+/// A session starts unestablished and only accepts a `Hello`. Once the handshake
+/// succeeds it remembers the peer's node ID and the negotiated parameters, and
+/// every later envelope must match them. The peer's sequence numbers must keep
+/// increasing, so a message cannot be replayed on the same connection.
+///
+/// Anyone can announce any node ID in a hello. The session only belongs to
+/// that node once the peer signed the handshake challenge with the node's key.
+#[derive(Debug)]
+pub struct Session {
+    node_id: NodeId,
+    peer: Option<NodeId>,
+    negotiated: Option<Negotiated>,
+    /// Challenge sent in the hello ack, for the peer to sign with its node's key
+    session_challenge: [u8; 32],
+    /// Whether the peer proved it holds its node's key
+    proven: bool,
+    next_sequence: u64,
+    /// Sequence number of the last envelope accepted from the peer
+    peer_sequence: Option<u64>,
+    /// Outstanding registration challenge and when it was issued
+    challenge: Option<([u8; 32], Instant)>,
+    freshness: FreshnessConfig,
+}
+
+impl Session {
+    pub fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            peer: None,
+            negotiated: None,
+            session_challenge: [0u8; 32],
+            proven: false,
+            next_sequence: 0,
+            peer_sequence: None,
+            challenge: None,
+            freshness: FreshnessConfig::default(),
+        }
+    }
+
+    /// Use the given bounds on the timestamps of commitments and reveals
+    pub fn with_freshness(mut self, freshness: FreshnessConfig) -> Self {
+        self.freshness = freshness;
+        self
+    }
+
+    /// Whether the handshake has completed
+    pub fn is_established(&self) -> bool {
+        self.negotiated.is_some()
+    }
+
+    /// The node ID the peer announced in its hello
+    pub fn peer(&self) -> Option<&NodeId> {
+        self.peer.as_ref()
+    }
+
+    /// The negotiated version and capabilities, once established
+    pub fn negotiated(&self) -> Option<&Negotiated> {
+        self.negotiated.as_ref()
+    }
+
+    /// The peer's node ID, once the peer proved it holds the node's key
+    pub fn member(&self) -> Option<&NodeId> {
+        self.peer.as_ref().filter(|_| self.proven)
+    }
+
+    /// Wrap an outgoing message in an envelope with the next sequence number
+    pub fn envelope(&mut self, message: ProtocolMessage) -> ProtocolEnvelope {
+        let mut envelope = ProtocolEnvelope::new(self.next_sequence, self.node_id.clone(), message);
+        if let Some(negotiated) = &self.negotiated {
+            envelope.version = negotiated.version;
+        }
+        self.next_sequence += 1;
+        envelope
+    }
+
+    /// Process the peer's hello, establishing the session on success
+    pub fn accept_hello(&mut self, sender: &NodeId, hello: &HelloMsg) -> Result<HelloAckMsg, ErrorMessage> {
+        if self.is_established() {
+            return Err(ErrorMessage::new(error_codes::UNEXPECTED_MESSAGE, "handshake already completed"));
+        }
+        if *sender != hello.node_id {
+            return Err(ErrorMessage::new(
+                error_codes::MESSAGE_REJECTED,
+                format!("envelope sender {} does not match hello node ID {}", sender, hello.node_id),
+            ));
+        }
+
+        let negotiated = negotiate(hello, SUPPORTED_PROTOCOL_VERSIONS, SUPPORTED_CAPABILITIES, REQUIRED_CAPABILITIES)?;
+        self.session_challenge = rand::random();
+        let ack = HelloAckMsg {
+            node_id: self.node_id.clone(),
+            version: negotiated.version,
+            capabilities: negotiated.capabilities.clone(),
+            challenge: self.session_challenge,
+        };
+
+        self.peer = Some(hello.node_id.clone());
+        self.negotiated = Some(negotiated);
+        Ok(ack)
+    }
+
+    /// Check that an envelope received after the handshake belongs to this session
+    ///
+    /// Records the envelope's sequence number once it passed.
+    pub fn validate(&mut self, envelope: &ProtocolEnvelope) -> Result<(), ErrorMessage> {
+        let (Some(peer), Some(negotiated)) = (&self.peer, &self.negotiated) else {
+            return Err(ErrorMessage::new(
+                error_codes::HANDSHAKE_REQUIRED,
+                format!("expected hello, got {}", envelope.message.kind()),
+            ));
+        };
+        if envelope.version != negotiated.version {
+            return Err(ErrorMessage::new(
+                error_codes::INCOMPATIBLE_VERSION,
+                format!("negotiated version {}, got {}", negotiated.version, envelope.version),
+            ));
+        }
+        if envelope.sender != *peer {
+            return Err(ErrorMessage::new(
+                error_codes::MESSAGE_REJECTED,
+                format!("sender {} does not match session peer {}", envelope.sender, peer),
+            ));
+        }
+        if let Some(last) = self.peer_sequence.filter(|last| envelope.sequence <= *last) {
+            return Err(ErrorMessage::new(
+                error_codes::REPLAYED_MESSAGE,
+                format!("sequence number {} is not above the last one received, {}", envelope.sequence, last),
+            ));
+        }
+        self.peer_sequence = Some(envelope.sequence);
+        Ok(())
+    }
+
+    /// Check the timestamp of a commitment or reveal against the freshness bounds
+    pub fn check_freshness(&self, timestamp: u64) -> Result<(), ErrorMessage> {
+        self.freshness.check_now(timestamp).map_err(|e| {
+            let error_code = match e {
+                FreshnessError::Stale { .. } => error_codes::STALE_MESSAGE,
+                FreshnessError::FromFuture { .. } => error_codes::FUTURE_MESSAGE,
+            };
+            ErrorMessage::new(error_code, e.to_string())
+        })
+    }
+
+    /// Check the peer's signature over the handshake challenge
+    ///
+    /// `public_keys` are the keys the peer's node may sign with. Once a proof
+    /// verifies, the session belongs to the node, see [`Session::member`].
+    pub fn accept_proof(&mut self, proof: &SessionProofMsg, public_keys: &[Vec<u8>]) -> Result<(), ErrorMessage> {
+        if self.peer.as_ref() != Some(&proof.node_id) {
+            return Err(ErrorMessage::new(
+                error_codes::MESSAGE_REJECTED,
+                format!("session proof for node {} on the session of another node", proof.node_id),
+            ));
+        }
+        if public_keys.is_empty() {
+            return Err(ErrorMessage::new(
+                error_codes::NOT_IN_COMMITTEE,
+                format!("node {} is not in the committee", proof.node_id),
+            ));
+        }
+        let verified = public_keys.iter().any(|key| {
+            protocol_crypto::verify_session_proof(key, &self.node_id, &self.session_challenge, proof) == Ok(true)
+        });
+        if !verified {
+            return Err(ErrorMessage::new(error_codes::MESSAGE_REJECTED, "session proof does not verify"));
+        }
+        self.proven = true;
+        Ok(())
+    }
+
+    /// Issue a fresh registration challenge, replacing any outstanding one
+    pub fn issue_challenge(&mut self) -> [u8; 32] {
+        let nonce: [u8; 32] = rand::random();
+        self.challenge = Some((nonce, Instant::now()));
+        nonce
+    }
+
+    /// Consume the outstanding challenge if it has not expired
+    ///
+    /// Each challenge can be answered once, so a registration cannot be replayed.
+    pub fn take_challenge(&mut self) -> Option<[u8; 32]> {
+        self.challenge
+            .take()
+            .filter(|(_, issued)| issued.elapsed() < REGISTRATION_CHALLENGE_TTL)
+            .map(|(nonce, _)| nonce)
+    }
+}
+
+/// Reply to send back after handling an envelope
+enum Reply {
+    /// Send an envelope and keep the connection open
+    Envelope(ProtocolEnvelope),
+    /// Send an error and close the connection
+    Close(ProtocolEnvelope),
+    /// Nothing to send
+    None,
+}
+
 /// Handle an individual TCP connection
+///
+/// The connection stays open for any number of framed envelopes until the peer
+/// disconnects, goes idle or violates the protocol. Once the handshake succeeds
+/// the connection is registered with the aggregator's sessions, and control
+/// messages pushed to the worker are sent between the replies.
 async fn handle_connection(
-    mut stream: TcpStream,
+    stream: TcpStream,
     aggregator: Arc<Aggregator>,
+    mut session: Session,
     peer_addr: SocketAddr,
 ) -> Result<()> {
     debug!("New connection from: {}", peer_addr);
 
-    let mut buffer = [0; 4096];
-    
-    // Read data from the stream with timeout
-    let n = match tokio::time::timeout(Duration::from_secs(30), stream.read(&mut buffer)).await {
-        Ok(Ok(n)) => n,
-        Ok(Err(e)) => {
-            error!("Failed to read from connection {}: {}", peer_addr, e);
-            return Err(anyhow::anyhow!("Read error: {}", e));
+    let mut framed = Framed::new(stream, ProtocolCodec::new());
+    let (push_tx, mut pushed) = mpsc::unbounded_channel::<ProtocolMessage>();
+    let mut registered: Option<(NodeId, u64)> = None;
+
+    let result = loop {
+        // Read the next frame from the stream with timeout, unless a control message is pushed first
+        let frame = tokio::select! {
+            read = tokio::time::timeout(Duration::from_secs(30), framed.next()) => match read {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    debug!("Connection from {} closed gracefully", peer_addr);
+                    break Ok(());
+                }
+                Err(_) => {
+                    error!("Read timeout from connection {}", peer_addr);
+                    break Err(anyhow::anyhow!("Read timeout"));
+                }
+            },
+            Some(message) = pushed.recv() => {
+                debug!("Pushing {} to {}", message.kind(), peer_addr);
+                if let Err(e) = framed.send(session.envelope(message)).await {
+                    warn!("Failed to push to {}: {} - connection may be dropped", peer_addr, e);
+                    break Ok(());
+                }
+                continue;
+            }
+        };
+
+        let reply = match frame {
+            Ok(envelope) => handle_envelope(envelope, &aggregator, &mut session, peer_addr).await,
+            Err(CodecError::Malformed(e)) => {
+                // Framed ends the stream after a decode error, so report it and close
+                warn!("Received malformed message from {}: {}", peer_addr, e);
+                let error = ErrorMessage::new(error_codes::MALFORMED_MESSAGE, e.to_string());
+                Reply::Close(session.envelope(ProtocolMessage::Error(error)))
+            }
+            Err(CodecError::FrameTooLarge { length, max }) => {
+                warn!("Frame of {} bytes from {} exceeds limit of {}", length, peer_addr, max);
+                let error = ErrorMessage::new(
+                    error_codes::MALFORMED_MESSAGE,
+                    format!("frame of {} bytes exceeds limit of {}", length, max),
+                );
+                Reply::Close(session.envelope(ProtocolMessage::Error(error)))
+            }
+            Err(CodecError::Io(e)) => {
+                error!("Failed to read from connection {}: {}", peer_addr, e);
+                break Err(anyhow::anyhow!("Read error: {}", e));
+            }
+        };
+
+        // Control messages can be pushed once the worker proved who it is
+        if registered.is_none() {
+            if let Some(peer) = session.member() {
+                let connection_id = aggregator.sessions.connect(peer, push_tx.clone());
+                registered = Some((peer.clone(), connection_id));
+            }
+        }
+
+        let (envelope, close) = match reply {
+            Reply::Envelope(envelope) => (envelope, false),
+            Reply::Close(envelope) => (envelope, true),
+            Reply::None => continue,
+        };
+
+        // Try to write the response, but handle potential connection drops
+        if let Err(e) = framed.send(envelope).await {
+            warn!("Failed to send response to {}: {} - connection may be dropped", peer_addr, e);
+            break Ok(());
         }
-        Err(_) => {
-            error!("Read timeout from connection {}", peer_addr);
-            return Err(anyhow::anyhow!("Read timeout"));
+        if close {
+            debug!("Closing connection from {} after protocol error", peer_addr);
+            break Ok(());
         }
     };
-    
-    if n == 0 {
-        debug!("Connection from {} closed gracefully", peer_addr);
-        return Ok(());
-    }
-
-    // Parse the incoming message
-    let message_str = String::from_utf8_lossy(&buffer[..n]);
-    
-    // Try to deserialize as CommitmentMsg first
-    if let Ok(commitment_msg) = serde_json::from_str::<CommitmentMsg>(&message_str) {
-        debug!("Received commitment message from {}: {:?}", peer_addr, commitment_msg.node_id);
-        
-        // For now, we'll pass an empty public key - in a real implementation,
-        // the public key would be associated with the node ID
-        let result = aggregator.process_commitment(commitment_msg, &[]).await;
-        
-        let response_bytes = match result {
-            Ok(success) => {
-                if success {
+
+    if let Some((peer, connection_id)) = registered {
+        aggregator.sessions.disconnect(&peer, connection_id);
+    }
+    result
+}
+
+/// Dispatch a decoded envelope according to the session state
+async fn handle_envelope(
+    envelope: ProtocolEnvelope,
+    aggregator: &Aggregator,
+    session: &mut Session,
+    peer_addr: SocketAddr,
+) -> Reply {
+    if !session.is_established() {
+        return match &envelope.message {
+            ProtocolMessage::Hello(hello) => match session.accept_hello(&envelope.sender, hello) {
+                Ok(ack) => {
+                    session.peer_sequence = Some(envelope.sequence);
+                    info!("Handshake with {} ({}) completed, protocol version {}", hello.node_id, peer_addr, ack.version);
+                    Reply::Envelope(session.envelope(ProtocolMessage::HelloAck(ack)))
+                }
+                Err(error) => {
+                    warn!("Rejecting handshake from {}: {}", peer_addr, error.error_message);
+                    Reply::Close(session.envelope(ProtocolMessage::Error(error)))
+                }
+            },
+            _ => {
+                let error = session.validate(&envelope).unwrap_err();
+                warn!("Rejecting {} from {} before handshake", envelope.message.kind(), peer_addr);
+                Reply::Close(session.envelope(ProtocolMessage::Error(error)))
+            }
+        };
+    }
+
+    if let Err(error) = session.validate(&envelope) {
+        warn!("Rejecting {} from {}: {}", envelope.message.kind(), peer_addr, error.error_message);
+        return Reply::Close(session.envelope(ProtocolMessage::Error(error)));
+    }
+
+    match envelope.message {
+        ProtocolMessage::Commitment(commitment_msg) => {
+            debug!("Received commitment message from {}: {:?}", peer_addr, commitment_msg.node_id);
+            if commitment_msg.node_id != envelope.sender {
+                warn!("Commitment for node {} sent by {}", commitment_msg.node_id, envelope.sender);
+                let reason = format!("commitment for node {} sent by {}", commitment_msg.node_id, envelope.sender);
+                return refuse(session, envelope.sequence, ErrorMessage::new(error_codes::MESSAGE_REJECTED, reason));
+            }
+            if let Err(error) = session.check_freshness(commitment_msg.timestamp) {
+                warn!("Rejecting commitment from {}: {}", peer_addr, error.error_message);
+                return refuse(session, envelope.sequence, error);
+            }
+
+            match aggregator.process_commitment(commitment_msg).await {
+                Ok(true) => {
                     info!("Successfully processed commitment from node: {}", peer_addr);
-                    &b"ACK"[..]
-                } else {
+                    ack(session, envelope.sequence, true)
+                }
+                Ok(false) => {
                     warn!("Failed to process commitment from node: {}", peer_addr);
-                    &b"NACK"[..]
+                    ack(session, envelope.sequence, false)
+                }
+                Err(e) => {
+                    error!("Error processing commitment: {}", e);
+                    let error_code = match e.downcast_ref::<AggregatorError>() {
+                        Some(AggregatorError::NodeNotInCommittee { .. }) => error_codes::NOT_IN_COMMITTEE,
+                        _ => error_codes::MESSAGE_REJECTED,
+                    };
+                    refuse(session, envelope.sequence, ErrorMessage::new(error_code, e.to_string()))
                 }
             }
-            Err(e) => {
-                let error_msg = format!("{}", e);
-                error!("Error processing commitment: {}", error_msg);
-                &b"ERROR"[..]
+        }
+        ProtocolMessage::Reveal(reveal_msg) => {
+            debug!("Received reveal message from {}: {:?}", peer_addr, reveal_msg.node_id);
+            if reveal_msg.node_id != envelope.sender {
+                warn!("Reveal for node {} sent by {}", reveal_msg.node_id, envelope.sender);
+                let reason = format!("reveal for node {} sent by {}", reveal_msg.node_id, envelope.sender);
+                return refuse(session, envelope.sequence, ErrorMessage::new(error_codes::MESSAGE_REJECTED, reason));
+            }
+            if let Err(error) = session.check_freshness(reveal_msg.timestamp) {
+                warn!("Rejecting reveal from {}: {}", peer_addr, error.error_message);
+                return refuse(session, envelope.sequence, error);
+            }
+
+            match aggregator.process_reveal(reveal_msg).await {
+                Ok(true) => {
+                    info!("Successfully processed reveal from node: {}", peer_addr);
+                    ack(session, envelope.sequence, true)
+                }
+                Ok(false) => {
+                    warn!("Failed to process reveal from node: {}", peer_addr);
+                    ack(session, envelope.sequence, false)
+                }
+                Err(e) => {
+                    error!("Error processing reveal: {}", e);
+                    refuse(session, envelope.sequence, ErrorMessage::new(error_codes::MESSAGE_REJECTED, e.to_string()))
+                }
             }
-        };
-        
-        // Try to write the response, but handle potential connection drops
-        if let Err(e) = stream.write_all(response_bytes).await {
-            warn!("Failed to send response to {}: {} - connection may be dropped", peer_addr, e);
         }
-    } else {
-        // If it's not a commitment message, log and close connection
-        warn!("Received unrecognized message from {}: {}", peer_addr, message_str);
-        if let Err(e) = stream.write_all(b"UNKNOWN_MESSAGE_TYPE").await {
-            warn!("Failed to send error response to {}: {} - connection may be dropped", peer_addr, e);
+        ProtocolMessage::Heartbeat(heartbeat) => {
+            // Heartbeats are never answered, even when rejected
+            if heartbeat.node_id != envelope.sender {
+                warn!("Heartbeat for node {} sent by {}", heartbeat.node_id, envelope.sender);
+                return Reply::None;
+            }
+
+            match aggregator.process_heartbeat(&heartbeat) {
+                Ok(true) => debug!("Heartbeat from {}: {}", heartbeat.node_id, heartbeat.status),
+                Ok(false) => warn!("Ignored heartbeat from node: {}", peer_addr),
+                Err(e) => warn!("Error processing heartbeat from {}: {}", peer_addr, e),
+            }
+            Reply::None
+        }
+        ProtocolMessage::SessionProof(proof) => match session.accept_proof(&proof, &aggregator.signing_keys(&proof.node_id)) {
+            Ok(()) => {
+                debug!("Node {} proved its session from {}", proof.node_id, peer_addr);
+                Reply::None
+            }
+            Err(error) => {
+                warn!("Rejecting session proof from {}: {}", peer_addr, error.error_message);
+                Reply::Envelope(session.envelope(ProtocolMessage::Error(error)))
+            }
+        },
+        ProtocolMessage::RegisterRequest(request) => {
+            // Registration failures are answered with a result, never an error
+            if request.node_id != envelope.sender {
+                let reason = format!("registration for node {} sent by {}", request.node_id, envelope.sender);
+                return register_result(session, rejected(request.node_id, reason));
+            }
+            if !session.negotiated().is_some_and(|n| n.capabilities.contains(&Capability::Registration)) {
+                let error = ErrorMessage::new(error_codes::MISSING_CAPABILITY, "registration was not negotiated");
+                return Reply::Envelope(session.envelope(ProtocolMessage::Error(error)));
+            }
+            if !aggregator.registrar.is_open() {
+                return register_result(session, rejected(request.node_id, "registration is closed".to_string()));
+            }
+
+            debug!("Issuing registration challenge to {}", request.node_id);
+            let nonce = session.issue_challenge();
+            Reply::Envelope(session.envelope(ProtocolMessage::RegisterChallenge(RegisterChallengeMsg { nonce })))
+        }
+        ProtocolMessage::Register(registration) => {
+            let challenge = session.take_challenge();
+            let result = if registration.node_id != envelope.sender {
+                let reason = format!("registration for node {} sent by {}", registration.node_id, envelope.sender);
+                rejected(registration.node_id, reason)
+            } else if challenge != Some(registration.nonce) {
+                rejected(registration.node_id, "no matching challenge, request a new one".to_string())
+            } else {
+                aggregator.registrar.register(&session.node_id, &registration)
+            };
+
+            info!("Registration of node {} from {}: {:?}", result.node_id, peer_addr, result.status);
+            register_result(session, result)
+        }
+        ProtocolMessage::KeyRotation(rotation) => {
+            if !session.negotiated().is_some_and(|n| n.capabilities.contains(&Capability::KeyRotation)) {
+                let error = ErrorMessage::new(error_codes::MISSING_CAPABILITY, "key rotation was not negotiated");
+                return Reply::Envelope(session.envelope(ProtocolMessage::Error(error)));
+            }
+            let result = if rotation.node_id != envelope.sender {
+                KeyRotationResultMsg {
+                    reason: format!("key rotation for node {} sent by {}", rotation.node_id, envelope.sender),
+                    node_id: rotation.node_id,
+                    accepted: false,
+                }
+            } else {
+                aggregator.registrar.rotate(&session.node_id, &rotation)
+            };
+
+            info!("Key rotation of node {} from {}: accepted {}", result.node_id, peer_addr, result.accepted);
+            Reply::Envelope(session.envelope(ProtocolMessage::KeyRotationResult(result)))
+        }
+        ProtocolMessage::Error(error) => {
+            warn!("Peer {} reported error {}: {}", peer_addr, error.error_code, error.error_message);
+            Reply::None
+        }
+        other => {
+            let error = ErrorMessage::new(
+                error_codes::UNEXPECTED_MESSAGE,
+                format!("aggregator does not accept {} messages", other.kind()),
+            );
+            Reply::Envelope(session.envelope(ProtocolMessage::Error(error)))
         }
     }
+}
+
+/// Acknowledge the request with the given sequence number
+fn ack(session: &mut Session, sequence: u64, accepted: bool) -> Reply {
+    Reply::Envelope(session.envelope(ProtocolMessage::Ack(AckMsg { sequence, accepted })))
+}
+
+/// Refuse the message sent with `sequence` with an error that names it
+fn refuse(session: &mut Session, sequence: u64, error: ErrorMessage) -> Reply {
+    Reply::Envelope(session.envelope(ProtocolMessage::Error(error.for_sequence(sequence))))
+}
+
+fn register_result(session: &mut Session, result: RegisterResultMsg) -> Reply {
+    Reply::Envelope(session.envelope(ProtocolMessage::RegisterResult(result)))
+}
 
-    Ok(())
+fn rejected(node_id: NodeId, reason: String) -> RegisterResultMsg {
+    RegisterResultMsg {
+        node_id,
+        status: RegistrationStatus::Rejected,
+        reason,
+    }
 }
 
 /// Client function to send messages to the aggregator (for testing purposes)
+///
+/// Performs the hello handshake as the commitment's node, then sends the commitment
+/// and returns the aggregator's reply. Control messages pushed in the meantime are
+/// skipped.
 pub async fn send_commitment_to_aggregator(
     addr: &str,
     commitment_msg: &CommitmentMsg,
-) -> Result<String> {
-    let mut stream = TcpStream::connect(addr).await?;
-    
-    let message_json = serde_json::to_string(commitment_msg)?;
-    stream.write_all(message_json.as_bytes()).await?;
-    
-    let mut response = [0; 1024];
-    let n = stream.read(&mut response).await?;
-    
-    Ok(String::from_utf8_lossy(&response[..n]).to_string())
+) -> Result<ProtocolMessage> {
+    let stream = TcpStream::connect(addr).await?;
+    let mut framed = Framed::new(stream, ProtocolCodec::new());
+    let sender = commitment_msg.node_id.clone();
+
+    let hello = ProtocolEnvelope::new(0, sender.clone(), ProtocolMessage::Hello(HelloMsg {
+        node_id: sender.clone(),
+        versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+        capabilities: SUPPORTED_CAPABILITIES.to_vec(),
+    }));
+    framed.send(hello).await?;
+
+    let ack = framed.next().await.ok_or_else(|| anyhow::anyhow!("Connection closed during handshake"))??;
+    if let ProtocolMessage::Error(error) = ack.message {
+        return Err(anyhow::anyhow!("Handshake rejected: {}", error.error_message));
+    }
+
+    let envelope = ProtocolEnvelope::new(1, sender, ProtocolMessage::Commitment(commitment_msg.clone()));
+    framed.send(envelope).await?;
+
+    loop {
+        let reply = framed.next().await.ok_or_else(|| anyhow::anyhow!("Connection closed before reply"))??;
+        match reply.message {
+            ProtocolMessage::StartCommitment(_) | ProtocolMessage::StartReveal(_) | ProtocolMessage::RoundCompletion(_) => {
+                continue
+            }
+            message => return Ok(message),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::aggregator::{Aggregator, AggregatorConfig};
+    use crate::committee::CommitteeMember;
+    use crate::registration::{Registrar, RegistrationMode};
+    use crate::sessions::DeliveryStatus;
+    use entropy_types::crypto::{self as protocol_crypto, ControlMessage};
+    use entropy_types::protocol::RegisterRequestMsg;
     use entropy_types::{CommitmentPayload, CommitmentMsg};
+    use entropy_worker::registration::registration_message;
+    use entropy_worker::service::{ServiceConfig, WorkerService};
+    use entropy_worker::worker::Worker;
+    use std::collections::BTreeMap;
+    use tokio::io::AsyncWriteExt;
     use tokio::time::timeout;
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[tokio::test]
     async fn test_tcp_listener() {
@@ -201,7 +698,7 @@ mod tests {
             round_id: 1,
             payload: commitment_payload,
             node_id: "test_node".to_string(),
-            timestamp: 1234567890,
+            timestamp: unix_now(),
         };
         
         // Try to send the commitment to the aggregator
@@ -210,17 +707,448 @@ mod tests {
             send_commitment_to_aggregator("127.0.0.1:9002", &commitment_msg)
         ).await;
         
-        match result {
-            Ok(response) => {
-                // The response might be an error because we didn't start a round first
-                println!("Response: {:?}", response);
-            }
-            Err(_) => {
-                println!("Timeout sending commitment");
-            }
-        }
+        // No round is active, so the commitment is acknowledged but not accepted
+        let response = result.expect("timeout sending commitment").unwrap();
+        assert_eq!(response, ProtocolMessage::Ack(AckMsg { sequence: 1, accepted: false }));
         
         // Stop the listener task
         listener_handle.abort();
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_persistent_framed_connection() {
+        let config = AggregatorConfig {
+            committee_size: 3,
+            threshold: 2,
+            port: 9003,
+            ..Default::default()
+        };
+        
+        let aggregator = Arc::new(Aggregator::new(config).unwrap());
+        let network_handler = NetworkHandler::new(aggregator.clone());
+        let listener_handle = tokio::spawn(async move {
+            let _ = network_handler.start_listener("127.0.0.1:9003").await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let stream = TcpStream::connect("127.0.0.1:9003").await.unwrap();
+        let mut framed = Framed::new(stream, ProtocolCodec::new());
+        
+        framed.send(hello_envelope("worker-1", SUPPORTED_PROTOCOL_VERSIONS.to_vec())).await.unwrap();
+        let reply = framed.next().await.unwrap().unwrap();
+        assert!(matches!(reply.message, ProtocolMessage::HelloAck(_)));
+        
+        // Several messages travel over the same connection, each with its own framed reply
+        for sequence in 1..4 {
+            let mut envelope = commitment_envelope("worker-1");
+            envelope.sequence = sequence;
+            framed.send(envelope).await.unwrap();
+            
+            let reply = framed.next().await.unwrap().unwrap();
+            assert_eq!(reply.message, ProtocolMessage::Ack(AckMsg { sequence, accepted: false }));
+        }
+        
+        // A malformed frame is reported before the connection is closed
+        framed.get_mut().write_all(&[0, 0, 0, 8]).await.unwrap();
+        framed.get_mut().write_all(b"not json").await.unwrap();
+        let reply = framed.next().await.unwrap().unwrap();
+        let ProtocolMessage::Error(error) = reply.message else { panic!("expected error") };
+        assert_eq!(error.error_code, error_codes::MALFORMED_MESSAGE);
+        assert!(framed.next().await.is_none());
+        
+        listener_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_non_member_commitment_reply() {
+        let config = AggregatorConfig {
+            committee_size: 3,
+            threshold: 2,
+            port: 9004,
+            ..Default::default()
+        };
+        
+        let aggregator = Arc::new(Aggregator::new(config).unwrap());
+        aggregator.start_new_round(1, vec!["worker-1".to_string()]).await.unwrap();
+        let network_handler = NetworkHandler::new(aggregator.clone());
+        let listener_handle = tokio::spawn(async move {
+            let _ = network_handler.start_listener("127.0.0.1:9004").await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        
+        let ProtocolMessage::Commitment(commitment_msg) = commitment_envelope("worker-1").message else { unreachable!() };
+        let response = send_commitment_to_aggregator("127.0.0.1:9004", &commitment_msg).await.unwrap();
+        
+        let ProtocolMessage::Error(error) = response else { panic!("expected error, got {:?}", response) };
+        assert_eq!(error.error_code, error_codes::NOT_IN_COMMITTEE);
+        
+        listener_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_start_messages_are_pushed() {
+        let config = AggregatorConfig {
+            committee_size: 1,
+            threshold: 1,
+            port: 9005,
+            ..Default::default()
+        };
+        let aggregator = Arc::new(Aggregator::new(config).unwrap());
+        let (secret_key, public_key) = entropy_worker::crypto::generate_keypair().unwrap();
+        aggregator
+            .committee
+            .add_member(CommitteeMember::new("worker-1".to_string(), public_key.serialize().to_vec()))
+            .unwrap();
+        let network_handler = NetworkHandler::new(aggregator.clone());
+        let listener_handle = tokio::spawn(async move {
+            let _ = network_handler.start_listener("127.0.0.1:9005").await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut framed = proven_connection("127.0.0.1:9005", "worker-1", &secret_key).await;
+        while !aggregator.sessions.is_connected("worker-1") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Starting the round pushes the signed start message to the committee
+        aggregator.start_new_round(1, vec!["worker-1".to_string()]).await.unwrap();
+        let ProtocolMessage::StartCommitment(start_msg) = framed.next().await.unwrap().unwrap().message else {
+            panic!("expected start commitment")
+        };
+        assert_eq!(start_msg.round_id, 1);
+        assert_eq!(start_msg.verify(&aggregator.identity.public_key().serialize()), Ok(true));
+        assert_eq!(aggregator.sessions.status("worker-1"), Some(DeliveryStatus::Pending { attempts: 1 }));
+
+        // The commitment answers it, and reaching the threshold pushes the start of the reveal phase
+        let secret = [7u8; 32];
+        let commitment = protocol_crypto::compute_commitment(1, &secret);
+        let mut envelope = commitment_envelope("worker-1");
+        envelope.sequence = 2;
+        envelope.message = ProtocolMessage::Commitment(CommitmentMsg {
+            round_id: 1,
+            payload: CommitmentPayload {
+                round_id: 1,
+                commitment,
+                signature: protocol_crypto::sign_commitment(&secret_key, 1, &commitment),
+            },
+            node_id: "worker-1".to_string(),
+            timestamp: unix_now(),
+        });
+        framed.send(envelope).await.unwrap();
+        let reply = framed.next().await.unwrap().unwrap();
+        assert_eq!(reply.message, ProtocolMessage::Ack(AckMsg { sequence: 2, accepted: true }));
+        let ProtocolMessage::StartReveal(start_msg) = framed.next().await.unwrap().unwrap().message else {
+            panic!("expected start reveal")
+        };
+        assert_eq!(start_msg.round_id, 1);
+        assert_eq!(aggregator.sessions.status("worker-1"), Some(DeliveryStatus::Pending { attempts: 1 }));
+
+        // The session is dropped when the worker disconnects
+        drop(framed);
+        while aggregator.sessions.is_connected("worker-1") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        listener_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unproven_session_does_not_take_over_pushes() {
+        let config = AggregatorConfig {
+            committee_size: 1,
+            threshold: 1,
+            port: 9006,
+            ..Default::default()
+        };
+        let aggregator = Arc::new(Aggregator::new(config).unwrap());
+        let (secret_key, public_key) = entropy_worker::crypto::generate_keypair().unwrap();
+        aggregator
+            .committee
+            .add_member(CommitteeMember::new("worker-1".to_string(), public_key.serialize().to_vec()))
+            .unwrap();
+        let network_handler = NetworkHandler::new(aggregator.clone());
+        let listener_handle = tokio::spawn(async move {
+            let _ = network_handler.start_listener("127.0.0.1:9006").await;
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut worker = proven_connection("127.0.0.1:9006", "worker-1", &secret_key).await;
+        while !aggregator.sessions.is_connected("worker-1") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // A second hello for the same node, whose proof is signed with another key
+        let (other_key, _) = entropy_worker::crypto::generate_keypair().unwrap();
+        let stream = TcpStream::connect("127.0.0.1:9006").await.unwrap();
+        let mut impostor = Framed::new(stream, ProtocolCodec::new());
+        impostor.send(hello_envelope("worker-1", SUPPORTED_PROTOCOL_VERSIONS.to_vec())).await.unwrap();
+        let ProtocolMessage::HelloAck(ack) = impostor.next().await.unwrap().unwrap().message else {
+            panic!("expected hello ack")
+        };
+        impostor.send(session_proof("worker-1", 1, &ack, &other_key)).await.unwrap();
+        let ProtocolMessage::Error(error) = impostor.next().await.unwrap().unwrap().message else {
+            panic!("expected error")
+        };
+        assert_eq!(error.error_code, error_codes::MESSAGE_REJECTED);
+
+        // The start of the round still goes to the worker that proved its key
+        aggregator.start_new_round(1, vec!["worker-1".to_string()]).await.unwrap();
+        assert!(matches!(worker.next().await.unwrap().unwrap().message, ProtocolMessage::StartCommitment(_)));
+        assert!(tokio::time::timeout(Duration::from_millis(200), impostor.next()).await.is_err());
+
+        // The impostor leaving does not drop the worker's session either
+        drop(impostor);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(aggregator.sessions.is_connected("worker-1"));
+
+        listener_handle.abort();
+    }
+
+    /// Connect as `node_id` and prove the session with its key
+    async fn proven_connection(addr: &str, node_id: &str, secret_key: &secp256k1::SecretKey) -> Framed<TcpStream, ProtocolCodec> {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut framed = Framed::new(stream, ProtocolCodec::new());
+        framed.send(hello_envelope(node_id, SUPPORTED_PROTOCOL_VERSIONS.to_vec())).await.unwrap();
+        let ProtocolMessage::HelloAck(ack) = framed.next().await.unwrap().unwrap().message else {
+            panic!("expected hello ack")
+        };
+        framed.send(session_proof(node_id, 1, &ack, secret_key)).await.unwrap();
+        framed
+    }
+
+    fn session_proof(node_id: &str, sequence: u64, ack: &HelloAckMsg, secret_key: &secp256k1::SecretKey) -> ProtocolEnvelope {
+        let proof = SessionProofMsg {
+            node_id: node_id.to_string(),
+            signature: protocol_crypto::sign_session_proof(secret_key, &ack.node_id, node_id, &ack.challenge),
+        };
+        ProtocolEnvelope::new(sequence, node_id.to_string(), ProtocolMessage::SessionProof(proof))
+    }
+
+    fn hello_envelope(node_id: &str, versions: Vec<u32>) -> ProtocolEnvelope {
+        ProtocolEnvelope::new(0, node_id.to_string(), ProtocolMessage::Hello(HelloMsg {
+            node_id: node_id.to_string(),
+            versions,
+            capabilities: SUPPORTED_CAPABILITIES.to_vec(),
+        }))
+    }
+
+    fn commitment_envelope(sender: &str) -> ProtocolEnvelope {
+        ProtocolEnvelope::new(1, sender.to_string(), ProtocolMessage::Commitment(CommitmentMsg {
+            round_id: 1,
+            payload: CommitmentPayload {
+                round_id: 1,
+                commitment: [1u8; 32],
+                signature: vec![],
+            },
+            node_id: sender.to_string(),
+            timestamp: unix_now(),
+        }))
+    }
+
+    fn unix_now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn established_session(node_id: &str) -> Session {
+        let mut session = Session::new("aggregator".to_string());
+        let hello = hello_envelope(node_id, SUPPORTED_PROTOCOL_VERSIONS.to_vec());
+        let ProtocolMessage::Hello(msg) = &hello.message else { unreachable!() };
+        session.accept_hello(&hello.sender, msg).unwrap();
+        session
+    }
+
+    #[test]
+    fn test_session_requires_handshake() {
+        let mut session = Session::new("aggregator".to_string());
+
+        let err = session.validate(&commitment_envelope("worker-1")).unwrap_err();
+        assert_eq!(err.error_code, error_codes::HANDSHAKE_REQUIRED);
+    }
+
+    #[test]
+    fn test_session_handshake() {
+        let mut session = Session::new("aggregator".to_string());
+        let hello = hello_envelope("worker-1", SUPPORTED_PROTOCOL_VERSIONS.to_vec());
+        let ProtocolMessage::Hello(msg) = &hello.message else { unreachable!() };
+
+        let ack = session.accept_hello(&hello.sender, msg).unwrap();
+        assert_eq!(ack.version, entropy_types::PROTOCOL_VERSION);
+        assert_eq!(ack.capabilities, SUPPORTED_CAPABILITIES.to_vec());
+        assert!(session.is_established());
+        assert_eq!(session.peer(), Some(&"worker-1".to_string()));
+
+        assert!(session.validate(&commitment_envelope("worker-1")).is_ok());
+
+        // A second hello on the same connection is not allowed
+        assert_eq!(
+            session.accept_hello(&hello.sender, msg).unwrap_err().error_code,
+            error_codes::UNEXPECTED_MESSAGE
+        );
+    }
+
+    #[test]
+    fn test_session_rejects_incompatible_version() {
+        let mut session = Session::new("aggregator".to_string());
+        let hello = hello_envelope("worker-1", vec![99]);
+        let ProtocolMessage::Hello(msg) = &hello.message else { unreachable!() };
+
+        let err = session.accept_hello(&hello.sender, msg).unwrap_err();
+        assert_eq!(err.error_code, error_codes::INCOMPATIBLE_VERSION);
+        assert!(!session.is_established());
+    }
+
+    #[test]
+    fn test_session_rejects_foreign_sender() {
+        let mut session = Session::new("aggregator".to_string());
+        let hello = hello_envelope("worker-1", SUPPORTED_PROTOCOL_VERSIONS.to_vec());
+        let ProtocolMessage::Hello(msg) = &hello.message else { unreachable!() };
+        session.accept_hello(&hello.sender, msg).unwrap();
+
+        let err = session.validate(&commitment_envelope("worker-2")).unwrap_err();
+        assert_eq!(err.error_code, error_codes::MESSAGE_REJECTED);
+
+        let mut wrong_version = commitment_envelope("worker-1");
+        wrong_version.version = 99;
+        assert_eq!(session.validate(&wrong_version).unwrap_err().error_code, error_codes::INCOMPATIBLE_VERSION);
+    }
+
+    #[test]
+    fn test_session_rejects_replayed_sequence() {
+        let mut session = established_session("worker-1");
+
+        let mut envelope = commitment_envelope("worker-1");
+        envelope.sequence = 2;
+        assert!(session.validate(&envelope).is_ok());
+
+        // The same envelope again, and an older one, are refused
+        assert_eq!(session.validate(&envelope).unwrap_err().error_code, error_codes::REPLAYED_MESSAGE);
+        envelope.sequence = 1;
+        assert_eq!(session.validate(&envelope).unwrap_err().error_code, error_codes::REPLAYED_MESSAGE);
+
+        envelope.sequence = 3;
+        assert!(session.validate(&envelope).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stale_and_future_commitments() {
+        let aggregator = Aggregator::new(AggregatorConfig::default()).unwrap();
+        let peer_addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let mut session = established_session("worker-1").with_freshness(FreshnessConfig {
+            window: Duration::from_secs(30),
+            max_clock_skew: Duration::from_secs(5),
+        });
+
+        let expect_error = |reply: Reply, error_code: u32| {
+            let ProtocolMessage::Error(error) = reply_message(reply) else { panic!("expected error") };
+            assert_eq!(error.error_code, error_code);
+        };
+
+        let mut envelope = commitment_envelope("worker-1");
+        let ProtocolMessage::Commitment(msg) = &mut envelope.message else { unreachable!() };
+        msg.timestamp = unix_now() - 120;
+        expect_error(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await, error_codes::STALE_MESSAGE);
+
+        let mut envelope = commitment_envelope("worker-1");
+        envelope.sequence = 2;
+        let ProtocolMessage::Commitment(msg) = &mut envelope.message else { unreachable!() };
+        msg.timestamp = unix_now() + 120;
+        expect_error(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await, error_codes::FUTURE_MESSAGE);
+
+        // The session stays open and a fresh commitment still goes through
+        let mut envelope = commitment_envelope("worker-1");
+        envelope.sequence = 3;
+        let reply = reply_message(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await);
+        assert!(matches!(reply, ProtocolMessage::Ack(AckMsg { sequence: 3, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_stale_commitment_settles_worker_round() {
+        let aggregator = Aggregator::new(AggregatorConfig::default()).unwrap();
+        let peer_addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let mut session = established_session("worker-1");
+        let config = ServiceConfig {
+            aggregator_keys: vec![*aggregator.identity.public_key()],
+            ..Default::default()
+        };
+        let mut service = WorkerService::new(Worker::new("worker-1".to_string()).unwrap(), config);
+
+        // The worker's commitment is held up for longer than the freshness window
+        let start = aggregator.start_new_round(1, vec!["worker-1".to_string()]).await.unwrap();
+        let Some(ProtocolMessage::Commitment(mut commitment)) = service.handle_message(ProtocolMessage::StartCommitment(start)) else {
+            panic!("expected commitment");
+        };
+        commitment.timestamp -= 600;
+        let envelope = ProtocolEnvelope::new(1, "worker-1".to_string(), ProtocolMessage::Commitment(commitment));
+        service.sent(envelope.sequence, &envelope.message);
+
+        // The refusal names the commitment, so the worker gives up the round right away
+        let reply = reply_message(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await);
+        let ProtocolMessage::Error(error) = &reply else { panic!("expected error, got {:?}", reply) };
+        assert_eq!((error.error_code, error.sequence), (error_codes::STALE_MESSAGE, Some(1)));
+        service.handle_message(reply);
+        assert!(!service.worker().is_participating());
+    }
+
+    #[test]
+    fn test_session_sequence_numbers() {
+        let mut session = Session::new("aggregator".to_string());
+        let error = ErrorMessage::new(error_codes::MALFORMED_MESSAGE, "test");
+
+        assert_eq!(session.envelope(ProtocolMessage::Error(error.clone())).sequence, 0);
+        assert_eq!(session.envelope(ProtocolMessage::Error(error)).sequence, 1);
+    }
+
+    fn reply_message(reply: Reply) -> ProtocolMessage {
+        match reply {
+            Reply::Envelope(envelope) => envelope.message,
+            Reply::Close(envelope) => panic!("connection closed after {:?}", envelope.message),
+            Reply::None => panic!("no reply"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registration_challenge() {
+        let mut aggregator = Aggregator::new(AggregatorConfig::default()).unwrap();
+        aggregator.registrar = Arc::new(Registrar::new(aggregator.committee.clone(), RegistrationMode::Open));
+        let identity = entropy_worker::Identity::generate().unwrap();
+        let node_id = identity.node_id();
+        let peer_addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let mut session = Session::new("aggregator".to_string());
+
+        let hello = hello_envelope(node_id, SUPPORTED_PROTOCOL_VERSIONS.to_vec());
+        let reply = handle_envelope(hello, &aggregator, &mut session, peer_addr).await;
+        assert!(matches!(reply_message(reply), ProtocolMessage::HelloAck(_)));
+
+        // A registration that does not answer an issued challenge is refused
+        let registration = registration_message(&identity, "aggregator", [0u8; 32], BTreeMap::new());
+        let envelope = ProtocolEnvelope::new(1, node_id.to_string(), ProtocolMessage::Register(registration));
+        let ProtocolMessage::RegisterResult(result) =
+            reply_message(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await)
+        else {
+            panic!("expected a registration result");
+        };
+        assert_eq!(result.status, RegistrationStatus::Rejected);
+
+        let request = RegisterRequestMsg { node_id: node_id.to_string() };
+        let envelope = ProtocolEnvelope::new(2, node_id.to_string(), ProtocolMessage::RegisterRequest(request));
+        let ProtocolMessage::RegisterChallenge(challenge) =
+            reply_message(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await)
+        else {
+            panic!("expected a challenge");
+        };
+
+        let registration = registration_message(&identity, "aggregator", challenge.nonce, BTreeMap::new());
+        for (sequence, expected) in [(3, RegistrationStatus::Approved), (4, RegistrationStatus::Rejected)] {
+            // The challenge can only be answered once
+            let message = ProtocolMessage::Register(registration.clone());
+            let envelope = ProtocolEnvelope::new(sequence, node_id.to_string(), message);
+            let ProtocolMessage::RegisterResult(result) =
+                reply_message(handle_envelope(envelope, &aggregator, &mut session, peer_addr).await)
+            else {
+                panic!("expected a registration result");
+            };
+            assert_eq!(result.status, expected);
+        }
+        assert!(aggregator.committee.contains(node_id));
+    }
+}
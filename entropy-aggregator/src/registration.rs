@@ -0,0 +1,488 @@
+//! Proof-of-possession registration of worker keys.
+//!
+//! A worker registers its public key by signing a challenge nonce the aggregator
+//! issued on its connection (see `entropy_types::protocol`). The [`Registrar`]
+//! checks that proof and, depending on its [`RegistrationMode`], adds the key to
+//! the committee registry right away or queues it for an operator.
+//!
+//! The approval queue is a directory. Each queued registration is written to
+//! `pending/<node-id>.json`; operators approve it by moving the file to
+//! `approved/` and refuse it by moving it to `rejected/`. Refused nodes cannot
+//! register again until the file is removed from `rejected/`.
+//!
+//! Members replace their key with a rotation signed by both the old and the new
+//! key. Rotations need no approval and are accepted in every mode; the new key
+//! takes over when the next round starts.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use entropy_types::crypto::{node_id_from_public_key, verify_key_rotation, verify_registration_signature};
+use entropy_types::{KeyRotationMsg, KeyRotationResultMsg, NodeId, RegisterMsg, RegisterResultMsg, RegistrationStatus};
+use log::{info, warn};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::committee::{CommitteeMember, CommitteeRegistry};
+
+/// How often the approval queue is scanned
+pub const APPROVAL_SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Subdirectory of registrations waiting for an operator
+pub const PENDING_DIR: &str = "pending";
+
+/// Subdirectory operators move approved registrations to
+pub const APPROVED_DIR: &str = "approved";
+
+/// Subdirectory operators move refused registrations to
+pub const REJECTED_DIR: &str = "rejected";
+
+/// How the aggregator treats registrations with a valid proof of possession
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// Refuse every registration; members only come from the committee file
+    #[default]
+    Closed,
+    /// Add every registered key to the committee
+    Open,
+    /// Queue registrations until an operator approves them
+    Manual,
+}
+
+/// A registration waiting in the approval queue
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingRegistration {
+    #[serde(flatten)]
+    pub member: CommitteeMember,
+    /// Seconds since the Unix epoch at which the worker registered
+    pub requested_at: u64,
+}
+
+/// Checks registrations and key rotations and applies them to the committee
+#[derive(Debug)]
+pub struct Registrar {
+    mode: RegistrationMode,
+    committee: Arc<CommitteeRegistry>,
+    committee_file: Option<PathBuf>,
+    queue_dir: Option<PathBuf>,
+}
+
+impl Registrar {
+    pub fn new(committee: Arc<CommitteeRegistry>, mode: RegistrationMode) -> Self {
+        Self {
+            mode,
+            committee,
+            committee_file: None,
+            queue_dir: None,
+        }
+    }
+
+    /// Save the committee to this file whenever a registration or rotation changes it
+    pub fn with_committee_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.committee_file = Some(path.into());
+        self
+    }
+
+    /// Keep the approval queue in `dir`, creating it and its subdirectories if needed
+    pub fn with_queue_dir(mut self, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for subdir in [PENDING_DIR, APPROVED_DIR, REJECTED_DIR] {
+            fs::create_dir_all(dir.join(subdir)).map_err(|e| {
+                anyhow::Error::msg(format!("Failed to create approval queue {}: {}", dir.join(subdir).display(), e))
+            })?;
+        }
+        self.queue_dir = Some(dir);
+        Ok(self)
+    }
+
+    pub fn mode(&self) -> RegistrationMode {
+        self.mode
+    }
+
+    pub fn queue_dir(&self) -> Option<&Path> {
+        self.queue_dir.as_deref()
+    }
+
+    /// Whether workers may ask for a registration challenge
+    pub fn is_open(&self) -> bool {
+        self.mode != RegistrationMode::Closed
+    }
+
+    /// Check a registration answering a challenge issued by `aggregator_id`
+    ///
+    /// The caller must have checked that `registration.nonce` is the challenge it
+    /// issued. Approved keys are added to the committee; in manual mode they are
+    /// queued instead.
+    pub fn register(&self, aggregator_id: &str, registration: &RegisterMsg) -> RegisterResultMsg {
+        let status = match self.check(aggregator_id, registration) {
+            Ok(member) => self.admit(member),
+            Err(reason) => Err(reason),
+        };
+
+        let (status, reason) = match status {
+            Ok(status) => (status, String::new()),
+            Err(reason) => {
+                warn!("Rejected registration of node {}: {}", registration.node_id, reason);
+                (RegistrationStatus::Rejected, reason)
+            }
+        };
+        RegisterResultMsg {
+            node_id: registration.node_id.clone(),
+            status,
+            reason,
+        }
+    }
+
+    /// Verify the proof of possession, returning the member to admit
+    fn check(&self, aggregator_id: &str, registration: &RegisterMsg) -> Result<CommitteeMember, String> {
+        if !self.is_open() {
+            return Err("registration is closed".to_string());
+        }
+        let public_key = PublicKey::from_slice(&registration.public_key).map_err(|e| format!("invalid public key: {}", e))?;
+        if registration.node_id != node_id_from_public_key(&public_key) {
+            return Err("node ID is not derived from the public key".to_string());
+        }
+        match verify_registration_signature(aggregator_id, registration) {
+            Ok(true) => {}
+            Ok(false) => return Err("signature does not prove possession of the key".to_string()),
+            Err(e) => return Err(e.to_string()),
+        }
+
+        Ok(CommitteeMember {
+            metadata: registration.metadata.clone(),
+            ..CommitteeMember::new(registration.node_id.clone(), registration.public_key.clone())
+        })
+    }
+
+    fn admit(&self, member: CommitteeMember) -> Result<RegistrationStatus, String> {
+        if let Some(existing) = self.committee.get(&member.node_id) {
+            return if existing.public_key == member.public_key {
+                Ok(RegistrationStatus::Approved)
+            } else {
+                Err("node is registered with another key".to_string())
+            };
+        }
+
+        match self.mode {
+            RegistrationMode::Closed => Err("registration is closed".to_string()),
+            RegistrationMode::Open => self.approve(member).map(|_| RegistrationStatus::Approved).map_err(|e| e.to_string()),
+            RegistrationMode::Manual => self.enqueue(member).map(|_| RegistrationStatus::Pending),
+        }
+    }
+
+    /// Check a key rotation sent to `aggregator_id` and schedule it for the next round
+    ///
+    /// A rotation that already took effect is accepted again without changes, so
+    /// a worker that lost the answer can retry.
+    pub fn rotate(&self, aggregator_id: &str, rotation: &KeyRotationMsg) -> KeyRotationResultMsg {
+        let result = self.check_rotation(aggregator_id, rotation).and_then(|applied| {
+            if applied {
+                return Ok(());
+            }
+            self.committee
+                .schedule_rotation(&rotation.node_id, rotation.new_public_key.clone())
+                .map_err(|e| e.to_string())?;
+            self.save_committee();
+            Ok(())
+        });
+
+        let reason = match result {
+            Ok(()) => String::new(),
+            Err(reason) => {
+                warn!("Rejected key rotation of node {}: {}", rotation.node_id, reason);
+                reason
+            }
+        };
+        KeyRotationResultMsg {
+            node_id: rotation.node_id.clone(),
+            accepted: reason.is_empty(),
+            reason,
+        }
+    }
+
+    /// Verify a rotation, returning whether it already took effect
+    fn check_rotation(&self, aggregator_id: &str, rotation: &KeyRotationMsg) -> Result<bool, String> {
+        let Some(member) = self.committee.get(&rotation.node_id) else {
+            return Err("node is not a committee member".to_string());
+        };
+        let applied = member.public_key == rotation.new_public_key
+            && member.retired_keys.last().is_some_and(|key| key.public_key == rotation.old_public_key);
+        if member.next_public_key.as_ref() == Some(&rotation.old_public_key) {
+            return Err("the previous rotation has not taken effect yet".to_string());
+        }
+        if member.public_key != rotation.old_public_key && !applied {
+            return Err("old key is not the node's current key".to_string());
+        }
+        match verify_key_rotation(aggregator_id, rotation) {
+            Ok(true) => Ok(applied),
+            Ok(false) => Err("rotation is not signed by both keys".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Write the committee to the committee file, if there is one, logging failures
+    pub fn save_committee(&self) {
+        if let Some(path) = &self.committee_file {
+            if let Err(e) = self.committee.save(path) {
+                // Keep the change for now; it is lost on restart unless saved later
+                warn!("Failed to save the committee to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Write a registration to the approval queue, replacing an earlier one for the node
+    fn enqueue(&self, member: CommitteeMember) -> Result<(), String> {
+        let Some(dir) = &self.queue_dir else {
+            return Err("registrations need approval, but no approval queue is configured".to_string());
+        };
+        let file_name = format!("{}.json", member.node_id);
+        if dir.join(REJECTED_DIR).join(&file_name).exists() {
+            return Err("registration was refused by an operator".to_string());
+        }
+
+        let node_id = member.node_id.clone();
+        let pending = PendingRegistration {
+            member,
+            requested_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+        let path = dir.join(PENDING_DIR).join(&file_name);
+        let tmp_path = path.with_extension("tmp");
+        serde_json::to_vec_pretty(&pending)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&tmp_path, json).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_path, &path).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                warn!("Failed to queue registration {}: {}", path.display(), e);
+                "failed to queue the registration".to_string()
+            })?;
+
+        info!("Registration of node {} is waiting for approval in {}", node_id, path.display());
+        Ok(())
+    }
+
+    /// Add a member to the committee and save the committee file
+    fn approve(&self, member: CommitteeMember) -> Result<()> {
+        let node_id = member.node_id.clone();
+        self.committee.add_member(member)?;
+        self.save_committee();
+        info!("Approved registration of node {}", node_id);
+        Ok(())
+    }
+
+    /// Registrations waiting for an operator, oldest first
+    pub fn pending(&self) -> Result<Vec<PendingRegistration>> {
+        let Some(dir) = &self.queue_dir else { return Ok(Vec::new()) };
+        let mut pending = json_files(&dir.join(PENDING_DIR))?
+            .iter()
+            .map(|path| read_registration(path))
+            .collect::<Result<Vec<_>>>()?;
+        pending.sort_by_key(|registration| registration.requested_at);
+        Ok(pending)
+    }
+
+    /// Admit the registrations operators moved to `approved/`
+    ///
+    /// Each file is removed once its node is in the committee. Returns the node
+    /// IDs that were added.
+    pub fn process_approvals(&self) -> Result<Vec<NodeId>> {
+        let Some(dir) = &self.queue_dir else { return Ok(Vec::new()) };
+
+        let mut approved = Vec::new();
+        for path in json_files(&dir.join(APPROVED_DIR))? {
+            let member = match read_registration(&path).and_then(|registration| validate(registration.member)) {
+                Ok(member) => member,
+                Err(e) => {
+                    warn!("Skipping approved registration {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            let node_id = member.node_id.clone();
+            if !self.committee.contains(&node_id) {
+                self.approve(member)?;
+                approved.push(node_id);
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(approved)
+    }
+}
+
+/// Check a queued member again before admitting it, since the queue is editable
+fn validate(member: CommitteeMember) -> Result<CommitteeMember> {
+    let public_key = PublicKey::from_slice(&member.public_key)
+        .map_err(|e| anyhow::Error::msg(format!("invalid public key: {}", e)))?;
+    if member.node_id != node_id_from_public_key(&public_key) {
+        return Err(anyhow::Error::msg("node ID is not derived from the public key"));
+    }
+    Ok(member)
+}
+
+/// `.json` files in a directory, in file name order
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_registration(path: &Path) -> Result<PendingRegistration> {
+    let bytes = fs::read(path)?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| anyhow::Error::msg(format!("Malformed registration {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entropy_types::crypto::sign_registration;
+    use entropy_worker::Identity;
+    use std::collections::BTreeMap;
+
+    fn registration(identity: &Identity, aggregator_id: &str) -> RegisterMsg {
+        let mut registration = RegisterMsg {
+            node_id: identity.node_id().to_string(),
+            public_key: identity.public_key().serialize().to_vec(),
+            nonce: [7u8; 32],
+            metadata: BTreeMap::from([("operator".to_string(), "acme".to_string())]),
+            signature: Vec::new(),
+        };
+        registration.signature = sign_registration(identity.secret_key(), aggregator_id, &registration);
+        registration
+    }
+
+    #[test]
+    fn test_open_registration() {
+        let dir = std::env::temp_dir().join(format!("registration-open-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let committee_file = dir.join("committee.json");
+
+        let committee = Arc::new(CommitteeRegistry::new());
+        let registrar = Registrar::new(committee.clone(), RegistrationMode::Open).with_committee_file(&committee_file);
+        let identity = Identity::generate().unwrap();
+
+        let result = registrar.register("aggregator", &registration(&identity, "aggregator"));
+        assert_eq!(result.status, RegistrationStatus::Approved);
+        assert_eq!(committee.public_key(identity.node_id()), Some(identity.public_key().serialize().to_vec()));
+        assert_eq!(committee.get(identity.node_id()).unwrap().metadata["operator"], "acme");
+        assert!(CommitteeRegistry::load(&committee_file).unwrap().contains(identity.node_id()));
+
+        // Registering again is harmless
+        let result = registrar.register("aggregator", &registration(&identity, "aggregator"));
+        assert_eq!(result.status, RegistrationStatus::Approved);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_registration_requires_proof_of_possession() {
+        let committee = Arc::new(CommitteeRegistry::new());
+        let registrar = Registrar::new(committee.clone(), RegistrationMode::Open);
+        let identity = Identity::generate().unwrap();
+        let impostor = Identity::generate().unwrap();
+
+        // Signed for another aggregator
+        let result = registrar.register("aggregator", &registration(&identity, "other-aggregator"));
+        assert_eq!(result.status, RegistrationStatus::Rejected);
+
+        // Someone else's key, signed with the impostor's
+        let mut stolen = registration(&identity, "aggregator");
+        stolen.signature = sign_registration(impostor.secret_key(), "aggregator", &stolen);
+        assert_eq!(registrar.register("aggregator", &stolen).status, RegistrationStatus::Rejected);
+
+        // A node ID that does not belong to the key
+        let mut renamed = registration(&identity, "aggregator");
+        renamed.node_id = impostor.node_id().to_string();
+        renamed.signature = sign_registration(identity.secret_key(), "aggregator", &renamed);
+        let result = registrar.register("aggregator", &renamed);
+        assert_eq!(result.status, RegistrationStatus::Rejected);
+        assert!(result.reason.contains("not derived"));
+
+        assert!(committee.is_empty());
+
+        let closed = Registrar::new(committee.clone(), RegistrationMode::Closed);
+        assert_eq!(closed.register("aggregator", &registration(&identity, "aggregator")).status, RegistrationStatus::Rejected);
+        assert!(committee.is_empty());
+    }
+
+    #[test]
+    fn test_manual_approval_queue() {
+        let dir = std::env::temp_dir().join(format!("registration-queue-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let committee = Arc::new(CommitteeRegistry::new());
+        let registrar = Registrar::new(committee.clone(), RegistrationMode::Manual).with_queue_dir(&dir).unwrap();
+        let accepted = Identity::generate().unwrap();
+        let refused = Identity::generate().unwrap();
+
+        for identity in [&accepted, &refused] {
+            let result = registrar.register("aggregator", &registration(identity, "aggregator"));
+            assert_eq!(result.status, RegistrationStatus::Pending);
+        }
+        assert_eq!(registrar.pending().unwrap().len(), 2);
+        assert!(registrar.process_approvals().unwrap().is_empty());
+        assert!(committee.is_empty());
+
+        // The operator approves one registration and refuses the other
+        let file_name = |identity: &Identity| format!("{}.json", identity.node_id());
+        fs::rename(dir.join(PENDING_DIR).join(file_name(&accepted)), dir.join(APPROVED_DIR).join(file_name(&accepted)))
+            .unwrap();
+        fs::rename(dir.join(PENDING_DIR).join(file_name(&refused)), dir.join(REJECTED_DIR).join(file_name(&refused)))
+            .unwrap();
+
+        assert_eq!(registrar.process_approvals().unwrap(), vec![accepted.node_id().to_string()]);
+        assert!(committee.contains(accepted.node_id()));
+        assert!(!dir.join(APPROVED_DIR).join(file_name(&accepted)).exists());
+        assert!(registrar.pending().unwrap().is_empty());
+
+        let result = registrar.register("aggregator", &registration(&refused, "aggregator"));
+        assert_eq!(result.status, RegistrationStatus::Rejected);
+        assert!(!committee.contains(refused.node_id()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let committee = Arc::new(CommitteeRegistry::new());
+        let registrar = Registrar::new(committee.clone(), RegistrationMode::Closed);
+        let old = Identity::generate().unwrap();
+        let new = Identity::generate().unwrap();
+        committee
+            .add_member(CommitteeMember::new(old.node_id().to_string(), old.public_key().serialize().to_vec()))
+            .unwrap();
+
+        let rotation = |old_key: &Identity, new_key: &Identity| {
+            let mut rotation = KeyRotationMsg {
+                node_id: old.node_id().to_string(),
+                old_public_key: old_key.public_key().serialize().to_vec(),
+                new_public_key: new_key.public_key().serialize().to_vec(),
+                old_signature: Vec::new(),
+                new_signature: Vec::new(),
+            };
+            entropy_types::crypto::sign_key_rotation(old_key.secret_key(), new_key.secret_key(), "aggregator", &mut rotation);
+            rotation
+        };
+
+        // Only the current key can be rotated away from
+        let stranger = Identity::generate().unwrap();
+        assert!(!registrar.rotate("aggregator", &rotation(&stranger, &new)).accepted);
+
+        // A rotation signed for another aggregator is refused
+        assert!(!registrar.rotate("other-aggregator", &rotation(&old, &new)).accepted);
+
+        assert!(registrar.rotate("aggregator", &rotation(&old, &new)).accepted);
+        assert_eq!(committee.apply_rotations(3), vec![old.node_id().to_string()]);
+        assert_eq!(committee.public_key(old.node_id()), Some(new.public_key().serialize().to_vec()));
+
+        // Repeating the rotation changes nothing, rotating back is refused
+        assert!(registrar.rotate("aggregator", &rotation(&old, &new)).accepted);
+        assert!(committee.apply_rotations(4).is_empty());
+        assert!(!registrar.rotate("aggregator", &rotation(&new, &old)).accepted);
+        assert_eq!(committee.public_key(old.node_id()), Some(new.public_key().serialize().to_vec()));
+    }
+}
@@ -0,0 +1,714 @@
+//! The round actor.
+//!
+//! A single task owns the state of the current round: its phase, the accepted
+//! commitments and reveals and the deadline of the running phase. The rest of
+//! the aggregator talks to it through a [`RoundHandle`], which queues commands
+//! on a channel and waits for the reply. Messages are therefore processed one
+//! at a time without locks, and without copying the round state per message.
+//!
+//! Phases end on events rather than by polling: the message that reaches the
+//! threshold moves the round on, and the phase deadline moves it back to idle
+//! when it passes first. After every command the actor publishes a small
+//! [`RoundStatus`], so the phase and counts can be read without a round trip.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Result;
+use entropy_types::crypto as protocol_crypto;
+use entropy_types::{CommitmentMsg, CommitmentPayload, NodeId, RevealMsg, RoundSecret};
+use log::{debug, error, info, warn};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{sleep_until, Duration, Instant};
+
+use crate::aggregator::AggregatorConfig;
+use crate::committee::CommitteeRegistry;
+use crate::error::AggregatorError;
+use crate::events::{AbortReason, AggregatorEvent, EventSender, Rejection};
+use crate::store::{InFlightRound, RecoveredRounds, RoundRecord, RoundStore};
+use crate::state_machine::AggregatorState;
+
+/// Commands that can be queued before senders have to wait
+const COMMAND_QUEUE: usize = 1024;
+
+/// Snapshot of the round published after every change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundStatus {
+    pub state: AggregatorState,
+    /// The round started last, kept after it ends
+    pub round_id: u64,
+    pub commitments: usize,
+    pub reveals: usize,
+}
+
+/// What became of a commitment or reveal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Stored; `completes_phase` if it reached the phase's threshold
+    Accepted { completes_phase: bool },
+    /// The same message was accepted before
+    Recorded,
+    /// Refused, the reason is logged
+    Rejected,
+}
+
+impl Outcome {
+    /// Whether the sender should be told its message was accepted
+    pub fn is_accepted(&self) -> bool {
+        !matches!(self, Outcome::Rejected)
+    }
+}
+
+enum Command {
+    Start { round_id: u64, committee: Vec<NodeId>, reply: oneshot::Sender<Result<Vec<NodeId>>> },
+    Commitment { msg: CommitmentMsg, reply: oneshot::Sender<Result<Outcome>> },
+    Reveal { msg: RevealMsg, reply: oneshot::Sender<Result<Outcome>> },
+    Committed { reply: oneshot::Sender<Vec<NodeId>> },
+    Aggregate { round_id: u64, reply: oneshot::Sender<Result<[u8; 32]>> },
+    Published { round_id: u64, reply: oneshot::Sender<()> },
+    Abandon { round_id: Option<u64>, reason: AbortReason, reply: oneshot::Sender<()> },
+}
+
+/// Sends commands to the round actor
+#[derive(Debug, Clone)]
+pub struct RoundHandle {
+    commands: mpsc::Sender<Command>,
+    status: watch::Receiver<RoundStatus>,
+}
+
+impl RoundHandle {
+    /// Spawn the round actor on the current Tokio runtime
+    ///
+    /// The actor stops once every handle is dropped. It reports commitments,
+    /// phase changes and aborted rounds on `events`, and records the rounds in
+    /// `store`. It continues after the last round of `recovered`, see
+    /// [`RoundActor::restore`] for a round that was still in flight.
+    pub fn spawn(
+        config: &AggregatorConfig,
+        committee: Arc<CommitteeRegistry>,
+        events: EventSender,
+        store: Arc<dyn RoundStore>,
+        recovered: RecoveredRounds,
+    ) -> Result<Self> {
+        let (commands, receiver) = mpsc::channel(COMMAND_QUEUE);
+        let (status_tx, status) = watch::channel(RoundStatus {
+            state: AggregatorState::Idle,
+            round_id: recovered.last_round_id,
+            commitments: 0,
+            reveals: 0,
+        });
+
+        let mut actor = RoundActor {
+            threshold: config.threshold,
+            commitment_timeout: config.commitment_timeout,
+            reveal_timeout: config.reveal_timeout,
+            committee,
+            state: AggregatorState::Idle,
+            round_id: recovered.last_round_id,
+            members: HashSet::new(),
+            commitments: HashMap::new(),
+            reveals: HashMap::new(),
+            deadline: None,
+            status: status_tx,
+            events,
+            store,
+        };
+        if let Some(round) = recovered.in_flight {
+            actor.restore(round)?;
+            actor.publish();
+        }
+        tokio::spawn(actor.run(receiver));
+        Ok(Self { commands, status })
+    }
+
+    /// The latest published status
+    pub fn status(&self) -> RoundStatus {
+        *self.status.borrow()
+    }
+
+    /// Queue a command and wait for its reply, by which time the status is up to date
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| anyhow::anyhow!("Round actor has stopped"))?;
+        response.await.map_err(|_| anyhow::anyhow!("Round actor dropped the request"))
+    }
+
+    /// Start collecting commitments from `committee` for a new round, discarding the current one
+    ///
+    /// Fails with `StaleRoundId` unless `round_id` is higher than that of any
+    /// round started before, including before a restart.
+    ///
+    /// Scheduled key rotations take effect with the round, before it takes any
+    /// commitment. Returns the nodes whose keys were rotated.
+    pub async fn start(&self, round_id: u64, committee: Vec<NodeId>) -> Result<Vec<NodeId>> {
+        self.request(|reply| Command::Start { round_id, committee, reply }).await?
+    }
+
+    /// Check and store a commitment
+    ///
+    /// Fails with `NodeNotInCommittee` if the node is not in the committee the
+    /// round was started with, or has no key for the round.
+    pub async fn commitment(&self, msg: CommitmentMsg) -> Result<Outcome> {
+        self.request(|reply| Command::Commitment { msg, reply }).await?
+    }
+
+    /// Check and store a reveal
+    pub async fn reveal(&self, msg: RevealMsg) -> Result<Outcome> {
+        self.request(|reply| Command::Reveal { msg, reply }).await?
+    }
+
+    /// Nodes whose commitments were accepted for the current round
+    pub async fn committed(&self) -> Result<Vec<NodeId>> {
+        self.request(|reply| Command::Committed { reply }).await
+    }
+
+    /// Derive the round's output from the reveals received so far
+    pub async fn aggregate(&self, round_id: u64) -> Result<[u8; 32]> {
+        self.request(|reply| Command::Aggregate { round_id, reply }).await?
+    }
+
+    /// Record that the output of an aggregated round was published
+    pub async fn published(&self, round_id: u64) -> Result<()> {
+        self.request(|reply| Command::Published { round_id, reply }).await
+    }
+
+    /// Return to idle, if the round still is `round_id` when given
+    pub async fn abandon(&self, round_id: Option<u64>, reason: AbortReason) -> Result<()> {
+        self.request(|reply| Command::Abandon { round_id, reason, reply }).await
+    }
+}
+
+struct RoundActor {
+    threshold: usize,
+    commitment_timeout: Duration,
+    reveal_timeout: Duration,
+    committee: Arc<CommitteeRegistry>,
+    state: AggregatorState,
+    round_id: u64,
+    /// Committee the current round was started with; only they may commit
+    members: HashSet<NodeId>,
+    commitments: HashMap<NodeId, (CommitmentPayload, Vec<u8>)>, // (payload, public_key)
+    reveals: HashMap<NodeId, RoundSecret>,                      // zeroized when cleared
+    /// When the running phase times out
+    deadline: Option<Instant>,
+    status: watch::Sender<RoundStatus>,
+    events: EventSender,
+    store: Arc<dyn RoundStore>,
+}
+
+impl RoundActor {
+    async fn run(mut self, mut commands: mpsc::Receiver<Command>) {
+        loop {
+            let deadline = self.deadline;
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.handle(command),
+                    None => break,
+                },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    self.expire();
+                    self.publish();
+                }
+            }
+        }
+        debug!("Round actor stopped");
+    }
+
+    fn publish(&self) {
+        self.status.send_replace(RoundStatus {
+            state: self.state,
+            round_id: self.round_id,
+            commitments: self.commitments.len(),
+            reveals: self.reveals.len(),
+        });
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Start { round_id, committee, reply } => {
+                let result = self.start(round_id, committee);
+                self.reply(reply, result);
+            }
+            Command::Commitment { msg, reply } => {
+                let outcome = self.commitment(msg);
+                self.reply(reply, outcome);
+            }
+            Command::Reveal { msg, reply } => {
+                let outcome = self.reveal(msg);
+                self.reply(reply, outcome);
+            }
+            Command::Committed { reply } => {
+                self.reply(reply, self.commitments.keys().cloned().collect());
+            }
+            Command::Aggregate { round_id, reply } => {
+                self.reply(reply, aggregate(round_id, &self.reveals));
+            }
+            Command::Published { round_id, reply } => {
+                if self.state == (AggregatorState::Aggregating { round_id }) {
+                    self.state = AggregatorState::Publishing { round_id };
+                }
+                self.reply(reply, ());
+            }
+            Command::Abandon { round_id, reason, reply } => {
+                if round_id.is_none() || round_id == self.state.get_round_id() {
+                    self.abort(reason);
+                }
+                self.reply(reply, ());
+            }
+        }
+    }
+
+    /// Publish the status, then answer the requester
+    fn reply<T>(&self, reply: oneshot::Sender<T>, value: T) {
+        self.publish();
+        // A requester that gave up does not need its reply
+        let _ = reply.send(value);
+    }
+
+    fn start(&mut self, round_id: u64, committee: Vec<NodeId>) -> Result<Vec<NodeId>> {
+        if round_id <= self.round_id {
+            return Err(AggregatorError::StaleRoundId { round_id, last_round_id: self.round_id }.into());
+        }
+        self.store.append(&RoundRecord::Started { round_id, committee: committee.clone() })?;
+
+        // Rotated keys take over at the round boundary
+        let rotated = self.committee.apply_rotations(round_id);
+
+        self.abort(AbortReason::Superseded { by: round_id });
+        self.state = AggregatorState::CollectingCommitments {
+            round_id,
+            threshold: self.threshold,
+        };
+        self.round_id = round_id;
+        self.members = committee.iter().cloned().collect();
+        self.commitments.clear();
+        self.reveals.clear();
+        self.deadline = Some(Instant::now() + self.commitment_timeout);
+        info!("Started new round: {}, waiting for commitments", round_id);
+        self.events.emit(AggregatorEvent::RoundStarted { round_id, committee });
+        Ok(rotated)
+    }
+
+    /// Pick up the round that was in flight when the aggregator stopped
+    ///
+    /// A round that has all its reveals needs no more from the workers, so it is
+    /// resumed at aggregation. Any other round is aborted: its deadlines have
+    /// passed and the workers have lost their sessions.
+    fn restore(&mut self, round: InFlightRound) -> Result<()> {
+        let round_id = round.round_id;
+        if !round.aggregated && round.reveals.len() < self.threshold {
+            warn!("Aborting round {} which was in progress when the aggregator stopped", round_id);
+            return self.store.append(&RoundRecord::Aborted { round_id, reason: AbortReason::Restarted });
+        }
+
+        info!("Resuming round {} at aggregation with {} reveals", round_id, round.reveals.len());
+        self.state = AggregatorState::Aggregating { round_id };
+        self.members = round.committee.into_iter().collect();
+        self.commitments = round.commitments;
+        self.reveals = round.reveals;
+        Ok(())
+    }
+
+    /// Back to idle, reporting the round in progress as aborted
+    fn abort(&mut self, reason: AbortReason) {
+        if self.state.is_in_progress() {
+            if let Some(round_id) = self.state.get_round_id() {
+                let record = RoundRecord::Aborted { round_id, reason: reason.clone() };
+                if let Err(e) = self.store.append(&record) {
+                    error!("Could not record the abort of round {}: {}", round_id, e);
+                }
+                self.events.emit(AggregatorEvent::RoundAborted { round_id, reason });
+            }
+        }
+        self.reset();
+    }
+
+    /// Report a refused commitment
+    fn reject(&self, commitment_msg: &CommitmentMsg, reason: Rejection) -> Outcome {
+        self.events.emit(AggregatorEvent::CommitmentRejected {
+            round_id: commitment_msg.round_id,
+            node_id: commitment_msg.node_id.clone(),
+            reason,
+        });
+        Outcome::Rejected
+    }
+
+    /// Back to idle, dropping the round's commitments and reveals
+    fn reset(&mut self) {
+        self.state = AggregatorState::Idle;
+        self.members.clear();
+        self.commitments.clear();
+        self.reveals.clear();
+        self.deadline = None;
+    }
+
+    fn expire(&mut self) {
+        let reason = match self.state {
+            AggregatorState::CollectingCommitments { round_id, .. } => {
+                warn!("Commitment phase timed out for round {}, received {} commitments out of {} needed, transitioning to Idle",
+                      round_id, self.commitments.len(), self.threshold);
+                AbortReason::CommitmentTimeout { commitments: self.commitments.len(), threshold: self.threshold }
+            }
+            AggregatorState::CollectingReveals { round_id, .. } => {
+                warn!("Reveal phase timed out for round {}, received {} reveals out of {} needed, transitioning to Idle",
+                      round_id, self.reveals.len(), self.threshold);
+                AbortReason::RevealTimeout { reveals: self.reveals.len(), threshold: self.threshold }
+            }
+            _ => AbortReason::Abandoned,
+        };
+        self.abort(reason);
+    }
+
+    fn commitment(&mut self, commitment_msg: CommitmentMsg) -> Result<Outcome> {
+        // A worker that reconnected sends again what it could not confirm
+        let recorded = commitment_msg.round_id == self.round_id
+            && self
+                .commitments
+                .get(&commitment_msg.node_id)
+                .is_some_and(|(payload, _)| *payload == commitment_msg.payload);
+        if recorded {
+            debug!("Commitment from node {} for round {} already recorded", commitment_msg.node_id, commitment_msg.round_id);
+            return Ok(Outcome::Recorded);
+        }
+
+        // Only accept commitments in the CollectingCommitments state
+        let AggregatorState::CollectingCommitments { round_id, .. } = self.state else {
+            warn!("Received commitment while not in CollectingCommitments state");
+            return Ok(self.reject(&commitment_msg, Rejection::WrongPhase));
+        };
+
+        // Verify the round ID matches
+        if commitment_msg.round_id != round_id {
+            warn!("Commitment has wrong round ID: {}, expected: {}", commitment_msg.round_id, round_id);
+            return Ok(self.reject(&commitment_msg, Rejection::WrongRound));
+        }
+
+        // Only the members the round was started with count, with their key for the round
+        let public_key = match self.members.contains(&commitment_msg.node_id) {
+            true => self.committee.public_key_for_round(&commitment_msg.node_id, round_id),
+            false => None,
+        };
+        let Some(public_key) = public_key else {
+            warn!("Rejecting commitment from node {} which is not in the committee of round {}", commitment_msg.node_id, round_id);
+            self.reject(&commitment_msg, Rejection::NotInCommittee);
+            return Err(AggregatorError::NodeNotInCommittee {
+                node_id: commitment_msg.node_id,
+                round_id,
+            }
+            .into());
+        };
+
+        // Verify the signature
+        if !verify_commitment_signature(&commitment_msg, &public_key) {
+            error!(
+                "Invalid signature on commitment from node: {}, round: {}, commitment_hash: {}",
+                commitment_msg.node_id,
+                commitment_msg.round_id,
+                hex::encode(&commitment_msg.payload.commitment[..8]) // First 8 bytes for brevity
+            );
+            return Ok(self.reject(&commitment_msg, Rejection::InvalidSignature));
+        }
+
+        // Check if this node has already sent a commitment for this round
+        if self.commitments.contains_key(&commitment_msg.node_id) {
+            warn!("Node {} already sent a commitment for round {}", commitment_msg.node_id, round_id);
+            return Ok(self.reject(&commitment_msg, Rejection::Conflicting));
+        }
+
+        self.store.append(&RoundRecord::Commitment {
+            round_id,
+            node_id: commitment_msg.node_id.clone(),
+            payload: commitment_msg.payload.clone(),
+            public_key: public_key.clone(),
+        })?;
+        self.commitments.insert(commitment_msg.node_id.clone(), (commitment_msg.payload, public_key));
+        debug!("Received valid commitment from node: {}", commitment_msg.node_id);
+        self.events.emit(AggregatorEvent::CommitmentAccepted { round_id, node_id: commitment_msg.node_id });
+
+        // Enough commitments move the round to the reveal phase
+        let completes_phase = self.commitments.len() >= self.threshold;
+        if completes_phase {
+            self.state = AggregatorState::CollectingReveals {
+                round_id,
+                threshold: self.threshold,
+            };
+            self.deadline = Some(Instant::now() + self.reveal_timeout);
+            info!("Transitioned to reveal phase for round: {}", round_id);
+            let mut committed: Vec<NodeId> = self.commitments.keys().cloned().collect();
+            committed.sort();
+            self.events.emit(AggregatorEvent::RevealPhase { round_id, committed });
+        }
+        Ok(Outcome::Accepted { completes_phase })
+    }
+
+    fn reveal(&mut self, reveal_msg: RevealMsg) -> Result<Outcome> {
+        let recorded = reveal_msg.round_id == self.round_id
+            && self
+                .reveals
+                .get(&reveal_msg.node_id)
+                .is_some_and(|secret| *secret == reveal_msg.payload.secret);
+        if recorded {
+            debug!("Reveal from node {} for round {} already recorded", reveal_msg.node_id, reveal_msg.round_id);
+            return Ok(Outcome::Recorded);
+        }
+
+        // Only accept reveals in the CollectingReveals state
+        let AggregatorState::CollectingReveals { round_id, .. } = self.state else {
+            warn!("Received reveal while not in CollectingReveals state");
+            return Ok(Outcome::Rejected);
+        };
+
+        // Verify the round ID matches
+        if reveal_msg.round_id != round_id {
+            warn!("Reveal has wrong round ID: {}, expected: {}", reveal_msg.round_id, round_id);
+            return Ok(Outcome::Rejected);
+        }
+
+        // Check if this node has already sent a reveal for this round
+        if self.reveals.contains_key(&reveal_msg.node_id) {
+            warn!("Node {} already sent a reveal for round {}", reveal_msg.node_id, round_id);
+            return Ok(Outcome::Rejected);
+        }
+
+        // Verify that this node previously sent a commitment
+        let Some((commitment_payload, public_key)) = self.commitments.get(&reveal_msg.node_id) else {
+            warn!("Node {} sent reveal without prior commitment", reveal_msg.node_id);
+            return Ok(Outcome::Rejected);
+        };
+
+        // Verify that the reveal matches the commitment
+        if !protocol_crypto::verify_reveal(round_id, reveal_msg.payload.secret.expose(), &commitment_payload.commitment) {
+            error!(
+                "Reveal from node {} doesn't match previous commitment for round {}",
+                reveal_msg.node_id, reveal_msg.round_id
+            );
+            return Ok(Outcome::Rejected);
+        }
+
+        // Verify that the reveal is signed by the key that signed the commitment
+        if !verify_reveal_signature(&reveal_msg, &commitment_payload.commitment, public_key) {
+            error!("Invalid signature on reveal from node {} for round {}", reveal_msg.node_id, reveal_msg.round_id);
+            return Ok(Outcome::Rejected);
+        }
+
+        self.store.append(&RoundRecord::Reveal {
+            round_id,
+            node_id: reveal_msg.node_id.clone(),
+            payload: reveal_msg.payload.clone(),
+        })?;
+        self.reveals.insert(reveal_msg.node_id.clone(), reveal_msg.payload.secret);
+        debug!("Received valid reveal from node: {}", reveal_msg.node_id);
+
+        // Enough reveals move the round on to aggregation, which has no deadline here
+        let completes_phase = self.reveals.len() >= self.threshold;
+        if completes_phase {
+            self.state = AggregatorState::Aggregating { round_id };
+            self.deadline = None;
+            info!("Transitioned to aggregation phase for round: {}", round_id);
+        }
+        Ok(Outcome::Accepted { completes_phase })
+    }
+}
+
+/// Verify the signature on a commitment message
+///
+/// The worker signs `entropy_types::crypto::commitment_signing_digest(round_id, commitment)`.
+/// Malformed keys or signatures are treated as invalid rather than as errors.
+pub fn verify_commitment_signature(msg: &CommitmentMsg, public_key: &[u8]) -> bool {
+    match protocol_crypto::verify_commitment_signature(
+        public_key,
+        msg.payload.round_id,
+        &msg.payload.commitment,
+        &msg.payload.signature,
+    ) {
+        Ok(valid) => valid,
+        Err(e) => {
+            warn!("Could not verify commitment signature from node {}: {}", msg.node_id, e);
+            false
+        }
+    }
+}
+
+/// Verify the signature on a reveal message
+///
+/// The worker signs `entropy_types::crypto::reveal_signing_digest` over its node ID,
+/// the round, the commitment the reveal opens and the secret, with the key its
+/// commitment was accepted under. Malformed signatures are treated as invalid.
+fn verify_reveal_signature(reveal_msg: &RevealMsg, commitment: &[u8; 32], public_key: &[u8]) -> bool {
+    match protocol_crypto::verify_reveal_signature(
+        public_key,
+        &reveal_msg.node_id,
+        reveal_msg.payload.round_id,
+        commitment,
+        reveal_msg.payload.secret.expose(),
+        &reveal_msg.payload.signature,
+    ) {
+        Ok(valid) => valid,
+        Err(e) => {
+            warn!("Could not verify reveal signature from node {}: {}", reveal_msg.node_id, e);
+            false
+        }
+    }
+}
+
+/// Aggregate reveals to generate the final entropy for the round
+pub fn aggregate(round_id: u64, reveals: &HashMap<NodeId, RoundSecret>) -> Result<[u8; 32]> {
+    if reveals.is_empty() {
+        return Err(anyhow::anyhow!("No reveals available for aggregation"));
+    }
+
+    // Derive the final entropy with the canonical output construction,
+    // which orders the reveals by NodeId
+    let sorted_reveals: BTreeMap<&NodeId, &RoundSecret> = reveals.iter().collect();
+    let final_entropy = protocol_crypto::derive_output(round_id, &sorted_reveals);
+
+    info!("Aggregated entropy for round {}: {}", round_id, hex::encode(final_entropy));
+    Ok(final_entropy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::committee::CommitteeMember;
+    use crate::store::MemoryRoundStore;
+    use entropy_types::CommitmentPayload;
+    use entropy_worker::crypto::generate_keypair;
+
+    fn handle(threshold: usize, timeout: Duration) -> (RoundHandle, EventSender, secp256k1::SecretKey) {
+        let config = AggregatorConfig {
+            threshold,
+            commitment_timeout: timeout,
+            reveal_timeout: timeout,
+            ..Default::default()
+        };
+        let committee = Arc::new(CommitteeRegistry::new());
+        let (secret_key, public_key) = generate_keypair().unwrap();
+        committee
+            .add_member(CommitteeMember::new("node1".to_string(), public_key.serialize().to_vec()))
+            .unwrap();
+        let events = EventSender::default();
+        let store = Arc::new(MemoryRoundStore::default());
+        let round = RoundHandle::spawn(&config, committee, events.clone(), store, RecoveredRounds::default()).unwrap();
+        (round, events, secret_key)
+    }
+
+    fn signed_commitment(round_id: u64, secret_key: &secp256k1::SecretKey) -> CommitmentMsg {
+        let commitment = protocol_crypto::compute_commitment(round_id, &[1u8; 32]);
+        CommitmentMsg {
+            round_id,
+            payload: CommitmentPayload {
+                round_id,
+                commitment,
+                signature: protocol_crypto::sign_commitment(secret_key, round_id, &commitment),
+            },
+            node_id: "node1".to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_phase_deadlines() {
+        let (round, events, secret_key) = handle(1, Duration::from_millis(300));
+        let mut subscription = events.subscribe();
+        round.start(1, vec!["node1".to_string()]).await.unwrap();
+
+        // Reaching the threshold replaces the commitment deadline with the reveal deadline
+        tokio::time::sleep(Duration::from_millis(180)).await;
+        let outcome = round.commitment(signed_commitment(1, &secret_key)).await.unwrap();
+        assert_eq!(outcome, Outcome::Accepted { completes_phase: true });
+        assert_eq!(round.committed().await.unwrap(), vec!["node1".to_string()]);
+        tokio::time::sleep(Duration::from_millis(180)).await;
+        assert!(round.status().state.is_collecting_reveals());
+
+        tokio::time::sleep(Duration::from_millis(180)).await;
+        let status = round.status();
+        assert!(status.state.is_idle());
+        assert_eq!((status.round_id, status.commitments), (1, 0));
+
+        let node1 = "node1".to_string();
+        let expected = [
+            AggregatorEvent::RoundStarted { round_id: 1, committee: vec![node1.clone()] },
+            AggregatorEvent::CommitmentAccepted { round_id: 1, node_id: node1.clone() },
+            AggregatorEvent::RevealPhase { round_id: 1, committed: vec![node1] },
+            AggregatorEvent::RoundAborted { round_id: 1, reason: AbortReason::RevealTimeout { reveals: 0, threshold: 1 } },
+        ];
+        for event in expected {
+            assert_eq!(subscription.try_recv(), Some(event));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commitments_only_from_round_committee() {
+        let (round, events, secret_key) = handle(1, Duration::from_secs(30));
+        let mut subscription = events.subscribe();
+
+        // node1 is registered, but was not selected for round 1
+        round.start(1, vec!["node2".to_string()]).await.unwrap();
+        let err = round.commitment(signed_commitment(1, &secret_key)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<AggregatorError>(), Some(AggregatorError::NodeNotInCommittee { round_id: 1, .. })));
+        assert_eq!(round.status().commitments, 0);
+        subscription.try_recv();
+        assert_eq!(
+            subscription.try_recv(),
+            Some(AggregatorEvent::CommitmentRejected {
+                round_id: 1,
+                node_id: "node1".to_string(),
+                reason: Rejection::NotInCommittee,
+            })
+        );
+
+        round.start(2, vec!["node1".to_string()]).await.unwrap();
+        let outcome = round.commitment(signed_commitment(2, &secret_key)).await.unwrap();
+        assert_eq!(outcome, Outcome::Accepted { completes_phase: true });
+    }
+
+    #[tokio::test]
+    async fn test_abandon_only_the_named_round() {
+        let (round, events, secret_key) = handle(2, Duration::from_secs(30));
+        round.start(2, vec!["node1".to_string()]).await.unwrap();
+        let mut subscription = events.subscribe();
+        assert_eq!(round.commitment(signed_commitment(2, &secret_key)).await.unwrap(), Outcome::Accepted { completes_phase: false });
+        assert_eq!(round.commitment(signed_commitment(2, &secret_key)).await.unwrap(), Outcome::Recorded);
+
+        // A late failure of round 1 leaves round 2 alone
+        round.abandon(Some(1), AbortReason::Abandoned).await.unwrap();
+        assert_eq!(round.status().commitments, 1);
+        round.abandon(Some(2), AbortReason::Abandoned).await.unwrap();
+        assert!(round.status().state.is_idle());
+
+        // The resent commitment was not reported again
+        assert!(matches!(subscription.try_recv(), Some(AggregatorEvent::CommitmentAccepted { round_id: 2, .. })));
+        assert_eq!(subscription.try_recv(), Some(AggregatorEvent::RoundAborted { round_id: 2, reason: AbortReason::Abandoned }));
+        assert_eq!(subscription.try_recv(), None);
+        assert!(round.aggregate(2).await.is_err());
+
+        // Round IDs are never used twice
+        let err = round.start(2, Vec::new()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AggregatorError>(),
+            Some(AggregatorError::StaleRoundId { round_id: 2, last_round_id: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_start_switches_rotated_keys() {
+        let config = AggregatorConfig { threshold: 1, ..Default::default() };
+        let committee = Arc::new(CommitteeRegistry::new());
+        let (old_key, old_public_key) = generate_keypair().unwrap();
+        let (new_key, new_public_key) = generate_keypair().unwrap();
+        committee
+            .add_member(CommitteeMember::new("node1".to_string(), old_public_key.serialize().to_vec()))
+            .unwrap();
+        committee.schedule_rotation("node1", new_public_key.serialize().to_vec()).unwrap();
+        let store = Arc::new(MemoryRoundStore::default());
+        let round = RoundHandle::spawn(&config, committee, EventSender::default(), store, RecoveredRounds::default()).unwrap();
+
+        // The new key is in place as soon as the round accepts commitments
+        assert_eq!(round.start(1, vec!["node1".to_string()]).await.unwrap(), vec!["node1".to_string()]);
+        assert_eq!(round.commitment(signed_commitment(1, &old_key)).await.unwrap(), Outcome::Rejected);
+        assert_eq!(round.commitment(signed_commitment(1, &new_key)).await.unwrap(), Outcome::Accepted { completes_phase: true });
+    }
+}
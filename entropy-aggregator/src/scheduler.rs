@@ -0,0 +1,181 @@
+//! Automatic round scheduling.
+//!
+//! The scheduler starts a round at every boundary of the configured
+//! [`RoundSchedule`], with the round ID the schedule assigns to that time. Each
+//! ID is used at most once: a round that cannot be started, or fails later, is
+//! not retried, and the next attempt waits for the next boundary with the next
+//! ID. Round IDs never go backwards, even if the clock does.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use entropy_types::{RoundSchedule, StartCommitmentMsg};
+use log::{info, warn};
+
+use crate::aggregator::Aggregator;
+
+/// What the scheduler did at a point in time
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduledRound {
+    /// The round covering the time was started
+    Started(StartCommitmentMsg),
+    /// The round covering the time was skipped and its ID will not be used
+    Skipped { round_id: u64, reason: String },
+    /// Nothing to start: genesis has not been reached or the round was handled already
+    NotDue,
+}
+
+/// Starts rounds on the aggregator following a round schedule
+pub struct RoundScheduler {
+    aggregator: Arc<Aggregator>,
+    schedule: RoundSchedule,
+    /// Highest round ID started or skipped so far
+    last_round_id: u64,
+}
+
+impl RoundScheduler {
+    pub fn new(aggregator: Arc<Aggregator>, schedule: RoundSchedule) -> Self {
+        let last_round_id = aggregator.get_round_id();
+        Self {
+            aggregator,
+            schedule,
+            last_round_id,
+        }
+    }
+
+    pub fn schedule(&self) -> &RoundSchedule {
+        &self.schedule
+    }
+
+    /// Start the round covering `now`, in seconds since the Unix epoch, if that has not happened yet
+    ///
+    /// A round is skipped when the previous one is still running or when the
+    /// aggregator refuses to start it, e.g. because too few members are live.
+    pub async fn tick(&mut self, now: u64) -> ScheduledRound {
+        let round_id = self.schedule.round_at(now);
+        if round_id == 0 || round_id <= self.last_round_id.max(self.aggregator.get_round_id()) {
+            return ScheduledRound::NotDue;
+        }
+        if round_id > self.last_round_id + 1 && self.last_round_id > 0 {
+            warn!("Rounds {} to {} were missed", self.last_round_id + 1, round_id - 1);
+        }
+        self.last_round_id = round_id;
+
+        let state = self.aggregator.get_state();
+        if state.is_in_progress() {
+            return ScheduledRound::Skipped {
+                round_id,
+                reason: format!("round {} is still in progress", state.get_round_id().unwrap_or_default()),
+            };
+        }
+
+        match self.aggregator.start_round(round_id).await {
+            Ok(start_msg) => ScheduledRound::Started(start_msg),
+            Err(e) => ScheduledRound::Skipped {
+                round_id,
+                reason: e.to_string(),
+            },
+        }
+    }
+
+    /// Start rounds at each boundary of the schedule, forever
+    pub async fn run(mut self) {
+        info!(
+            "Scheduling a round every {} seconds from genesis at {}",
+            self.schedule.period_secs, self.schedule.genesis
+        );
+        loop {
+            let now_ms = unix_millis();
+            match self.tick(now_ms / 1000).await {
+                ScheduledRound::Started(start_msg) => {
+                    info!("Started scheduled round {} with committee {:?}", start_msg.round_id, start_msg.committee);
+                }
+                ScheduledRound::Skipped { round_id, reason } => {
+                    warn!("Skipped scheduled round {}: {}", round_id, reason);
+                }
+                ScheduledRound::NotDue => {}
+            }
+
+            let next_start_ms = self.schedule.next_round_start(now_ms / 1000).saturating_mul(1000);
+            tokio::time::sleep(Duration::from_millis(next_start_ms.saturating_sub(unix_millis()))).await;
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::AggregatorConfig;
+    use crate::committee::CommitteeMember;
+    use entropy_types::HeartbeatMsg;
+    use entropy_worker::crypto::generate_keypair;
+
+    const GENESIS: u64 = 1_700_000_000;
+
+    fn scheduler(threshold: usize) -> RoundScheduler {
+        let config = AggregatorConfig {
+            committee_size: 1,
+            threshold,
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(config).unwrap();
+        let (_, public_key) = generate_keypair().unwrap();
+        aggregator
+            .committee
+            .add_member(CommitteeMember::new("node1".to_string(), public_key.serialize().to_vec()))
+            .unwrap();
+        aggregator.liveness.record(&HeartbeatMsg {
+            node_id: "node1".to_string(),
+            timestamp: GENESIS,
+            status: "ok".to_string(),
+            version: String::new(),
+            sent_at_ms: 1,
+            signature: Vec::new(),
+        });
+        RoundScheduler::new(Arc::new(aggregator), RoundSchedule::new(GENESIS, Duration::from_secs(30)))
+    }
+
+    async fn idle(scheduler: &RoundScheduler) {
+        scheduler.aggregator.abandon_round().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rounds_follow_the_schedule() {
+        let mut scheduler = scheduler(1);
+
+        assert_eq!(scheduler.tick(GENESIS - 1).await, ScheduledRound::NotDue);
+        let ScheduledRound::Started(start_msg) = scheduler.tick(GENESIS + 5).await else { panic!("round not started") };
+        assert_eq!(start_msg.round_id, 1);
+        assert_eq!(start_msg.committee, vec!["node1".to_string()]);
+        assert_eq!(scheduler.tick(GENESIS + 10).await, ScheduledRound::NotDue);
+
+        // Round 1 has not finished when round 2 is due, so round 2 is skipped
+        assert!(matches!(scheduler.tick(GENESIS + 30).await, ScheduledRound::Skipped { round_id: 2, .. }));
+        idle(&scheduler).await;
+        assert_eq!(scheduler.tick(GENESIS + 40).await, ScheduledRound::NotDue);
+
+        // After a pause the scheduler continues with the round covering the current time
+        let ScheduledRound::Started(start_msg) = scheduler.tick(GENESIS + 300).await else { panic!("round not started") };
+        assert_eq!(start_msg.round_id, 11);
+        assert_eq!(scheduler.aggregator.get_round_id(), 11);
+
+        // A clock going backwards does not take the round ID back
+        idle(&scheduler).await;
+        assert_eq!(scheduler.tick(GENESIS + 100).await, ScheduledRound::NotDue);
+    }
+
+    #[tokio::test]
+    async fn test_failed_round_is_not_retried() {
+        let mut scheduler = scheduler(2);
+
+        // Too few members are live, so the rounds cannot start and their IDs are used up
+        assert!(matches!(scheduler.tick(GENESIS).await, ScheduledRound::Skipped { round_id: 1, .. }));
+        assert_eq!(scheduler.tick(GENESIS + 1).await, ScheduledRound::NotDue);
+        assert!(matches!(scheduler.tick(GENESIS + 30).await, ScheduledRound::Skipped { round_id: 2, .. }));
+        assert!(scheduler.aggregator.get_state().is_idle());
+    }
+}
@@ -0,0 +1,327 @@
+//! Delivery of round control messages to connected workers.
+//!
+//! Every worker connection registers with the [`SessionRegistry`] once its
+//! handshake succeeds, handing over a channel the connection writes pushed
+//! messages from. The aggregator pushes each round's start commitment to the
+//! committee members and its start reveal to the members that committed.
+//!
+//! A push counts as delivered once the worker answers it with a commitment or
+//! reveal for the round. Until then it is sent again every retry interval, and
+//! to a worker that reconnects; after the configured number of attempts the
+//! worker is marked unreachable. Workers answer a repeated start message with
+//! what they sent before, so retries are harmless.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use entropy_types::{NodeId, ProtocolMessage};
+use log::{debug, info, warn};
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Delivery settings
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    /// How long to wait for an answer before sending a control message again
+    pub retry_interval: Duration,
+    /// Attempts after which a worker that has not answered is unreachable
+    pub max_attempts: u32,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            retry_interval: Duration::from_secs(5),
+            max_attempts: 3,
+        }
+    }
+}
+
+/// Phase of a round a control message opens
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundPhase {
+    Commitment,
+    Reveal,
+}
+
+/// Where the delivery of a control message to a worker stands
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Sent `attempts` times without an answer; 0 while the worker is not connected
+    Pending { attempts: u32 },
+    /// The worker answered
+    Delivered,
+    /// The worker did not answer within the allowed attempts
+    Unreachable,
+}
+
+/// Operator view of a delivery
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DeliveryReport {
+    pub node_id: NodeId,
+    pub round_id: u64,
+    pub phase: RoundPhase,
+    pub connected: bool,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug)]
+struct Connection {
+    id: u64,
+    sender: UnboundedSender<ProtocolMessage>,
+}
+
+#[derive(Debug)]
+struct Delivery {
+    round_id: u64,
+    phase: RoundPhase,
+    message: ProtocolMessage,
+    status: DeliveryStatus,
+    last_attempt: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_connection_id: u64,
+    connections: HashMap<NodeId, Connection>,
+    deliveries: HashMap<NodeId, Delivery>,
+}
+
+impl Inner {
+    /// Send the delivery's message over the node's connection, if there is one
+    fn attempt(&mut self, node_id: &str) -> bool {
+        let (Some(connection), Some(delivery)) = (self.connections.get(node_id), self.deliveries.get_mut(node_id)) else {
+            return false;
+        };
+        if connection.sender.send(delivery.message.clone()).is_err() {
+            return false;
+        }
+        delivery.last_attempt = Some(Instant::now());
+        if let DeliveryStatus::Pending { attempts } = &mut delivery.status {
+            *attempts += 1;
+        }
+        true
+    }
+}
+
+/// Thread-safe registry of worker connections and the control messages pushed to them
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    config: DeliveryConfig,
+    inner: Mutex<Inner>,
+}
+
+impl SessionRegistry {
+    pub fn new(config: DeliveryConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn config(&self) -> &DeliveryConfig {
+        &self.config
+    }
+
+    /// Register the connection of a worker that completed its handshake
+    ///
+    /// Replaces an earlier connection of the same node. A control message the
+    /// node has not answered yet is sent over the new connection right away.
+    /// Returns the ID to pass to [`SessionRegistry::disconnect`].
+    pub fn connect(&self, node_id: &str, sender: UnboundedSender<ProtocolMessage>) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_connection_id += 1;
+        let id = inner.next_connection_id;
+        inner.connections.insert(node_id.to_string(), Connection { id, sender });
+
+        if let Some(delivery) = inner.deliveries.get_mut(node_id) {
+            if delivery.status == DeliveryStatus::Unreachable {
+                delivery.status = DeliveryStatus::Pending { attempts: 0 };
+            }
+            if delivery.status != DeliveryStatus::Delivered && inner.attempt(node_id) {
+                debug!("Resent pending control message to reconnected node {}", node_id);
+            }
+        }
+        id
+    }
+
+    /// Remove a connection, unless the node has connected again since
+    pub fn disconnect(&self, node_id: &str, connection_id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.connections.get(node_id).is_some_and(|connection| connection.id == connection_id) {
+            inner.connections.remove(node_id);
+        }
+    }
+
+    pub fn is_connected(&self, node_id: &str) -> bool {
+        self.inner.lock().unwrap().connections.contains_key(node_id)
+    }
+
+    /// Push a control message opening `phase` of `round_id` to the given nodes
+    ///
+    /// Deliveries for earlier rounds are dropped. Returns how many nodes were
+    /// connected and sent the message right away.
+    pub fn push(&self, node_ids: &[NodeId], round_id: u64, phase: RoundPhase, message: ProtocolMessage) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.deliveries.retain(|_, delivery| delivery.round_id >= round_id);
+
+        let mut sent = 0;
+        for node_id in node_ids {
+            inner.deliveries.insert(node_id.clone(), Delivery {
+                round_id,
+                phase,
+                message: message.clone(),
+                status: DeliveryStatus::Pending { attempts: 0 },
+                last_attempt: None,
+            });
+            if inner.attempt(node_id) {
+                sent += 1;
+            }
+        }
+        debug!("Pushed {} for round {} to {} of {} nodes", message.kind(), round_id, sent, node_ids.len());
+        sent
+    }
+
+    /// Record that a node answered the control message opening `phase` of `round_id`
+    pub fn confirm(&self, node_id: &str, round_id: u64, phase: RoundPhase) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(delivery) = inner.deliveries.get_mut(node_id) {
+            if delivery.round_id == round_id && delivery.phase == phase {
+                delivery.status = DeliveryStatus::Delivered;
+            }
+        }
+    }
+
+    /// Send unanswered control messages again once the retry interval has passed
+    ///
+    /// Nodes that used up their attempts are marked unreachable and returned.
+    /// A node that is not connected uses up an attempt each interval as well.
+    pub fn retry(&self) -> Vec<NodeId> {
+        let mut inner = self.inner.lock().unwrap();
+        let due: Vec<NodeId> = inner
+            .deliveries
+            .iter()
+            .filter(|(_, delivery)| matches!(delivery.status, DeliveryStatus::Pending { .. }))
+            .filter(|(_, delivery)| delivery.last_attempt.is_none_or(|at| at.elapsed() >= self.config.retry_interval))
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+
+        let mut unreachable = Vec::new();
+        for node_id in due {
+            let delivery = inner.deliveries.get_mut(&node_id).unwrap();
+            let DeliveryStatus::Pending { attempts } = &mut delivery.status else { continue };
+            if *attempts >= self.config.max_attempts {
+                warn!("Node {} did not answer {} of round {}, marking it unreachable",
+                      node_id, delivery.message.kind(), delivery.round_id);
+                delivery.status = DeliveryStatus::Unreachable;
+                unreachable.push(node_id);
+                continue;
+            }
+
+            if !inner.attempt(&node_id) {
+                // Not connected: count the attempt so a vanished worker ends up unreachable
+                let delivery = inner.deliveries.get_mut(&node_id).unwrap();
+                delivery.last_attempt = Some(Instant::now());
+                if let DeliveryStatus::Pending { attempts } = &mut delivery.status {
+                    *attempts += 1;
+                }
+            } else {
+                info!("Resent control message to node {}", node_id);
+            }
+        }
+        unreachable
+    }
+
+    /// Delivery state of the control messages of the current round, ordered by node ID
+    pub fn deliveries(&self) -> Vec<DeliveryReport> {
+        let inner = self.inner.lock().unwrap();
+        let mut reports: Vec<DeliveryReport> = inner
+            .deliveries
+            .iter()
+            .map(|(node_id, delivery)| DeliveryReport {
+                node_id: node_id.clone(),
+                round_id: delivery.round_id,
+                phase: delivery.phase,
+                connected: inner.connections.contains_key(node_id),
+                status: delivery.status.clone(),
+            })
+            .collect();
+        reports.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        reports
+    }
+
+    /// Delivery status of the control message last pushed to a node
+    pub fn status(&self, node_id: &str) -> Option<DeliveryStatus> {
+        self.inner.lock().unwrap().deliveries.get(node_id).map(|delivery| delivery.status.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entropy_types::StartRevealMsg;
+    use tokio::sync::mpsc;
+
+    fn start_reveal(round_id: u64) -> ProtocolMessage {
+        ProtocolMessage::StartReveal(StartRevealMsg { round_id, signature: Vec::new() })
+    }
+
+    fn registry() -> SessionRegistry {
+        SessionRegistry::new(DeliveryConfig {
+            retry_interval: Duration::ZERO,
+            max_attempts: 2,
+        })
+    }
+
+    #[test]
+    fn test_push_and_confirm() {
+        let registry = registry();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        registry.connect("node1", sender);
+
+        let nodes = vec!["node1".to_string(), "node2".to_string()];
+        assert_eq!(registry.push(&nodes, 1, RoundPhase::Reveal, start_reveal(1)), 1);
+        assert_eq!(receiver.try_recv().unwrap(), start_reveal(1));
+        assert_eq!(registry.status("node1"), Some(DeliveryStatus::Pending { attempts: 1 }));
+        assert_eq!(registry.status("node2"), Some(DeliveryStatus::Pending { attempts: 0 }));
+
+        // Only an answer for the same round and phase confirms the delivery
+        registry.confirm("node1", 1, RoundPhase::Commitment);
+        registry.confirm("node1", 2, RoundPhase::Reveal);
+        assert_eq!(registry.status("node1"), Some(DeliveryStatus::Pending { attempts: 1 }));
+        registry.confirm("node1", 1, RoundPhase::Reveal);
+        assert_eq!(registry.status("node1"), Some(DeliveryStatus::Delivered));
+
+        // A later round replaces the deliveries of the earlier one
+        registry.push(&nodes[1..], 2, RoundPhase::Reveal, start_reveal(2));
+        assert_eq!(registry.status("node1"), None);
+    }
+
+    #[test]
+    fn test_retry_until_unreachable() {
+        let registry = registry();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let connection_id = registry.connect("node1", sender);
+        registry.push(&["node1".to_string()], 1, RoundPhase::Reveal, start_reveal(1));
+        assert_eq!(receiver.try_recv().unwrap(), start_reveal(1));
+
+        assert!(registry.retry().is_empty());
+        assert_eq!(receiver.try_recv().unwrap(), start_reveal(1));
+        assert_eq!(registry.retry(), vec!["node1".to_string()]);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(registry.status("node1"), Some(DeliveryStatus::Unreachable));
+
+        // A stale connection going away does not drop a newer one
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        registry.connect("node1", sender);
+        registry.disconnect("node1", connection_id);
+        assert!(registry.is_connected("node1"));
+
+        // Reconnecting gives the worker another chance
+        assert_eq!(receiver.try_recv().unwrap(), start_reveal(1));
+        assert_eq!(registry.status("node1"), Some(DeliveryStatus::Pending { attempts: 1 }));
+    }
+}
@@ -1,22 +1,21 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use entropy_types::{CommitmentPayload, NodeId};
 
 /// Aggregator state enum representing different phases of the protocol
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+///
+/// The commitments and reveals themselves are kept by the round actor, so the
+/// state is cheap to copy.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AggregatorState {
     /// Initial state - waiting to start a new round
     Idle,
     /// Collecting commitments from worker nodes
     CollectingCommitments {
         round_id: u64,
-        commitments: HashMap<NodeId, (CommitmentPayload, Vec<u8>)>, // (payload, public_key)
         threshold: usize,
     },
     /// Collecting reveals from worker nodes
     CollectingReveals {
         round_id: u64,
-        reveals: HashMap<NodeId, Vec<u8>>, // (node_id, reveal_data)
         threshold: usize,
     },
     /// Aggregating the final entropy value in TEE
@@ -50,6 +49,16 @@ impl AggregatorState {
         matches!(self, AggregatorState::Publishing { .. })
     }
 
+    /// Check if a round is still being collected or aggregated
+    pub fn is_in_progress(&self) -> bool {
+        matches!(
+            self,
+            AggregatorState::CollectingCommitments { .. }
+                | AggregatorState::CollectingReveals { .. }
+                | AggregatorState::Aggregating { .. }
+        )
+    }
+
     /// Get the round ID if the state has one
     pub fn get_round_id(&self) -> Option<u64> {
         match self {
@@ -60,16 +69,6 @@ impl AggregatorState {
             AggregatorState::Publishing { round_id } => Some(*round_id),
         }
     }
-
-    /// Check if we have enough commitments to transition to reveal phase
-    pub fn has_enough_commitments(&self, threshold: usize) -> bool {
-        match self {
-            AggregatorState::CollectingCommitments { commitments, .. } => {
-                commitments.len() >= threshold
-            }
-            _ => false,
-        }
-    }
 }
 
 #[cfg(test)]
@@ -80,37 +79,18 @@ mod tests {
     fn test_state_enum() {
         let idle_state = AggregatorState::Idle;
         assert!(idle_state.is_idle());
+        assert!(!idle_state.is_in_progress());
 
         let collecting_state = AggregatorState::CollectingCommitments {
             round_id: 1,
-            commitments: HashMap::new(),
             threshold: 3,
         };
         assert!(collecting_state.is_collecting_commitments());
+        assert!(collecting_state.is_in_progress());
         assert_eq!(collecting_state.get_round_id(), Some(1));
-    }
-
-    #[test]
-    fn test_enough_commitments() {
-        let mut commitments = HashMap::new();
-        commitments.insert("node1".to_string(), (CommitmentPayload {
-            round_id: 1,
-            commitment: [0u8; 32],
-            signature: vec![],
-        }, vec![]));
-        commitments.insert("node2".to_string(), (CommitmentPayload {
-            round_id: 1,
-            commitment: [0u8; 32],
-            signature: vec![],
-        }, vec![]));
-
-        let state = AggregatorState::CollectingCommitments {
-            round_id: 1,
-            commitments,
-            threshold: 3,
-        };
 
-        assert!(!state.has_enough_commitments(3));
-        assert!(state.has_enough_commitments(2));
+        let publishing_state = AggregatorState::Publishing { round_id: 1 };
+        assert!(publishing_state.is_publishing());
+        assert!(!publishing_state.is_in_progress());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,524 @@
+//! Durable record of the aggregator's rounds.
+//!
+//! Everything that happens to a round is appended to a [`RoundStore`] as a
+//! [`RoundRecord`] before the aggregator acts on it: the start of the round,
+//! each accepted commitment and reveal, the aggregated output with its
+//! attestation, the Linera transaction it was submitted in, and the abort of a
+//! round that ended without an output. Records are never changed or removed.
+//!
+//! A [`FileRoundStore`] does not keep growing a single file: once it holds
+//! enough records, the file is kept under an archive name at the next round
+//! start and a new one is begun with a [`RoundRecord::Checkpoint`] of the
+//! counters. Only the current file is replayed, the archived ones are the
+//! history and are left for the operator to keep or remove.
+//!
+//! On startup the aggregator replays the records into [`RecoveredRounds`]. The
+//! submission counters pick up where they were, new rounds must use a higher
+//! round ID than any round recorded as started, and a round that had not ended
+//! is either resumed or aborted, see `Aggregator::with_store`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use entropy_types::{CommitmentPayload, NodeId, RevealPayload, RoundSecret};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::events::AbortReason;
+
+/// Round store file used when none is configured
+pub const DEFAULT_STORE_PATH: &str = "aggregator-rounds.jsonl";
+
+/// Records after which [`FileRoundStore`] begins a new file at the next round start
+pub const DEFAULT_SEGMENT_RECORDS: usize = 10_000;
+
+/// One entry of the round store
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "record")]
+pub enum RoundRecord {
+    Started {
+        round_id: u64,
+        committee: Vec<NodeId>,
+    },
+    /// A commitment accepted under `public_key`
+    Commitment {
+        round_id: u64,
+        node_id: NodeId,
+        payload: CommitmentPayload,
+        #[serde(with = "hex_bytes")]
+        public_key: Vec<u8>,
+    },
+    /// A reveal accepted for the node's commitment
+    Reveal {
+        round_id: u64,
+        node_id: NodeId,
+        payload: RevealPayload,
+    },
+    Aggregated {
+        round_id: u64,
+        #[serde(with = "hex_array")]
+        output: [u8; 32],
+        #[serde(with = "hex_bytes")]
+        attestation: Vec<u8>,
+    },
+    /// The output was submitted to the beacon microchain
+    Submitted {
+        round_id: u64,
+        tx_hash: String,
+        block: Option<u64>,
+    },
+    Aborted {
+        round_id: u64,
+        reason: AbortReason,
+    },
+    /// What the records before it added up to; no round is in flight here
+    Checkpoint {
+        last_round_id: u64,
+        submissions_count: u64,
+        last_submission_block: Option<u64>,
+    },
+}
+
+impl RoundRecord {
+    pub fn round_id(&self) -> u64 {
+        match self {
+            RoundRecord::Started { round_id, .. }
+            | RoundRecord::Commitment { round_id, .. }
+            | RoundRecord::Reveal { round_id, .. }
+            | RoundRecord::Aggregated { round_id, .. }
+            | RoundRecord::Submitted { round_id, .. }
+            | RoundRecord::Aborted { round_id, .. } => *round_id,
+            RoundRecord::Checkpoint { last_round_id, .. } => *last_round_id,
+        }
+    }
+}
+
+/// Append-only storage for round records
+pub trait RoundStore: Send + Sync {
+    /// Durably add a record; returns once it survives a crash
+    fn append(&self, record: &RoundRecord) -> Result<()>;
+
+    /// All records, in the order they were appended
+    fn load(&self) -> Result<Vec<RoundRecord>>;
+}
+
+/// Round store that keeps the records in memory, for tests and benchmarks
+#[derive(Debug, Default)]
+pub struct MemoryRoundStore {
+    records: Mutex<Vec<RoundRecord>>,
+}
+
+impl RoundStore for MemoryRoundStore {
+    fn append(&self, record: &RoundRecord) -> Result<()> {
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<RoundRecord>> {
+        Ok(self.records.lock().unwrap().clone())
+    }
+}
+
+/// Round store backed by a file with one JSON record per line
+///
+/// Every append is synced to disk. The file holds the revealed secrets, so it
+/// is only readable by its owner. When a round starts after the file has
+/// reached its record limit, the file is kept as `<path>.<last round ID>` and
+/// `<path>` starts over with a checkpoint, so `load` only returns the records
+/// since.
+#[derive(Debug)]
+pub struct FileRoundStore {
+    path: PathBuf,
+    segment_records: usize,
+    segment: Mutex<Segment>,
+}
+
+/// The file being appended to and what its records add up to
+#[derive(Debug)]
+struct Segment {
+    file: File,
+    records: usize,
+    recovered: RecoveredRounds,
+}
+
+impl FileRoundStore {
+    /// Open the store, creating the file if needed
+    ///
+    /// A record that was cut off by a crash while being appended is dropped.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = open_private(&path).map_err(|e| {
+            anyhow::Error::msg(format!("Failed to open round store {}: {}", path.display(), e))
+        })?;
+
+        // Appending after a partial line would corrupt the next record as well
+        let contents = fs::read(&path)?;
+        let complete = contents.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < contents.len() {
+            warn!("Dropping a record of {} bytes cut off at the end of {}", contents.len() - complete, path.display());
+            file.set_len(complete as u64)?;
+            file.sync_all()?;
+        }
+
+        let records = read_records(&path)?;
+        let segment = Segment {
+            file,
+            records: records.len(),
+            recovered: RecoveredRounds::replay(records),
+        };
+        Ok(Self {
+            path,
+            segment_records: DEFAULT_SEGMENT_RECORDS,
+            segment: Mutex::new(segment),
+        })
+    }
+
+    /// Begin a new file at the first round start after `records` records
+    pub fn with_segment_records(mut self, records: usize) -> Self {
+        self.segment_records = records;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the file is kept once it is replaced, named after its last round
+    pub fn archive_path(&self, last_round_id: u64) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", last_round_id));
+        path.into()
+    }
+
+    /// Keep the current file under its archive name and continue in a new one
+    /// that starts with a checkpoint of its records
+    fn start_segment(&self, segment: &mut Segment) -> Result<()> {
+        let recovered = &segment.recovered;
+        let archive = self.archive_path(recovered.last_round_id);
+        if archive.exists() {
+            // Left by a crash before the new file replaced this one, so it holds a prefix of it
+            if !fs::read(&self.path)?.starts_with(&fs::read(&archive)?) {
+                return Err(anyhow::Error::msg(format!("Round store archive {} already exists", archive.display())));
+            }
+            fs::remove_file(&archive)?;
+        }
+        fs::hard_link(&self.path, &archive)?;
+
+        let checkpoint = RoundRecord::Checkpoint {
+            last_round_id: recovered.last_round_id,
+            submissions_count: recovered.submissions_count,
+            last_submission_block: recovered.last_submission_block,
+        };
+        let mut next = self.path.clone().into_os_string();
+        next.push(".next");
+        let next = PathBuf::from(next);
+        let _ = fs::remove_file(&next);
+        let mut file = open_private(&next)?;
+        file.write_all(&record_line(&checkpoint)?)?;
+        file.sync_all()?;
+        fs::rename(&next, &self.path)?;
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent() {
+            File::open(if dir.as_os_str().is_empty() { Path::new(".") } else { dir })?.sync_all()?;
+        }
+
+        info!("Kept {} round records in {}", segment.records, archive.display());
+        segment.file = file;
+        segment.records = 1;
+        Ok(())
+    }
+}
+
+impl RoundStore for FileRoundStore {
+    fn append(&self, record: &RoundRecord) -> Result<()> {
+        let line = record_line(record)?;
+        let mut segment = self.segment.lock().unwrap();
+        if matches!(record, RoundRecord::Started { .. }) && segment.records >= self.segment_records {
+            self.start_segment(&mut segment)?;
+        }
+        segment.file.write_all(&line)?;
+        segment.file.sync_data()?;
+        segment.records += 1;
+        segment.recovered.apply(record.clone());
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<RoundRecord>> {
+        let _segment = self.segment.lock().unwrap();
+        read_records(&self.path)
+    }
+}
+
+/// Open a file for appending, creating it readable by its owner only
+fn open_private(path: &Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true).append(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn record_line(record: &RoundRecord) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn read_records(path: &Path) -> Result<Vec<RoundRecord>> {
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            anyhow::Error::msg(format!("Invalid record on line {} of {}: {}", number + 1, path.display(), e))
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// A round that had started but not ended when the records were written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InFlightRound {
+    pub round_id: u64,
+    pub committee: Vec<NodeId>,
+    pub commitments: HashMap<NodeId, (CommitmentPayload, Vec<u8>)>, // (payload, public_key)
+    pub reveals: HashMap<NodeId, RoundSecret>,
+    /// The output was derived but not submitted
+    pub aggregated: bool,
+}
+
+/// State of the aggregator rebuilt from the round records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecoveredRounds {
+    /// Highest round ID recorded as started; new rounds must use a higher one
+    pub last_round_id: u64,
+    pub submissions_count: u64,
+    pub last_submission_block: Option<u64>,
+    pub in_flight: Option<InFlightRound>,
+}
+
+impl RecoveredRounds {
+    /// Replay records in the order they were appended
+    pub fn replay(records: impl IntoIterator<Item = RoundRecord>) -> Self {
+        let mut recovered = Self::default();
+        for record in records {
+            recovered.apply(record);
+        }
+        recovered
+    }
+
+    fn apply(&mut self, record: RoundRecord) {
+        let round_id = record.round_id();
+        let in_flight = self.in_flight.as_mut().filter(|round| round.round_id == round_id);
+        match record {
+            RoundRecord::Started { round_id, committee } => {
+                self.last_round_id = self.last_round_id.max(round_id);
+                self.in_flight = Some(InFlightRound {
+                    round_id,
+                    committee,
+                    ..Default::default()
+                });
+            }
+            RoundRecord::Commitment { node_id, payload, public_key, .. } => {
+                if let Some(round) = in_flight {
+                    round.commitments.insert(node_id, (payload, public_key));
+                }
+            }
+            RoundRecord::Reveal { node_id, payload, .. } => {
+                if let Some(round) = in_flight {
+                    round.reveals.insert(node_id, payload.secret);
+                }
+            }
+            RoundRecord::Aggregated { .. } => {
+                if let Some(round) = in_flight {
+                    round.aggregated = true;
+                }
+            }
+            RoundRecord::Submitted { block, .. } => {
+                self.submissions_count += 1;
+                self.last_submission_block = block;
+                if in_flight.is_some() {
+                    self.in_flight = None;
+                }
+            }
+            RoundRecord::Aborted { .. } => {
+                if in_flight.is_some() {
+                    self.in_flight = None;
+                }
+            }
+            RoundRecord::Checkpoint { last_round_id, submissions_count, last_submission_block } => {
+                self.last_round_id = self.last_round_id.max(last_round_id);
+                self.submissions_count = submissions_count;
+                self.last_submission_block = last_submission_block;
+                self.in_flight = None;
+            }
+        }
+    }
+
+    /// Load and replay the records of a store
+    pub fn load(store: &dyn RoundStore) -> Result<Self> {
+        let records = store.load()?;
+        let count = records.len();
+        let recovered = Self::replay(records);
+        if count > 0 {
+            info!(
+                "Recovered {} round records: last round {}, {} submissions",
+                count, recovered.last_round_id, recovered.submissions_count
+            );
+        }
+        Ok(recovered)
+    }
+}
+
+/// Serialize byte vectors as hex strings
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serialize 32-byte arrays as hex strings
+mod hex_array {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        super::hex_bytes::serialize(bytes, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let bytes = super::hex_bytes::deserialize(deserializer)?;
+        bytes.try_into().map_err(|_| serde::de::Error::custom("expected 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store(name: &str) -> FileRoundStore {
+        let path = std::env::temp_dir().join(format!("round-store-test-{}-{}.jsonl", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        FileRoundStore::open(path).unwrap()
+    }
+
+    fn commitment(round_id: u64, node_id: &str) -> RoundRecord {
+        RoundRecord::Commitment {
+            round_id,
+            node_id: node_id.to_string(),
+            payload: CommitmentPayload { round_id, commitment: [1u8; 32], signature: vec![2u8; 64] },
+            public_key: vec![3u8; 33],
+        }
+    }
+
+    fn started(round_id: u64) -> RoundRecord {
+        RoundRecord::Started { round_id, committee: vec!["node1".to_string()] }
+    }
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let store = test_store("round-trip");
+        let records = vec![
+            started(1),
+            commitment(1, "node1"),
+            RoundRecord::Reveal {
+                round_id: 1,
+                node_id: "node1".to_string(),
+                payload: RevealPayload { round_id: 1, secret: [4u8; 32].into(), signature: vec![5u8; 64] },
+            },
+            RoundRecord::Aggregated { round_id: 1, output: [6u8; 32], attestation: vec![7u8] },
+            RoundRecord::Submitted { round_id: 1, tx_hash: "0xabc".to_string(), block: Some(9) },
+        ];
+        for record in &records {
+            store.append(record).unwrap();
+        }
+        assert_eq!(store.load().unwrap(), records);
+
+        // A record cut off by a crash is dropped when the store is opened again
+        let mut file = fs::OpenOptions::new().append(true).open(store.path()).unwrap();
+        file.write_all(br#"{"record":"started","rou"#).unwrap();
+        drop(file);
+        let store = FileRoundStore::open(store.path()).unwrap();
+        store.append(&started(2)).unwrap();
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.len(), records.len() + 1);
+        assert_eq!(loaded.last(), Some(&started(2)));
+
+        fs::remove_file(store.path()).unwrap();
+    }
+
+    #[test]
+    fn test_replay() {
+        let recovered = RecoveredRounds::replay([
+            started(1),
+            commitment(1, "node1"),
+            RoundRecord::Submitted { round_id: 1, tx_hash: "0xabc".to_string(), block: Some(4) },
+            started(2),
+            RoundRecord::Aborted { round_id: 2, reason: AbortReason::Superseded { by: 3 } },
+            started(3),
+            commitment(3, "node1"),
+            // Records of other rounds do not end the round in flight
+            commitment(2, "node2"),
+            RoundRecord::Aborted { round_id: 2, reason: AbortReason::Abandoned },
+        ]);
+
+        assert_eq!(recovered.last_round_id, 3);
+        assert_eq!(recovered.submissions_count, 1);
+        assert_eq!(recovered.last_submission_block, Some(4));
+        let round = recovered.in_flight.unwrap();
+        assert_eq!(round.round_id, 3);
+        assert_eq!(round.commitments.keys().collect::<Vec<_>>(), vec!["node1"]);
+        assert!(round.reveals.is_empty() && !round.aggregated);
+
+        let recovered = RecoveredRounds::replay([
+            RoundRecord::Checkpoint { last_round_id: 5, submissions_count: 3, last_submission_block: Some(8) },
+            started(6),
+            RoundRecord::Submitted { round_id: 6, tx_hash: "0xdef".to_string(), block: Some(9) },
+        ]);
+        assert_eq!(recovered.last_round_id, 6);
+        assert_eq!(recovered.submissions_count, 4);
+        assert_eq!(recovered.last_submission_block, Some(9));
+        assert!(recovered.in_flight.is_none());
+    }
+
+    #[test]
+    fn test_file_store_starts_new_file() {
+        let store = test_store("segments").with_segment_records(3);
+        let submitted = RoundRecord::Submitted { round_id: 1, tx_hash: "0xabc".to_string(), block: Some(4) };
+        let records = vec![started(1), commitment(1, "node1"), submitted, started(2), commitment(2, "node1")];
+        for record in &records {
+            store.append(record).unwrap();
+        }
+
+        // The records up to round 1 are kept aside and only the rest is replayed
+        let archive = store.archive_path(1);
+        assert_eq!(read_records(&archive).unwrap(), records[..3]);
+        let loaded = store.load().unwrap();
+        assert_eq!(
+            loaded[0],
+            RoundRecord::Checkpoint { last_round_id: 1, submissions_count: 1, last_submission_block: Some(4) }
+        );
+        assert_eq!(loaded[1..], records[3..]);
+        let store = FileRoundStore::open(store.path()).unwrap();
+        assert_eq!(RecoveredRounds::load(&store).unwrap(), RecoveredRounds::replay(records));
+
+        fs::remove_file(&archive).unwrap();
+        fs::remove_file(store.path()).unwrap();
+    }
+}
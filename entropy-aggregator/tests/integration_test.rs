@@ -44,13 +44,10 @@ async fn test_full_aggregation_and_submission_flow() {
     // For this test, we'll simulate having reveals available and test the submission
     
     // Manually add some reveals to test aggregation
-    {
-        let reveals = aggregator.reveals.lock().unwrap();
-        println!("✓ Reveals map ready, current count: {}", reveals.len());
-    }
+    println!("✓ Reveals ready, current count: {}", aggregator.get_reveal_count());
     
     // Test the aggregation function directly
-    let entropy = aggregator.aggregate_reveals(1);
+    let entropy = aggregator.aggregate_reveals(1).await;
     assert!(entropy.is_err()); // Should fail since no reveals are available
     println!("✓ Aggregation correctly fails when no reveals available");
     
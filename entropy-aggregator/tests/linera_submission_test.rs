@@ -51,15 +51,12 @@ async fn test_aggregator_with_linera_client() {
     let test_attestation = vec![1u8, 2u8, 3u8];
     
     // Since there are no reveals, this should fail
-    let result = aggregator.aggregate_reveals(test_round_id);
+    let result = aggregator.aggregate_reveals(test_round_id).await;
     assert!(result.is_err(), "Should fail when no reveals are available");
     println!("✓ Correctly fails when no reveals are available");
     
-    // Test that we can access the reveals map to add some test data
-    {
-        let reveals = aggregator.reveals.lock().unwrap();
-        println!("✓ Can access reveals map (current count: {})", reveals.len());
-    }
+    // Test that we can read the reveal count
+    println!("✓ Can read the reveal count (current count: {})", aggregator.get_reveal_count());
     
     // Test the state transitions
     let initial_state = aggregator.get_state();
@@ -0,0 +1,47 @@
+import * as fs from 'fs';
+import * as path from 'path';
+import {
+  COMMITMENT_DOMAIN,
+  COMMITMENT_SIGNATURE_DOMAIN,
+  OUTPUT_DOMAIN,
+  computeCommitment,
+  commitmentSigningDigest,
+  verifyReveal,
+  deriveOutput,
+} from '../protocol';
+
+// Shared with the Rust crates so both sides of the protocol can never drift apart
+const vectorsPath = path.resolve(__dirname, '../../../types/test-vectors/protocol_crypto.json');
+const vectors = JSON.parse(fs.readFileSync(vectorsPath, 'utf8'));
+
+describe('protocol crypto vectors', () => {
+  it('should use the same domains as the Rust crates', () => {
+    expect(vectors.domains.commitment).toBe(COMMITMENT_DOMAIN);
+    expect(vectors.domains.commitment_signature).toBe(COMMITMENT_SIGNATURE_DOMAIN);
+    expect(vectors.domains.output).toBe(OUTPUT_DOMAIN);
+  });
+
+  it('should compute commitments and signing digests', () => {
+    for (const vector of vectors.commitments) {
+      expect(computeCommitment(vector.round_id, vector.secret)).toBe(vector.commitment);
+      expect(commitmentSigningDigest(vector.round_id, vector.commitment)).toBe(vector.signing_digest);
+    }
+  });
+
+  it('should verify reveals only for the committed round', () => {
+    for (const vector of vectors.commitments) {
+      expect(verifyReveal(vector.round_id, vector.secret, vector.commitment)).toBe(true);
+      expect(verifyReveal(vector.round_id + 1, vector.secret, vector.commitment)).toBe(false);
+    }
+  });
+
+  it('should derive the beacon output', () => {
+    for (const vector of vectors.outputs) {
+      const reveals = vector.reveals.map((reveal: { node_id: string; secret: string }) => ({
+        nodeId: reveal.node_id,
+        secret: reveal.secret,
+      }));
+      expect(deriveOutput(vector.round_id, reveals)).toBe(vector.output);
+    }
+  });
+});
@@ -18,4 +18,15 @@ export {
 } from './errors';
 export { EventManager, convertBeaconEventToResult } from './events';
 export { ReconnectionManager, type ReconnectionOptions } from './reconnection';
-export { LineraProvider } from './provider';
\ No newline at end of file
+export { LineraProvider } from './provider';
+export {
+  computeCommitment,
+  commitmentSigningDigest,
+  verifyReveal,
+  deriveOutput,
+  taggedHash,
+  COMMITMENT_DOMAIN,
+  COMMITMENT_SIGNATURE_DOMAIN,
+  OUTPUT_DOMAIN,
+  type RevealEntry,
+} from './protocol';
\ No newline at end of file
@@ -0,0 +1,101 @@
+/**
+ * Canonical protocol cryptography for Entropy Client SDK
+ * Mirrors the `crypto` module of the entropy-types crate
+ *
+ * Every hash is SHA256(domain || 0x00 || fields...), round IDs are encoded as
+ * 8-byte big-endian integers, and all byte strings are hex encoded. The shared
+ * vectors in types/test-vectors/protocol_crypto.json pin these encodings.
+ */
+
+import { createHash } from 'crypto';
+
+export const COMMITMENT_DOMAIN = 'alea-entropy/v1/commitment';
+export const COMMITMENT_SIGNATURE_DOMAIN = 'alea-entropy/v1/commitment-signature';
+export const OUTPUT_DOMAIN = 'alea-entropy/v1/output';
+
+export interface RevealEntry {
+  nodeId: string;
+  secret: string; // hex string representing [u8; 32]
+}
+
+const stripHexPrefix = (value: string): string =>
+  value.startsWith('0x') ? value.slice(2) : value;
+
+const bytes32 = (value: string): Buffer => {
+  const bytes = Buffer.from(stripHexPrefix(value), 'hex');
+  if (bytes.length !== 32) {
+    throw new Error(`Expected 32 bytes, got ${bytes.length}`);
+  }
+  return bytes;
+};
+
+const roundIdBytes = (roundId: number | bigint): Buffer => {
+  const bytes = Buffer.alloc(8);
+  bytes.writeBigUInt64BE(BigInt(roundId));
+  return bytes;
+};
+
+const u32Bytes = (value: number): Buffer => {
+  const bytes = Buffer.alloc(4);
+  bytes.writeUInt32BE(value);
+  return bytes;
+};
+
+/**
+ * Hash the given parts under a protocol domain
+ * @param domain - Domain separation tag
+ * @param parts - Byte strings hashed in order after the tag
+ * @returns Hex encoded SHA-256 digest
+ */
+export const taggedHash = (domain: string, parts: Buffer[]): string => {
+  const hash = createHash('sha256');
+  hash.update(Buffer.from(domain, 'utf8'));
+  hash.update(Buffer.from([0]));
+  parts.forEach((part) => hash.update(part));
+  return hash.digest('hex');
+};
+
+/**
+ * Compute the commitment a worker publishes for its round secret
+ * @param roundId - The round the secret is committed for
+ * @param secret - Hex encoded 32-byte secret
+ * @returns Hex encoded commitment
+ */
+export const computeCommitment = (roundId: number | bigint, secret: string): string =>
+  taggedHash(COMMITMENT_DOMAIN, [roundIdBytes(roundId), bytes32(secret)]);
+
+/**
+ * Compute the digest a worker signs to authenticate its commitment
+ * @param roundId - The round the commitment belongs to
+ * @param commitment - Hex encoded commitment
+ * @returns Hex encoded digest
+ */
+export const commitmentSigningDigest = (roundId: number | bigint, commitment: string): string =>
+  taggedHash(COMMITMENT_SIGNATURE_DOMAIN, [roundIdBytes(roundId), bytes32(commitment)]);
+
+/**
+ * Check that a revealed secret opens the given commitment for the round
+ * @returns boolean - True if the reveal matches the commitment
+ */
+export const verifyReveal = (roundId: number | bigint, secret: string, commitment: string): boolean =>
+  computeCommitment(roundId, secret) === stripHexPrefix(commitment).toLowerCase();
+
+/**
+ * Derive the beacon output for a round from the revealed secrets
+ * Reveals are ordered by the UTF-8 bytes of their node ID, as in the Rust crates
+ * @param roundId - The round the reveals belong to
+ * @param reveals - Revealed secrets in any order
+ * @returns Hex encoded 32-byte output
+ */
+export const deriveOutput = (roundId: number | bigint, reveals: RevealEntry[]): string => {
+  const sorted = reveals
+    .map((reveal) => ({ nodeId: Buffer.from(reveal.nodeId, 'utf8'), secret: bytes32(reveal.secret) }))
+    .sort((a, b) => Buffer.compare(a.nodeId, b.nodeId));
+
+  const parts: Buffer[] = [roundIdBytes(roundId), u32Bytes(sorted.length)];
+  sorted.forEach(({ nodeId, secret }) => {
+    parts.push(u32Bytes(nodeId.length), nodeId, secret);
+  });
+
+  return taggedHash(OUTPUT_DOMAIN, parts);
+};
@@ -63,12 +63,16 @@ export interface HeartbeatMsg {
  nodeId: string; // NodeId
   timestamp: number;
   status: string;
+  version: string;
+  sentAtMs: number;
+  signature: string; // hex string
 }
 
 export interface ErrorMessage {
   errorCode: number;
   errorMessage: string;
   timestamp: number;
+  sequence?: number; // sequence number of the refused envelope, if any
 }
 
 export interface RoundCompletionMsg {
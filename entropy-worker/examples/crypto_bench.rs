@@ -6,9 +6,9 @@ fn main() {
     let start = Instant::now();
     for _ in 0..1000 {
         let secret = crypto::generate_secret().unwrap();
-        let commitment = crypto::compute_commitment(&secret);
+        let commitment = crypto::compute_commitment(1, secret.expose());
         let keypair = crypto::generate_keypair().unwrap();
-        let signature = crypto::sign_commitment(&keypair.0, &commitment).unwrap();
+        let _signature = crypto::sign_commitment(&keypair.0, 1, &commitment).unwrap();
     }
     println!("Crypto operations took: {:?}", start.elapsed());
 }
@@ -0,0 +1,374 @@
+//! Worker configuration file.
+//!
+//! Settings are read from a TOML file and can each be overridden by an
+//! `ENTROPY_WORKER_<SECTION>_<KEY>` environment variable, e.g.
+//! `ENTROPY_WORKER_RETRY_MAX_ATTEMPTS=5`. Lists are given comma-separated.
+//! Missing settings take their defaults, and the result is validated before
+//! the worker starts.
+//!
+//! ```toml
+//! node_id = "worker-0123…"
+//! keystore = "worker-keystore.json"
+//! journal_dir = "worker-journal"
+//! seed_file = "/etc/entropy-worker/seed.bin"
+//! log_level = "info"
+//!
+//! [aggregator]
+//! endpoints = ["aggregator-1.example.org:900", "aggregator-2.example.org:900"]
+//! public_keys = ["02…"]
+//! connect_timeout_ms = 10000
+//!
+//! [retry]
+//! base_delay_ms = 100
+//! max_delay_ms = 30000
+//! max_attempts = 10
+//! jitter = 0.5
+//! ```
+
+use anyhow::Result;
+use entropy_types::crypto::NODE_ID_PREFIX;
+use log::LevelFilter;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::journal::DEFAULT_JOURNAL_DIR;
+use crate::keystore::{DEFAULT_KEYSTORE_PATH, PASSPHRASE_ENV};
+use crate::network::{ClientConfig, ReconnectPolicy};
+
+/// Config file used when none is given on the command line
+pub const DEFAULT_CONFIG_PATH: &str = "worker.toml";
+
+/// Prefix of the environment variables that override config values
+pub const ENV_PREFIX: &str = "ENTROPY_WORKER_";
+
+/// Complete worker configuration
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerSettings {
+    /// Expected node ID; the keystore's identity must match it if set
+    pub node_id: Option<String>,
+
+    /// Encrypted keystore holding the worker's identity
+    pub keystore: PathBuf,
+
+    /// Directory of the round secret journal
+    pub journal_dir: PathBuf,
+
+    /// Operator-supplied seed mixed into every round secret
+    pub seed_file: Option<PathBuf>,
+
+    /// Log level used unless `RUST_LOG` is set
+    pub log_level: String,
+
+    pub aggregator: AggregatorSection,
+
+    pub retry: RetrySection,
+}
+
+/// How to reach the aggregator
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AggregatorSection {
+    /// Aggregator addresses as `host:port`, in order of preference; the worker
+    /// fails over to the next one when it loses its connection
+    pub endpoints: Vec<String>,
+
+    /// Hex-encoded keys the aggregators sign round messages with, as printed
+    /// by the aggregator at startup; required to take part in rounds
+    pub public_keys: Vec<String>,
+
+    /// Timeout for connecting and completing the handshake
+    pub connect_timeout_ms: u64,
+}
+
+/// Reconnect backoff, see `network::ReconnectPolicy`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySection {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Consecutive failed attempts before giving up; unset retries forever
+    pub max_attempts: Option<u32>,
+    pub jitter: f64,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            node_id: None,
+            keystore: PathBuf::from(DEFAULT_KEYSTORE_PATH),
+            journal_dir: PathBuf::from(DEFAULT_JOURNAL_DIR),
+            seed_file: None,
+            log_level: "info".to_string(),
+            aggregator: AggregatorSection::default(),
+            retry: RetrySection::default(),
+        }
+    }
+}
+
+impl Default for AggregatorSection {
+    fn default() -> Self {
+        Self {
+            endpoints: vec!["localhost:900".to_string()],
+            public_keys: Vec::new(),
+            connect_timeout_ms: 10_000,
+        }
+    }
+}
+
+impl Default for RetrySection {
+    fn default() -> Self {
+        let policy = ReconnectPolicy::default();
+        Self {
+            base_delay_ms: policy.base_delay.as_millis() as u64,
+            max_delay_ms: policy.max_delay.as_millis() as u64,
+            max_attempts: policy.max_attempts,
+            jitter: policy.jitter,
+        }
+    }
+}
+
+impl WorkerSettings {
+    /// Load the config file, apply environment overrides and validate the result
+    ///
+    /// A missing file is only an error if `required` is set; otherwise the
+    /// defaults are used.
+    pub fn load(path: &Path, required: bool) -> Result<Self> {
+        let mut config = if path.exists() || required {
+            let text = std::fs::read_to_string(path).map_err(|e| {
+                anyhow::Error::msg(format!("Failed to read config file {}: {}", path.display(), e))
+            })?;
+            Self::parse(&text).map_err(|e| {
+                anyhow::Error::msg(format!("Invalid config file {}: {}", path.display(), e))
+            })?
+        } else {
+            Self::default()
+        };
+
+        config.apply_env(std::env::vars())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Override values from `ENTROPY_WORKER_*` variables
+    ///
+    /// Unknown variables with the prefix are rejected, like unknown keys in the file.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<()> {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let invalid = |e: &dyn std::fmt::Display| {
+                anyhow::Error::msg(format!("Invalid value {:?} for {}: {}", value, name, e))
+            };
+
+            match key {
+                "NODE_ID" => self.node_id = Some(value.clone()),
+                "KEYSTORE" => self.keystore = PathBuf::from(&value),
+                "JOURNAL_DIR" => self.journal_dir = PathBuf::from(&value),
+                "SEED_FILE" => self.seed_file = Some(PathBuf::from(&value)),
+                "LOG_LEVEL" => self.log_level = value.clone(),
+                "AGGREGATOR_ENDPOINTS" => {
+                    self.aggregator.endpoints = value
+                        .split(',')
+                        .map(|endpoint| endpoint.trim().to_string())
+                        .filter(|endpoint| !endpoint.is_empty())
+                        .collect();
+                }
+                "AGGREGATOR_PUBLIC_KEYS" => {
+                    self.aggregator.public_keys = value
+                        .split(',')
+                        .map(|key| key.trim().to_string())
+                        .filter(|key| !key.is_empty())
+                        .collect();
+                }
+                "AGGREGATOR_CONNECT_TIMEOUT_MS" => {
+                    self.aggregator.connect_timeout_ms = value.parse().map_err(|e| invalid(&e))?;
+                }
+                "RETRY_BASE_DELAY_MS" => self.retry.base_delay_ms = value.parse().map_err(|e| invalid(&e))?,
+                "RETRY_MAX_DELAY_MS" => self.retry.max_delay_ms = value.parse().map_err(|e| invalid(&e))?,
+                "RETRY_MAX_ATTEMPTS" => {
+                    self.retry.max_attempts = match value.as_str() {
+                        "" | "none" => None,
+                        attempts => Some(attempts.parse().map_err(|e| invalid(&e))?),
+                    };
+                }
+                "RETRY_JITTER" => self.retry.jitter = value.parse().map_err(|e| invalid(&e))?,
+                _ if name == PASSPHRASE_ENV => {}
+                // Most likely a typo, which would otherwise go unnoticed
+                _ => return Err(anyhow::Error::msg(format!("Unknown setting {}", name))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the configuration can be used to run a worker
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if let Some(node_id) = &self.node_id {
+            if !node_id.starts_with(NODE_ID_PREFIX) {
+                problems.push(format!("node_id {:?} does not start with {:?}", node_id, NODE_ID_PREFIX));
+            }
+        }
+        if self.keystore.as_os_str().is_empty() {
+            problems.push("keystore path is empty".to_string());
+        }
+        if self.journal_dir.as_os_str().is_empty() {
+            problems.push("journal_dir is empty".to_string());
+        }
+        if self.log_level.parse::<LevelFilter>().is_err() {
+            problems.push(format!("log_level {:?} is not a log level", self.log_level));
+        }
+
+        if self.aggregator.endpoints.is_empty() {
+            problems.push("aggregator.endpoints is empty".to_string());
+        }
+        for endpoint in &self.aggregator.endpoints {
+            let valid = endpoint
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+            if !valid {
+                problems.push(format!("aggregator endpoint {:?} is not host:port", endpoint));
+            }
+        }
+        for key in &self.aggregator.public_keys {
+            if parse_public_key(key).is_none() {
+                problems.push(format!("aggregator public key {:?} is not a hex-encoded secp256k1 key", key));
+            }
+        }
+        if self.aggregator.connect_timeout_ms == 0 {
+            problems.push("aggregator.connect_timeout_ms must be positive".to_string());
+        }
+
+        if self.retry.base_delay_ms == 0 {
+            problems.push("retry.base_delay_ms must be positive".to_string());
+        }
+        if self.retry.max_delay_ms < self.retry.base_delay_ms {
+            problems.push("retry.max_delay_ms is smaller than retry.base_delay_ms".to_string());
+        }
+        if self.retry.max_attempts == Some(0) {
+            problems.push("retry.max_attempts must be positive; leave it unset to retry forever".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.retry.jitter) {
+            problems.push(format!("retry.jitter {} is not between 0 and 1", self.retry.jitter));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(format!("Invalid worker configuration: {}", problems.join("; "))))
+        }
+    }
+
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        ReconnectPolicy {
+            base_delay: Duration::from_millis(self.retry.base_delay_ms),
+            max_delay: Duration::from_millis(self.retry.max_delay_ms),
+            max_attempts: self.retry.max_attempts,
+            jitter: self.retry.jitter,
+        }
+    }
+
+    /// The pinned aggregator keys; `validate` has checked that they parse
+    pub fn aggregator_keys(&self) -> Vec<PublicKey> {
+        self.aggregator.public_keys.iter().filter_map(|key| parse_public_key(key)).collect()
+    }
+
+    /// Connection settings for the aggregator endpoints
+    pub fn client_config(&self, node_id: String) -> ClientConfig {
+        let mut config = ClientConfig::with_endpoints(self.aggregator.endpoints.clone(), node_id);
+        config.reconnect = self.reconnect_policy();
+        config.connect_timeout = Duration::from_millis(self.aggregator.connect_timeout_ms);
+        config
+    }
+}
+
+fn parse_public_key(key: &str) -> Option<PublicKey> {
+    hex::decode(key).ok().and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_partial_file() {
+        let config = WorkerSettings::parse(
+            r#"
+            keystore = "/var/lib/worker/keystore.json"
+
+            [aggregator]
+            endpoints = ["agg-1:900", "agg-2:900"]
+            public_keys = ["0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"]
+
+            [retry]
+            max_attempts = 5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.keystore, PathBuf::from("/var/lib/worker/keystore.json"));
+        assert_eq!(config.aggregator.endpoints, vec!["agg-1:900", "agg-2:900"]);
+        assert_eq!(config.aggregator_keys().len(), 1);
+        assert_eq!(config.retry.max_attempts, Some(5));
+        assert_eq!(config.retry.base_delay_ms, RetrySection::default().base_delay_ms);
+        assert_eq!(config.journal_dir, PathBuf::from(DEFAULT_JOURNAL_DIR));
+        config.validate().unwrap();
+
+        assert!(WorkerSettings::parse("keystor = \"typo.json\"").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = WorkerSettings::default();
+        config
+            .apply_env(vars(&[
+                ("ENTROPY_WORKER_AGGREGATOR_ENDPOINTS", "agg-1:900, agg-2:901"),
+                ("ENTROPY_WORKER_RETRY_MAX_ATTEMPTS", "3"),
+                ("ENTROPY_WORKER_LOG_LEVEL", "debug"),
+                ("ENTROPY_WORKER_PASSPHRASE", "not a setting"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.aggregator.endpoints, vec!["agg-1:900", "agg-2:901"]);
+        assert_eq!(config.retry.max_attempts, Some(3));
+        assert_eq!(config.log_level, "debug");
+
+        let result = config.apply_env(vars(&[("ENTROPY_WORKER_RETRY_JITTER", "lots")]));
+        assert!(result.unwrap_err().to_string().contains("ENTROPY_WORKER_RETRY_JITTER"));
+        assert!(config.apply_env(vars(&[("ENTROPY_WORKER_KEY_STORE", "typo.json")])).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        WorkerSettings::default().validate().unwrap();
+
+        let mut config = WorkerSettings {
+            node_id: Some("node-1".to_string()),
+            log_level: "loud".to_string(),
+            ..Default::default()
+        };
+        config.aggregator.endpoints = vec!["no-port".to_string()];
+        config.aggregator.public_keys = vec!["02abcd".to_string()];
+        config.retry.max_delay_ms = 1;
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("node_id"));
+        assert!(message.contains("no-port"));
+        assert!(message.contains("02abcd"));
+        assert!(message.contains("retry.max_delay_ms"));
+        assert!(message.contains("log_level"));
+    }
+}
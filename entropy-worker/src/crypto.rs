@@ -1,49 +1,33 @@
 use anyhow::Result;
-use entropy_types::CommitmentPayload;
+use entropy_types::crypto as protocol_crypto;
+use entropy_types::{CommitmentPayload, RoundSecret};
 use getrandom::getrandom;
-use ring::{rand, digest};
-use secp256k1::{Secp256k1, SecretKey, PublicKey, Message};
-use sha2::{Sha256, Digest};
+use secp256k1::{Secp256k1, SecretKey, PublicKey};
+use zeroize::Zeroizing;
 
 /// Generate a cryptographically secure random 32-byte secret using OS RNG
-pub fn generate_secret() -> Result<[u8; 32]> {
-    let mut secret = [0u8; 32];
-    getrandom(&mut secret).map_err(|e| anyhow::Error::msg(format!("Failed to generate random secret: {}", e)))?;
-    Ok(secret)
+///
+/// Workers draw round secrets from their health-tested `entropy::EntropyPool`.
+pub fn generate_secret() -> Result<RoundSecret> {
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    getrandom(bytes.as_mut()).map_err(|e| anyhow::Error::msg(format!("Failed to generate random secret: {}", e)))?;
+    Ok(RoundSecret::new(*bytes))
 }
 
-/// Compute SHA256 hash of the secret to create commitment
-pub fn compute_commitment(secret: &[u8; 32]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(secret);
-    let result = hasher.finalize();
-    let mut commitment = [0u8; 32];
-    commitment.copy_from_slice(&result);
-    commitment
+/// Compute the commitment to the secret for the given round
+///
+/// Delegates to the canonical, domain-separated construction in `entropy_types::crypto`
+/// so the aggregator recomputes exactly the same value from the reveal.
+pub fn compute_commitment(round_id: u64, secret: &[u8; 32]) -> [u8; 32] {
+    protocol_crypto::compute_commitment(round_id, secret)
 }
 
-/// Sign the commitment with the node's secp256k1 private key
-pub fn sign_commitment(secret_key: &SecretKey, commitment: &[u8; 32]) -> Result<Vec<u8>> {
-    let secp = Secp256k1::new();
-    
-    // Hash the commitment to create a message digest
-    let mut hasher = Sha256::new();
-    hasher.update(commitment);
-    let hash_bytes = hasher.finalize();
-    
-    // Create a message from the hash
-    let message = Message::from_digest_slice(&hash_bytes)?;
-    
-    // Sign the message
-    let signature = secp.sign_ecdsa_recoverable(&message, secret_key);
-    let (recovery_id, signature_bytes) = signature.serialize_compact();
-    
-    // Combine signature and recovery ID into a single vector
-    let mut signature_data = vec![0u8; 65]; // 64 bytes for signature + 1 byte for recovery ID
-    signature_data[0..64].copy_from_slice(&signature_bytes);
-    signature_data[64] = recovery_id.to_i32() as u8;
-    
-    Ok(signature_data)
+/// Sign the commitment for the given round with the node's secp256k1 private key
+///
+/// Returns the 65-byte recoverable signature (64-byte compact signature + recovery ID)
+/// over `entropy_types::crypto::commitment_signing_digest`.
+pub fn sign_commitment(secret_key: &SecretKey, round_id: u64, commitment: &[u8; 32]) -> Result<Vec<u8>> {
+    Ok(protocol_crypto::sign_commitment(secret_key, round_id, commitment))
 }
 
 /// Generate a new secp256k1 key pair for the worker node
@@ -51,11 +35,11 @@ pub fn generate_keypair() -> Result<(SecretKey, PublicKey)> {
     let secp = Secp256k1::new();
     
     // Generate a random secret key using the OS RNG
-    let mut secret_bytes = [0u8; 32];
-    getrandom(&mut secret_bytes)?;
+    let mut secret_bytes = Zeroizing::new([0u8; 32]);
+    getrandom(secret_bytes.as_mut())?;
     
     // Ensure the secret key is valid for secp256k1
-    let secret_key = SecretKey::from_slice(&secret_bytes)?;
+    let secret_key = SecretKey::from_slice(secret_bytes.as_ref())?;
     let public_key = PublicKey::from_secret_key(&secp, &secret_key);
     
     Ok((secret_key, public_key))
@@ -67,8 +51,8 @@ pub fn create_commitment_payload(
     secret: &[u8; 32],
     secret_key: &SecretKey,
 ) -> Result<CommitmentPayload> {
-    let commitment = compute_commitment(secret);
-    let signature = sign_commitment(secret_key, &commitment)?;
+    let commitment = compute_commitment(round_id, secret);
+    let signature = sign_commitment(secret_key, round_id, &commitment)?;
     
     Ok(CommitmentPayload {
         round_id,
@@ -88,8 +72,8 @@ mod tests {
         let secret2 = generate_secret().unwrap();
         
         // Verify both secrets are 32 bytes
-        assert_eq!(secret1.len(), 32);
-        assert_eq!(secret2.len(), 32);
+        assert_eq!(secret1.expose().len(), 32);
+        assert_eq!(secret2.expose().len(), 32);
         
         // Verify they are different (highly likely with proper randomness)
         assert_ne!(secret1, secret2);
@@ -98,19 +82,22 @@ mod tests {
     #[test]
     fn test_compute_commitment() {
         let secret = [1u8; 32];
-        let commitment = compute_commitment(&secret);
+        let commitment = compute_commitment(1, &secret);
         
         // Verify commitment is 32 bytes
         assert_eq!(commitment.len(), 32);
         
         // Verify deterministic behavior - same input produces same output
-        let commitment2 = compute_commitment(&secret);
+        let commitment2 = compute_commitment(1, &secret);
         assert_eq!(commitment, commitment2);
         
         // Verify different inputs produce different outputs
         let secret2 = [2u8; 32];
-        let commitment3 = compute_commitment(&secret2);
+        let commitment3 = compute_commitment(1, &secret2);
         assert_ne!(commitment, commitment3);
+        
+        // Verify the commitment is bound to the round
+        assert_ne!(commitment, compute_commitment(2, &secret));
     }
 
     #[test]
@@ -128,7 +115,7 @@ mod tests {
         let (secret_key, _) = generate_keypair().unwrap();
         let commitment = [1u8; 32];
         
-        let signature = sign_commitment(&secret_key, &commitment).unwrap();
+        let signature = sign_commitment(&secret_key, 1, &commitment).unwrap();
         
         // Verify signature is 65 bytes (64 bytes for signature + 1 byte for recovery ID)
         assert_eq!(signature.len(), 65);
@@ -146,11 +133,18 @@ mod tests {
         assert_eq!(payload.round_id, round_id);
         
         // Verify commitment matches expected value
-        let expected_commitment = compute_commitment(&secret);
+        let expected_commitment = compute_commitment(round_id, &secret);
         assert_eq!(payload.commitment, expected_commitment);
         
-        // Verify signature is valid (length check)
+        // Verify signature is 65 bytes and checks out under the public key
         assert_eq!(payload.signature.len(), 65);
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        assert!(protocol_crypto::verify_commitment_signature(
+            &public_key.serialize(),
+            round_id,
+            &payload.commitment,
+            &payload.signature,
+        ).unwrap());
     }
 
     #[test]
@@ -159,22 +153,40 @@ mod tests {
         let secret = generate_secret().unwrap();
         
         // Compute commitment
-        let commitment = compute_commitment(&secret);
+        let commitment = compute_commitment(42, secret.expose());
         
         // Generate keypair
         let (secret_key, public_key) = generate_keypair().unwrap();
         
         // Sign the commitment
-        let signature = sign_commitment(&secret_key, &commitment).unwrap();
+        let signature = sign_commitment(&secret_key, 42, &commitment).unwrap();
         
         // Verify all components work together
         assert_eq!(commitment.len(), 32);
         assert_eq!(signature.len(), 65);
         
         // Create commitment payload
-        let payload = create_commitment_payload(42, &secret, &secret_key).unwrap();
+        let payload = create_commitment_payload(42, secret.expose(), &secret_key).unwrap();
         assert_eq!(payload.round_id, 42);
-        assert_eq!(payload.commitment, compute_commitment(&secret));
+        assert_eq!(payload.commitment, compute_commitment(42, secret.expose()));
         assert_eq!(payload.signature.len(), 65);
+        assert!(protocol_crypto::verify_commitment_signature(&public_key.serialize(), 42, &commitment, &signature).unwrap());
+    }
+
+    #[test]
+    fn test_shared_protocol_vectors() {
+        // The worker must produce exactly what the shared vectors pin down
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../types/test-vectors/protocol_crypto.json")).unwrap();
+
+        for vector in vectors["commitments"].as_array().unwrap() {
+            let round_id = vector["round_id"].as_u64().unwrap();
+            let secret: [u8; 32] = hex::decode(vector["secret"].as_str().unwrap()).unwrap().try_into().unwrap();
+            let secret_key = SecretKey::from_slice(&hex::decode(vector["secret_key"].as_str().unwrap()).unwrap()).unwrap();
+
+            let payload = create_commitment_payload(round_id, &secret, &secret_key).unwrap();
+            assert_eq!(hex::encode(payload.commitment), vector["commitment"].as_str().unwrap());
+            assert_eq!(hex::encode(&payload.signature), vector["signature"].as_str().unwrap());
+        }
     }
 }
\ No newline at end of file
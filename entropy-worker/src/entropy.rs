@@ -0,0 +1,574 @@
+//! Entropy sources for round secrets.
+//!
+//! Round secrets are extracted from every configured source at once: the OS
+//! RNG, `/dev/hwrng` and RDRAND when the host has them, and an optional
+//! operator-supplied seed file. The raw bytes of all sources are hashed
+//! together, so the secret stays unpredictable as long as any one source is.
+//!
+//! Every source is watched by the continuous health tests of NIST SP 800-90B
+//! (section 4.4): the repetition count test and the adaptive proportion test.
+//! A failed source stays failed until the worker restarts, and no secret is
+//! generated while any source is failed.
+
+use anyhow::Result;
+use entropy_types::crypto::tagged_hash;
+use entropy_types::RoundSecret;
+use getrandom::getrandom;
+use log::{error, info};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Domain separating the extractor from the protocol's other hashes
+pub const EXTRACTOR_DOMAIN: &[u8] = b"alea-entropy/v1/extractor";
+
+/// Domain used to expand a seed file into a byte stream
+pub const SEED_FILE_DOMAIN: &[u8] = b"alea-entropy/v1/seed-file";
+
+/// Where the kernel exposes the hardware RNG
+pub const DEFAULT_HWRNG_PATH: &str = "/dev/hwrng";
+
+/// Shortest seed file accepted, in bytes
+pub const MIN_SEED_LENGTH: usize = 32;
+
+/// Samples each source must pass before its first use (SP 800-90B 4.3)
+const STARTUP_SAMPLES: usize = 1024;
+
+/// A source of raw random bytes
+pub trait EntropySource: Send {
+    /// Short name used in logs and heartbeats
+    fn name(&self) -> &str;
+
+    /// Fill `buf` with bytes from the source
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()>;
+}
+
+/// The operating system's RNG
+pub struct OsRng;
+
+impl EntropySource for OsRng {
+    fn name(&self) -> &str {
+        "os-rng"
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        getrandom(buf).map_err(|e| anyhow::Error::msg(format!("OS RNG failed: {}", e)))
+    }
+}
+
+/// A hardware RNG character device such as `/dev/hwrng`
+pub struct HwRng {
+    path: PathBuf,
+    file: File,
+}
+
+impl HwRng {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)
+            .map_err(|e| anyhow::Error::msg(format!("Failed to open {}: {}", path.display(), e)))?;
+        Ok(HwRng { path, file })
+    }
+
+    /// The host's hardware RNG, if it has one and it can be read
+    pub fn detect() -> Option<Self> {
+        let mut hwrng = Self::open(DEFAULT_HWRNG_PATH).ok()?;
+        // The device node exists even when no RNG is bound to it
+        hwrng.fill(&mut [0u8; 1]).ok()?;
+        Some(hwrng)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl EntropySource for HwRng {
+    fn name(&self) -> &str {
+        "hwrng"
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.file
+            .read_exact(buf)
+            .map_err(|e| anyhow::Error::msg(format!("Failed to read {}: {}", self.path.display(), e)))
+    }
+}
+
+/// The CPU's RDRAND instruction
+pub struct Rdrand {
+    _private: (),
+}
+
+impl Rdrand {
+    /// RDRAND, if the CPU supports it
+    pub fn detect() -> Option<Self> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if std::arch::is_x86_feature_detected!("rdrand") {
+                return Some(Rdrand { _private: () });
+            }
+        }
+        None
+    }
+}
+
+impl EntropySource for Rdrand {
+    fn name(&self) -> &str {
+        "rdrand"
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        for chunk in buf.chunks_mut(8) {
+            // Safe because `detect` checked that the CPU supports RDRAND
+            let value = unsafe { rdrand64() }
+                .ok_or_else(|| anyhow::Error::msg("RDRAND did not return a value"))?;
+            chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn fill(&mut self, _buf: &mut [u8]) -> Result<()> {
+        Err(anyhow::Error::msg("RDRAND is not available on this architecture"))
+    }
+}
+
+/// Read one RDRAND value, retrying as Intel recommends for transient underflows
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand64() -> Option<u64> {
+    let mut value = 0u64;
+    for _ in 0..10 {
+        if std::arch::x86_64::_rdrand64_step(&mut value) == 1 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// An operator-supplied seed, e.g. dice rolls or output of an offline RNG
+///
+/// The seed is read once and expanded with a counter, so it only adds entropy
+/// that the operator put in the file; it guards against the other sources being
+/// weak, not against them all being compromised along with the file.
+pub struct SeedFile {
+    seed: Zeroizing<Vec<u8>>,
+    counter: u64,
+}
+
+impl SeedFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let seed = std::fs::read(path)
+            .map(Zeroizing::new)
+            .map_err(|e| anyhow::Error::msg(format!("Failed to read seed file {}: {}", path.display(), e)))?;
+        if seed.len() < MIN_SEED_LENGTH {
+            return Err(anyhow::Error::msg(format!(
+                "Seed file {} holds {} bytes, at least {} are required",
+                path.display(),
+                seed.len(),
+                MIN_SEED_LENGTH
+            )));
+        }
+        Ok(SeedFile { seed, counter: 0 })
+    }
+}
+
+impl EntropySource for SeedFile {
+    fn name(&self) -> &str {
+        "seed-file"
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        for chunk in buf.chunks_mut(32) {
+            let block = Zeroizing::new(tagged_hash(SEED_FILE_DOMAIN, &[self.seed.as_slice(), &self.counter.to_be_bytes()]));
+            self.counter += 1;
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of the continuous health tests
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    /// Min-entropy assumed per byte, in bits
+    pub min_entropy_per_byte: f64,
+
+    /// The tests' false positive probability is `2^-false_positive_log2`
+    pub false_positive_log2: u32,
+
+    /// Window size of the adaptive proportion test, in bytes
+    pub apt_window: usize,
+
+    /// Bytes drawn from each source per secret
+    pub bytes_per_source: usize,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            // Conservative for sources that already condition their output
+            min_entropy_per_byte: 4.0,
+            false_positive_log2: 20,
+            apt_window: 512,
+            // 256 bits of assumed min-entropy per source
+            bytes_per_source: 64,
+        }
+    }
+}
+
+impl HealthConfig {
+    /// Run length at which the repetition count test fails
+    pub fn rct_cutoff(&self) -> usize {
+        1 + (self.false_positive_log2 as f64 / self.min_entropy_per_byte).ceil() as usize
+    }
+
+    /// Count within a window at which the adaptive proportion test fails
+    ///
+    /// One more than the smallest `k` with `P(X <= k) >= 1 - alpha` for
+    /// `X ~ Binomial(window, 2^-H)`, as in SP 800-90B 4.4.2.
+    pub fn apt_cutoff(&self) -> usize {
+        let n = self.apt_window;
+        let p = 2f64.powf(-self.min_entropy_per_byte);
+        let alpha = 2f64.powi(-(self.false_positive_log2 as i32));
+
+        let mut pmf = (1.0 - p).powi(n as i32);
+        let mut cdf = pmf;
+        let mut k = 0;
+        while cdf < 1.0 - alpha && k < n {
+            pmf *= (n - k) as f64 / (k + 1) as f64 * p / (1.0 - p);
+            cdf += pmf;
+            k += 1;
+        }
+        1 + k
+    }
+}
+
+/// Why a source was taken out of service
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthFailure {
+    RepetitionCount { value: u8, run: usize },
+    AdaptiveProportion { value: u8, count: usize, window: usize },
+    Unavailable { message: String },
+}
+
+impl fmt::Display for HealthFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthFailure::RepetitionCount { value, run } => {
+                write!(f, "repetition count test failed: 0x{:02x} repeated {} times", value, run)
+            }
+            HealthFailure::AdaptiveProportion { value, count, window } => {
+                write!(f, "adaptive proportion test failed: 0x{:02x} seen {} times in {} bytes", value, count, window)
+            }
+            HealthFailure::Unavailable { message } => write!(f, "source unavailable: {}", message),
+        }
+    }
+}
+
+/// Running state of both health tests for one source
+struct HealthTests {
+    rct_cutoff: usize,
+    apt_cutoff: usize,
+    apt_window: usize,
+    last: Option<u8>,
+    run: usize,
+    apt_value: Option<u8>,
+    apt_count: usize,
+    apt_seen: usize,
+}
+
+impl HealthTests {
+    fn new(config: &HealthConfig) -> Self {
+        HealthTests {
+            rct_cutoff: config.rct_cutoff(),
+            apt_cutoff: config.apt_cutoff(),
+            apt_window: config.apt_window,
+            last: None,
+            run: 0,
+            apt_value: None,
+            apt_count: 0,
+            apt_seen: 0,
+        }
+    }
+
+    fn test(&mut self, sample: u8) -> Result<(), HealthFailure> {
+        // Repetition count test (4.4.1)
+        if self.last == Some(sample) {
+            self.run += 1;
+            if self.run >= self.rct_cutoff {
+                return Err(HealthFailure::RepetitionCount { value: sample, run: self.run });
+            }
+        } else {
+            self.last = Some(sample);
+            self.run = 1;
+        }
+
+        // Adaptive proportion test (4.4.2): count the window's first sample
+        match self.apt_value {
+            None => {
+                self.apt_value = Some(sample);
+                self.apt_count = 1;
+                self.apt_seen = 1;
+            }
+            Some(value) => {
+                self.apt_seen += 1;
+                if sample == value {
+                    self.apt_count += 1;
+                    if self.apt_count >= self.apt_cutoff {
+                        return Err(HealthFailure::AdaptiveProportion {
+                            value,
+                            count: self.apt_count,
+                            window: self.apt_window,
+                        });
+                    }
+                }
+                if self.apt_seen == self.apt_window {
+                    self.apt_value = None;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A source together with its health test state
+struct Monitored {
+    source: Box<dyn EntropySource>,
+    tests: HealthTests,
+    failure: Option<HealthFailure>,
+}
+
+impl Monitored {
+    /// Read `buf.len()` bytes, running each through the health tests
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), HealthFailure> {
+        if let Some(failure) = &self.failure {
+            return Err(failure.clone());
+        }
+
+        let result = match self.source.fill(buf) {
+            Ok(()) => buf.iter().try_for_each(|sample| self.tests.test(*sample)),
+            Err(e) => Err(HealthFailure::Unavailable { message: e.to_string() }),
+        };
+        if let Err(failure) = &result {
+            error!("Entropy source {} taken out of service: {}", self.source.name(), failure);
+            self.failure = Some(failure.clone());
+        }
+        result
+    }
+}
+
+/// The set of sources round secrets are drawn from
+pub struct EntropyPool {
+    config: HealthConfig,
+    sources: Vec<Monitored>,
+}
+
+impl EntropyPool {
+    /// A pool without sources; `generate_secret` fails until one is added
+    pub fn new(config: HealthConfig) -> Self {
+        EntropyPool {
+            config,
+            sources: Vec::new(),
+        }
+    }
+
+    /// The OS RNG plus whichever hardware sources the host has
+    pub fn system() -> Self {
+        let mut pool = Self::new(HealthConfig::default()).with_source(Box::new(OsRng));
+        if let Some(hwrng) = HwRng::detect() {
+            pool = pool.with_source(Box::new(hwrng));
+        }
+        if let Some(rdrand) = Rdrand::detect() {
+            pool = pool.with_source(Box::new(rdrand));
+        }
+        pool
+    }
+
+    /// Add a source after running the startup health tests on it
+    ///
+    /// A source that fails them is kept, so the failure shows up in heartbeats.
+    pub fn with_source(mut self, source: Box<dyn EntropySource>) -> Self {
+        let mut monitored = Monitored {
+            tests: HealthTests::new(&self.config),
+            source,
+            failure: None,
+        };
+        if monitored.read(&mut [0u8; STARTUP_SAMPLES]).is_ok() {
+            info!("Using entropy source {}", monitored.source.name());
+        }
+        self.sources.push(monitored);
+        self
+    }
+
+    pub fn config(&self) -> &HealthConfig {
+        &self.config
+    }
+
+    /// Names of all sources, in the order they are mixed
+    pub fn source_names(&self) -> Vec<&str> {
+        self.sources.iter().map(|monitored| monitored.source.name()).collect()
+    }
+
+    /// Sources that failed their health tests, with the reason
+    pub fn failures(&self) -> Vec<(&str, &HealthFailure)> {
+        self.sources
+            .iter()
+            .filter_map(|monitored| monitored.failure.as_ref().map(|failure| (monitored.source.name(), failure)))
+            .collect()
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        !self.sources.is_empty() && self.sources.iter().all(|monitored| monitored.failure.is_none())
+    }
+
+    /// Extract a 32-byte secret from all sources
+    ///
+    /// Fails if there are no sources or any source is failed or fails its health
+    /// tests on the bytes drawn for this secret.
+    pub fn generate_secret(&mut self) -> Result<RoundSecret> {
+        if self.sources.is_empty() {
+            return Err(anyhow::Error::msg("No entropy sources configured"));
+        }
+
+        // Draw from every source even after a failure, so all failures get reported
+        let mut input = Zeroizing::new(Vec::new());
+        let mut failed = Vec::new();
+        for monitored in &mut self.sources {
+            let mut bytes = Zeroizing::new(vec![0u8; self.config.bytes_per_source]);
+            if let Err(failure) = monitored.read(&mut bytes) {
+                failed.push(format!("{}: {}", monitored.source.name(), failure));
+                continue;
+            }
+
+            let name = monitored.source.name().as_bytes();
+            input.extend_from_slice(&(name.len() as u32).to_be_bytes());
+            input.extend_from_slice(name);
+            input.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            input.extend_from_slice(&bytes);
+        }
+
+        if !failed.is_empty() {
+            return Err(anyhow::Error::msg(format!("Entropy sources failed: {}", failed.join("; "))));
+        }
+        Ok(RoundSecret::new(tagged_hash(EXTRACTOR_DOMAIN, &[&input])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo-random bytes that turn into a constant after `good_bytes`
+    struct Degrading {
+        counter: u64,
+        good_bytes: usize,
+        stuck_value: u8,
+    }
+
+    impl EntropySource for Degrading {
+        fn name(&self) -> &str {
+            "degrading"
+        }
+
+        fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+            for byte in buf.iter_mut() {
+                *byte = if self.good_bytes > 0 {
+                    self.good_bytes -= 1;
+                    self.counter += 1;
+                    tagged_hash(b"test", &[&self.counter.to_be_bytes()])[0]
+                } else {
+                    self.stuck_value
+                };
+            }
+            Ok(())
+        }
+    }
+
+    /// Alternates between two values, which passes the repetition count test
+    struct Alternating(bool);
+
+    impl EntropySource for Alternating {
+        fn name(&self) -> &str {
+            "alternating"
+        }
+
+        fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+            for byte in buf.iter_mut() {
+                self.0 = !self.0;
+                *byte = self.0 as u8;
+            }
+            Ok(())
+        }
+    }
+
+    fn config(min_entropy_per_byte: f64) -> HealthConfig {
+        HealthConfig {
+            min_entropy_per_byte,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cutoffs_match_sp800_90b() {
+        // Table 2 of SP 800-90B: W = 512, alpha = 2^-20
+        for (h, cutoff) in [(0.5, 410), (1.0, 311), (2.0, 177), (4.0, 62), (8.0, 13)] {
+            assert_eq!(config(h).apt_cutoff(), cutoff, "H = {}", h);
+        }
+        assert_eq!(config(1.0).rct_cutoff(), 21);
+        assert_eq!(config(4.0).rct_cutoff(), 6);
+    }
+
+    #[test]
+    fn test_generate_secret_mixes_sources() {
+        let mut pool = EntropyPool::system();
+        assert!(pool.is_healthy());
+        assert_eq!(pool.source_names()[0], "os-rng");
+
+        let first = pool.generate_secret().unwrap();
+        assert_ne!(first, pool.generate_secret().unwrap());
+        assert!(EntropyPool::new(HealthConfig::default()).generate_secret().is_err());
+    }
+
+    #[test]
+    fn test_stuck_source_fails_repetition_count() {
+        let degrading = Degrading { counter: 0, good_bytes: STARTUP_SAMPLES + 64, stuck_value: 0 };
+        let mut pool = EntropyPool::new(HealthConfig::default())
+            .with_source(Box::new(OsRng))
+            .with_source(Box::new(degrading));
+        assert!(pool.is_healthy());
+        pool.generate_secret().unwrap();
+
+        let err = pool.generate_secret().unwrap_err();
+        assert!(err.to_string().contains("degrading"));
+        assert!(!pool.is_healthy());
+        assert!(matches!(pool.failures()[..], [("degrading", HealthFailure::RepetitionCount { value: 0, .. })]));
+
+        // The failure persists
+        assert!(pool.generate_secret().is_err());
+    }
+
+    #[test]
+    fn test_biased_source_fails_adaptive_proportion() {
+        let pool = EntropyPool::new(HealthConfig::default()).with_source(Box::new(Alternating(false)));
+        assert!(matches!(pool.failures()[..], [("alternating", HealthFailure::AdaptiveProportion { count: 62, .. })]));
+    }
+
+    #[test]
+    fn test_seed_file() {
+        let path = std::env::temp_dir().join(format!("seed-file-test-{}", std::process::id()));
+        std::fs::write(&path, [7u8; 16]).unwrap();
+        assert!(SeedFile::open(&path).is_err());
+
+        std::fs::write(&path, b"operator supplied seed of at least 32 bytes").unwrap();
+        let mut pool = EntropyPool::new(HealthConfig::default()).with_source(Box::new(SeedFile::open(&path).unwrap()));
+        assert!(pool.is_healthy());
+        assert_ne!(pool.generate_secret().unwrap(), pool.generate_secret().unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
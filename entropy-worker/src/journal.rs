@@ -0,0 +1,234 @@
+//! Write-ahead journal for round secrets.
+//!
+//! Before a worker sends its commitment, the round's secret is written to the
+//! journal and fsynced. If the worker crashes before revealing, it reloads the
+//! entry on restart and can still reveal. Each round is stored in its own file
+//! (`round-<id>.json`); the file is overwritten and removed once the round ends.
+
+use anyhow::Result;
+use entropy_types::crypto::verify_reveal;
+use entropy_types::RoundSecret;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::keystore::{sync_parent_dir, write_private_file};
+
+/// Journal directory used when none is configured
+pub const DEFAULT_JOURNAL_DIR: &str = "worker-journal";
+
+const ENTRY_PREFIX: &str = "round-";
+const ENTRY_EXTENSION: &str = "json";
+
+/// Secret and commitment of a round the worker has committed to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub round_id: u64,
+    #[serde(with = "hex_secret")]
+    pub secret: RoundSecret,
+    #[serde(with = "hex_array")]
+    pub commitment: [u8; 32],
+}
+
+/// Directory of journaled round secrets
+#[derive(Debug)]
+pub struct SecretJournal {
+    dir: PathBuf,
+}
+
+impl SecretJournal {
+    /// Open the journal, creating its directory if needed
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| {
+            anyhow::Error::msg(format!("Failed to create journal directory {}: {}", dir.display(), e))
+        })?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(SecretJournal { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Durably record a round's secret; returns once the entry is on disk
+    pub fn record(&self, entry: &JournalEntry) -> Result<()> {
+        write_private_file(&self.entry_path(entry.round_id), &serde_json::to_vec(entry)?)?;
+        debug!("Journaled secret for round {}", entry.round_id);
+        Ok(())
+    }
+
+    /// All valid entries, ordered by round ID
+    ///
+    /// Unreadable entries and secrets that do not open their commitment are
+    /// skipped with a warning, and leftovers of interrupted writes are removed.
+    pub fn load(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = Vec::new();
+
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            let is_entry = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(ENTRY_PREFIX));
+            if !is_entry {
+                continue;
+            }
+
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some(ENTRY_EXTENSION) => {}
+                Some("tmp") => {
+                    // Never renamed into place, so the commitment was never sent
+                    secure_delete(&path)?;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let entry: JournalEntry = match fs::read(&path).map_err(anyhow::Error::from).and_then(|bytes| {
+                serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+            }) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping unreadable journal entry {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if !verify_reveal(entry.round_id, entry.secret.expose(), &entry.commitment) {
+                warn!("Skipping journal entry {}: secret does not open its commitment", path.display());
+                continue;
+            }
+            entries.push(entry);
+        }
+
+        entries.sort_by_key(|entry| entry.round_id);
+        Ok(entries)
+    }
+
+    /// Securely delete a round's entry; removing a missing entry is not an error
+    pub fn remove(&self, round_id: u64) -> Result<()> {
+        let path = self.entry_path(round_id);
+        if path.exists() {
+            secure_delete(&path)?;
+            debug!("Removed journal entry for round {}", round_id);
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, round_id: u64) -> PathBuf {
+        self.dir.join(format!("{}{}.{}", ENTRY_PREFIX, round_id, ENTRY_EXTENSION))
+    }
+}
+
+/// Overwrite a file with zeros, flush it and unlink it
+///
+/// This keeps the secret out of the file system's live data; it cannot reach
+/// copies left behind by copy-on-write file systems or SSD wear levelling.
+fn secure_delete(path: &Path) -> Result<()> {
+    let length = fs::metadata(path)?.len();
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; length as usize])?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)?;
+    sync_parent_dir(path)
+}
+
+/// Serialize round secrets as hex strings, without leaving copies behind
+mod hex_secret {
+    use entropy_types::RoundSecret;
+    use serde::{Deserializer, Serializer};
+    use zeroize::Zeroizing;
+
+    pub fn serialize<S: Serializer>(secret: &RoundSecret, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&Zeroizing::new(hex::encode(secret.expose())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RoundSecret, D::Error> {
+        let bytes = Zeroizing::new(super::hex_array::deserialize(deserializer)?);
+        Ok(RoundSecret::new(*bytes))
+    }
+}
+
+/// Serialize 32-byte arrays as hex strings
+mod hex_array {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = hex::decode(s).map_err(serde::de::Error::custom)?;
+        bytes.try_into().map_err(|_| serde::de::Error::custom("expected 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{compute_commitment, generate_secret};
+
+    fn test_journal(name: &str) -> SecretJournal {
+        let dir = std::env::temp_dir().join(format!("journal-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        SecretJournal::open(dir).unwrap()
+    }
+
+    fn entry(round_id: u64) -> JournalEntry {
+        let secret = generate_secret().unwrap();
+        JournalEntry {
+            round_id,
+            commitment: compute_commitment(round_id, secret.expose()),
+            secret,
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let journal = test_journal("roundtrip");
+        let (first, second) = (entry(3), entry(1));
+        journal.record(&first).unwrap();
+        journal.record(&second).unwrap();
+
+        // A reopened journal sees the same entries, ordered by round
+        let reopened = SecretJournal::open(journal.dir()).unwrap();
+        assert_eq!(reopened.load().unwrap(), vec![second, first]);
+    }
+
+    #[test]
+    fn test_remove_deletes_entry() {
+        let journal = test_journal("remove");
+        journal.record(&entry(1)).unwrap();
+        journal.record(&entry(2)).unwrap();
+
+        journal.remove(1).unwrap();
+        journal.remove(1).unwrap();
+        assert_eq!(journal.load().unwrap().iter().map(|e| e.round_id).collect::<Vec<_>>(), vec![2]);
+        assert!(!journal.entry_path(1).exists());
+    }
+
+    #[test]
+    fn test_invalid_entries_are_skipped() {
+        let journal = test_journal("invalid");
+        journal.record(&entry(1)).unwrap();
+
+        // A secret that does not match its commitment, a truncated file and an unfinished write
+        let mut mismatched = entry(2);
+        mismatched.secret = [0u8; 32].into();
+        journal.record(&mismatched).unwrap();
+        fs::write(journal.dir().join("round-3.json"), "{\"round_id\":").unwrap();
+        fs::write(journal.dir().join("round-4.tmp"), "partial").unwrap();
+
+        assert_eq!(journal.load().unwrap().iter().map(|e| e.round_id).collect::<Vec<_>>(), vec![1]);
+        assert!(!journal.dir().join("round-4.tmp").exists());
+    }
+}
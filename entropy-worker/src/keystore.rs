@@ -0,0 +1,451 @@
+//! Persistent worker identity.
+//!
+//! The worker's secp256k1 key is stored in a JSON keystore file, encrypted with
+//! ChaCha20-Poly1305 under a key derived from a passphrase with PBKDF2-HMAC-SHA256.
+//! The public key and node ID are stored in the clear so they can be shown and
+//! registered with the aggregator without unlocking the keystore; they are bound
+//! to the ciphertext as associated data and checked again after decryption.
+//!
+//! A node's ID is derived from its first key and kept when the key is rotated.
+//! Version 1 keystores only bind the public key and require the derived node ID;
+//! they can still be loaded, but saving always writes version 2.
+
+use anyhow::Result;
+use entropy_types::crypto::node_id_from_public_key;
+use entropy_types::NodeId;
+use getrandom::getrandom;
+use log::info;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::crypto::generate_keypair;
+
+/// Keystore location used when none is configured
+pub const DEFAULT_KEYSTORE_PATH: &str = "worker-keystore.json";
+
+/// Environment variable the passphrase is read from, if set
+pub const PASSPHRASE_ENV: &str = "ENTROPY_WORKER_PASSPHRASE";
+
+/// Version of the keystore file format
+pub const KEYSTORE_VERSION: u32 = 2;
+
+/// PBKDF2 iterations for newly written keystores
+pub const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+const KDF_ALGORITHM: &str = "pbkdf2-hmac-sha256";
+const CIPHER_ALGORITHM: &str = "chacha20-poly1305";
+const SALT_LEN: usize = 16;
+
+/// The worker's long-term signing identity
+pub struct Identity {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    node_id: NodeId,
+}
+
+impl Identity {
+    /// Generate a fresh identity from the OS RNG
+    pub fn generate() -> Result<Self> {
+        let (secret_key, _) = generate_keypair()?;
+        Ok(Self::from_secret_key(secret_key))
+    }
+
+    pub fn from_secret_key(secret_key: SecretKey) -> Self {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        let node_id = node_id_from_public_key(&public_key);
+        Self::with_node_id(secret_key, node_id)
+    }
+
+    fn with_node_id(secret_key: SecretKey, node_id: NodeId) -> Self {
+        Identity {
+            public_key: PublicKey::from_secret_key(&Secp256k1::new(), &secret_key),
+            secret_key,
+            node_id,
+        }
+    }
+
+    /// Generate a new key for the same node, to rotate to
+    pub fn rotated(&self) -> Result<Self> {
+        let (secret_key, _) = generate_keypair()?;
+        Ok(Self::with_node_id(secret_key, self.node_id.clone()))
+    }
+
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Node ID, derived from the node's first public key
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+}
+
+impl Drop for Identity {
+    fn drop(&mut self) {
+        self.secret_key.non_secure_erase();
+    }
+}
+
+/// On-disk layout of the keystore file
+#[derive(Serialize, Deserialize, Debug)]
+struct KeystoreFile {
+    version: u32,
+    node_id: NodeId,
+    /// Compressed public key, hex encoded
+    public_key: String,
+    kdf: KdfParams,
+    cipher: CipherParams,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct KdfParams {
+    algorithm: String,
+    iterations: u32,
+    salt: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CipherParams {
+    algorithm: String,
+    nonce: String,
+    /// Encrypted secret key followed by the authentication tag, hex encoded
+    ciphertext: String,
+}
+
+/// Passphrase-encrypted key file
+pub struct Keystore {
+    path: PathBuf,
+    kdf_iterations: u32,
+}
+
+impl Keystore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Keystore {
+            path: path.into(),
+            kdf_iterations: DEFAULT_KDF_ITERATIONS,
+        }
+    }
+
+    /// Use a different PBKDF2 iteration count when writing the keystore
+    pub fn with_kdf_iterations(mut self, iterations: u32) -> Self {
+        self.kdf_iterations = iterations;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Generate a new identity and save it, refusing to overwrite an existing keystore
+    pub fn create(&self, passphrase: &str) -> Result<Identity> {
+        if self.exists() {
+            return Err(anyhow::Error::msg(format!(
+                "Keystore {} already exists", self.path.display()
+            )));
+        }
+
+        let identity = Identity::generate()?;
+        self.save(&identity, passphrase)?;
+        Ok(identity)
+    }
+
+    /// Encrypt the identity under the passphrase and write it to disk
+    ///
+    /// The file is written to a temporary path, synced and renamed into place, so
+    /// an interrupted write never leaves a truncated keystore behind.
+    pub fn save(&self, identity: &Identity, passphrase: &str) -> Result<()> {
+        if passphrase.is_empty() {
+            return Err(anyhow::Error::msg("Keystore passphrase must not be empty"));
+        }
+        let iterations = NonZeroU32::new(self.kdf_iterations)
+            .ok_or_else(|| anyhow::Error::msg("KDF iteration count must be positive"))?;
+
+        let mut salt = [0u8; SALT_LEN];
+        getrandom(&mut salt)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom(&mut nonce)?;
+
+        let public_key = identity.public_key.serialize();
+        let key = derive_key(passphrase, &salt, iterations)?;
+        let mut ciphertext = identity.secret_key.secret_bytes().to_vec();
+        let aad = associated_data(KEYSTORE_VERSION, &identity.node_id, &identity.public_key);
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(&aad), &mut ciphertext)
+            .map_err(|_| anyhow::Error::msg("Failed to encrypt secret key"))?;
+
+        let file = KeystoreFile {
+            version: KEYSTORE_VERSION,
+            node_id: identity.node_id.clone(),
+            public_key: hex::encode(public_key),
+            kdf: KdfParams {
+                algorithm: KDF_ALGORITHM.to_string(),
+                iterations: iterations.get(),
+                salt: hex::encode(salt),
+            },
+            cipher: CipherParams {
+                algorithm: CIPHER_ALGORITHM.to_string(),
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(&ciphertext),
+            },
+        };
+
+        write_private_file(&self.path, serde_json::to_string_pretty(&file)?.as_bytes())?;
+        info!("Saved identity {} to {}", identity.node_id, self.path.display());
+        Ok(())
+    }
+
+    /// Decrypt the identity with the passphrase
+    pub fn load(&self, passphrase: &str) -> Result<Identity> {
+        let file = self.read_file()?;
+        let public_key = parse_public_key(&file)?;
+
+        if file.kdf.algorithm != KDF_ALGORITHM || file.cipher.algorithm != CIPHER_ALGORITHM {
+            return Err(anyhow::Error::msg(format!(
+                "Unsupported keystore algorithms {} / {}", file.kdf.algorithm, file.cipher.algorithm
+            )));
+        }
+        let iterations = NonZeroU32::new(file.kdf.iterations)
+            .ok_or_else(|| anyhow::Error::msg("Keystore has an invalid KDF iteration count"))?;
+        let salt = hex::decode(&file.kdf.salt)?;
+        let nonce: [u8; NONCE_LEN] = hex::decode(&file.cipher.nonce)?
+            .try_into()
+            .map_err(|_| anyhow::Error::msg("Keystore has an invalid nonce"))?;
+        let mut ciphertext = Zeroizing::new(hex::decode(&file.cipher.ciphertext)?);
+
+        let key = derive_key(passphrase, &salt, iterations)?;
+        let aad = associated_data(file.version, &file.node_id, &public_key);
+        let plaintext = key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(&aad), &mut ciphertext)
+            .map_err(|_| anyhow::Error::msg("Failed to decrypt keystore: wrong passphrase or corrupted file"))?;
+
+        let identity = Identity::with_node_id(SecretKey::from_slice(plaintext)?, file.node_id);
+        if identity.public_key != public_key {
+            return Err(anyhow::Error::msg("Keystore secret key does not match its public key"));
+        }
+
+        info!("Loaded identity {} from {}", identity.node_id, self.path.display());
+        Ok(identity)
+    }
+
+    /// Read the node ID and public key without unlocking the keystore
+    pub fn read_public(&self) -> Result<(NodeId, PublicKey)> {
+        let file = self.read_file()?;
+        let public_key = parse_public_key(&file)?;
+        Ok((file.node_id, public_key))
+    }
+
+    fn read_file(&self) -> Result<KeystoreFile> {
+        let json = fs::read_to_string(&self.path).map_err(|e| {
+            anyhow::Error::msg(format!("Failed to read keystore {}: {}", self.path.display(), e))
+        })?;
+        let file: KeystoreFile = serde_json::from_str(&json).map_err(|e| {
+            anyhow::Error::msg(format!("Invalid keystore {}: {}", self.path.display(), e))
+        })?;
+
+        if !(1..=KEYSTORE_VERSION).contains(&file.version) {
+            return Err(anyhow::Error::msg(format!(
+                "Unsupported keystore version {} (expected at most {})", file.version, KEYSTORE_VERSION
+            )));
+        }
+        Ok(file)
+    }
+}
+
+/// Read the keystore passphrase from the environment, or from the first line of stdin
+pub fn read_passphrase() -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    eprint!("Keystore passphrase: ");
+    std::io::stderr().flush()?;
+    let mut line = Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut line)?;
+    Ok(Zeroizing::new(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> Result<LessSafeKey> {
+    let mut key_bytes = Zeroizing::new([0u8; 32]);
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), key_bytes.as_mut());
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key_bytes.as_ref())
+        .map_err(|_| anyhow::Error::msg("Failed to create keystore cipher"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Data the ciphertext is bound to: the public key, and from version 2 the node ID
+fn associated_data(version: u32, node_id: &str, public_key: &PublicKey) -> Vec<u8> {
+    let mut aad = Vec::new();
+    if version >= 2 {
+        aad.extend_from_slice(&(node_id.len() as u32).to_be_bytes());
+        aad.extend_from_slice(node_id.as_bytes());
+    }
+    aad.extend_from_slice(&public_key.serialize());
+    aad
+}
+
+fn parse_public_key(file: &KeystoreFile) -> Result<PublicKey> {
+    let public_key = PublicKey::from_slice(&hex::decode(&file.public_key)?)?;
+    // Version 1 does not bind the node ID, so it must be the derived one
+    if file.version == 1 && node_id_from_public_key(&public_key) != file.node_id {
+        return Err(anyhow::Error::msg(format!(
+            "Keystore node ID {} does not match its public key", file.node_id
+        )));
+    }
+    Ok(public_key)
+}
+
+/// Atomically and durably write a file readable only by the current user
+pub(crate) fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Flush directory entries so a rename or removal survives a crash
+pub(crate) fn sync_parent_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_keystore(name: &str) -> Keystore {
+        let dir = std::env::temp_dir().join(format!("keystore-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Keystore::new(dir.join("keystore.json")).with_kdf_iterations(1_000)
+    }
+
+    #[test]
+    fn test_create_and_load() {
+        let keystore = test_keystore("roundtrip");
+        let created = keystore.create("correct horse").unwrap();
+
+        let loaded = keystore.load("correct horse").unwrap();
+        assert_eq!(loaded.secret_key(), created.secret_key());
+        assert_eq!(loaded.node_id(), created.node_id());
+        assert_eq!(loaded.node_id(), node_id_from_public_key(created.public_key()));
+
+        // The public half is readable without the passphrase
+        let (node_id, public_key) = keystore.read_public().unwrap();
+        assert_eq!(node_id, created.node_id());
+        assert_eq!(&public_key, created.public_key());
+
+        // The secret key is not stored in the clear
+        let contents = fs::read_to_string(keystore.path()).unwrap();
+        assert!(!contents.contains(&hex::encode(created.secret_key().secret_bytes())));
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let keystore = test_keystore("passphrase");
+        keystore.create("correct horse").unwrap();
+
+        assert!(keystore.load("battery staple").is_err());
+    }
+
+    #[test]
+    fn test_create_does_not_overwrite() {
+        let keystore = test_keystore("overwrite");
+        let created = keystore.create("correct horse").unwrap();
+
+        assert!(keystore.create("correct horse").is_err());
+        assert_eq!(keystore.load("correct horse").unwrap().node_id(), created.node_id());
+    }
+
+    #[test]
+    fn test_tampered_public_key_is_rejected() {
+        let keystore = test_keystore("tamper");
+        keystore.create("correct horse").unwrap();
+
+        // Swap in another key and matching node ID; decryption must fail on the associated data
+        let other = Identity::generate().unwrap();
+        let mut file: KeystoreFile = serde_json::from_str(&fs::read_to_string(keystore.path()).unwrap()).unwrap();
+        file.public_key = hex::encode(other.public_key().serialize());
+        file.node_id = other.node_id().to_string();
+        fs::write(keystore.path(), serde_json::to_string(&file).unwrap()).unwrap();
+
+        assert!(keystore.load("correct horse").is_err());
+    }
+
+    #[test]
+    fn test_rotated_identity_keeps_node_id() {
+        let keystore = test_keystore("rotated");
+        let original = keystore.create("correct horse").unwrap();
+        let rotated = original.rotated().unwrap();
+        assert_eq!(rotated.node_id(), original.node_id());
+        assert_ne!(rotated.public_key(), original.public_key());
+
+        keystore.save(&rotated, "correct horse").unwrap();
+        let loaded = keystore.load("correct horse").unwrap();
+        assert_eq!(loaded.node_id(), original.node_id());
+        assert_eq!(loaded.secret_key(), rotated.secret_key());
+
+        // The node ID is bound to the ciphertext
+        let mut file: KeystoreFile = serde_json::from_str(&fs::read_to_string(keystore.path()).unwrap()).unwrap();
+        file.node_id = Identity::generate().unwrap().node_id().to_string();
+        fs::write(keystore.path(), serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(keystore.load("correct horse").is_err());
+    }
+
+    #[test]
+    fn test_loads_version_1_keystore() {
+        let keystore = test_keystore("v1");
+        let identity = keystore.create("correct horse").unwrap();
+
+        // Re-encrypt the way version 1 did, binding only the public key
+        let mut file: KeystoreFile = serde_json::from_str(&fs::read_to_string(keystore.path()).unwrap()).unwrap();
+        let iterations = NonZeroU32::new(file.kdf.iterations).unwrap();
+        let key = derive_key("correct horse", &hex::decode(&file.kdf.salt).unwrap(), iterations).unwrap();
+        let nonce: [u8; NONCE_LEN] = hex::decode(&file.cipher.nonce).unwrap().try_into().unwrap();
+        let mut ciphertext = identity.secret_key().secret_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&identity.public_key().serialize()),
+            &mut ciphertext,
+        )
+        .unwrap();
+        file.version = 1;
+        file.cipher.ciphertext = hex::encode(&ciphertext);
+        fs::write(keystore.path(), serde_json::to_string(&file).unwrap()).unwrap();
+
+        assert_eq!(keystore.load("correct horse").unwrap().secret_key(), identity.secret_key());
+    }
+}
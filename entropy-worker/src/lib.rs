@@ -1,7 +1,18 @@
 pub mod worker;
+pub mod config;
 pub mod crypto;
+pub mod entropy;
+pub mod journal;
+pub mod keystore;
 pub mod network;
+pub mod registration;
+pub mod service;
 
 // Re-export important items for external use
-pub use worker::Worker;
+pub use worker::{Worker, WorkerConfig};
+pub use config::WorkerSettings;
+pub use entropy::{EntropyPool, EntropySource, HealthConfig};
+pub use journal::SecretJournal;
+pub use keystore::{Identity, Keystore};
+pub use service::{ServiceConfig, WorkerService};
 pub use crypto::{generate_secret, compute_commitment, create_commitment_payload, generate_keypair, sign_commitment};
\ No newline at end of file
@@ -1,108 +1,437 @@
 use log::{info, debug, error};
 use env_logger::Env;
-use entropy_types::StartCommitmentMsg;
-use std::env;
+use entropy_types::RegistrationStatus;
+use entropy_types::bundle::OfflineBundle;
+use clap::{Parser, Subcommand};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
 
-mod crypto;
-mod worker;
-mod network;
+use entropy_worker::config::{WorkerSettings, DEFAULT_CONFIG_PATH};
+use entropy_worker::entropy::{EntropyPool, SeedFile};
+use entropy_worker::journal::SecretJournal;
+use entropy_worker::keystore::{read_passphrase, Identity, Keystore};
+use entropy_worker::registration;
+use entropy_worker::worker::Worker;
+use entropy_worker::network::TcpClient;
+use entropy_worker::service::{ServiceConfig, WorkerService};
 
-use crate::worker::Worker;
-use crate::network::TcpClient;
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// TOML config file; any setting can be overridden with ENTROPY_WORKER_* variables
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Connect to the aggregator and take part in rounds (the default)
+    Run,
+
+    /// Take part in a round without a connection, exchanging bundle files with the aggregator
+    #[command(subcommand)]
+    Offline(OfflineCommand),
+
+    /// Create the worker's identity and store it in the keystore
+    Keygen {
+        /// Replace an existing keystore
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Register the worker's public key with the aggregator, proving possession of the key
+    Register {
+        /// Operator information stored with the registration, as key=value (repeatable)
+        #[arg(long = "metadata", value_parser = parse_metadata)]
+        metadata: Vec<(String, String)>,
+    },
+
+    /// Replace the worker's key with a new one, keeping its node ID
+    RotateKey,
+
+    /// Show the configuration, identity, pending rounds and entropy sources
+    Status,
+
+    /// Print the node ID and public key to add to the committee file
+    ShowPubkey,
+}
+
+#[derive(Subcommand, Debug)]
+enum OfflineCommand {
+    /// Commit to a round and write the signed commitment bundle
+    Commit {
+        /// Round to commit to
+        #[arg(long)]
+        round_id: u64,
+
+        /// Bundle file to write; defaults to <node-id>-round-<id>-commitment.json
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Write the signed reveal bundle for a round committed to offline
+    Reveal {
+        /// Round to reveal
+        #[arg(long)]
+        round_id: u64,
+
+        /// Bundle file to write; defaults to <node-id>-round-<id>-reveal.json
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    
+    // An explicitly given config file must exist; the default one is optional
+    let config_path = args.config.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+    let settings = WorkerSettings::load(&config_path, args.config.is_some())?;
     
+    // RUST_LOG still takes precedence over the configured level
+    env_logger::Builder::from_env(Env::default().default_filter_or(&settings.log_level)).init();
+    
+    let keystore = Keystore::new(&settings.keystore);
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => run(&settings, &keystore).await,
+        Command::Offline(command) => offline(&settings, &keystore, command),
+        Command::Keygen { force } => keygen(&keystore, force),
+        Command::Register { metadata } => register(&settings, &keystore, metadata.into_iter().collect()).await,
+        Command::RotateKey => rotate_key(&settings, &keystore).await,
+        Command::Status => status(&settings, &keystore, &config_path),
+        Command::ShowPubkey => show_pubkey(&keystore),
+    }
+}
+
+/// Take part in rounds until Ctrl+C or until the aggregator cannot be reached
+async fn run(settings: &WorkerSettings, keystore: &Keystore) -> Result<(), Box<dyn std::error::Error>> {
     info!("Entropy Worker Node starting...");
     
-    // Parse command line arguments
-    let args: Vec<String> = env::args().collect();
-    let offline_mode = args.iter().any(|arg| arg == "--mode=offline" || arg == "--offline");
-    
-    // Initialize worker with a default node ID
-    let node_id = format!("worker-{}", rand::random::<u64>());
-    let mut worker = Worker::new(node_id)?;
-    
-    if offline_mode {
-        info!("Running in offline mode - generating commitment without network connection");
-        
-        // Simulate receiving a start commitment message
-        let start_msg = StartCommitmentMsg {
-            round_id: 1,
-            committee: vec![worker.get_node_id().to_string()],
-        };
-        
-        // Generate commitment payload
-        let payload = worker.handle_start_commitment(&start_msg)?;
-        
-        info!("Generated commitment payload for round {}: {:?}", payload.round_id, hex::encode(&payload.commitment));
-        info!("Signature: {}", hex::encode(&payload.signature));
-        
-        // In offline mode, just display the results and exit
-        println!("Commitment generated successfully:");
-        println!("  Round ID: {}", payload.round_id);
-        println!("  Commitment: {}", hex::encode(&payload.commitment));
-        println!("  Signature: {}", hex::encode(&payload.signature));
-        
-        return Ok(());
-    }
-    
-    // Normal mode - connect to aggregator and participate in protocol
+    // Round messages are only answered if signed by a pinned aggregator key
+    let aggregator_keys = settings.aggregator_keys();
+    if aggregator_keys.is_empty() {
+        error!("No aggregator key pinned; set aggregator.public_keys to the key the aggregator prints at startup");
+        return Err("aggregator.public_keys is empty".into());
+    }
+    
+    // Initialize worker with its persistent identity
+    if !keystore.exists() {
+        error!("No keystore at {}; run `entropy-worker keygen` first", keystore.path().display());
+        return Err(format!("keystore {} not found", keystore.path().display()).into());
+    }
+    let worker = Worker::from_identity(&load_identity(settings, keystore, &read_passphrase()?)?)
+        .with_entropy(entropy_pool(settings)?);
+    if !worker.entropy().is_healthy() {
+        error!("Entropy sources failed their startup health tests, the worker will not commit to rounds");
+    }
+    info!("Drawing round secrets from {}", worker.entropy().source_names().join(", "));
     debug!("Worker node initialized with ID: {}", worker.get_node_id());
     
+    // Keep round secrets on disk so a restart can still reveal
+    let worker = worker.with_journal(SecretJournal::open(&settings.journal_dir)?)?;
+    
     // Initialize TCP client to connect to aggregator
-    let mut tcp_client = TcpClient::new("localhost:900");
-    
-    // Attempt to connect to aggregator
-    match tcp_client.connect() {
-        Ok(()) => info!("Successfully connected to aggregator"),
-        Err(e) => {
-            error!("Failed to connect to aggregator: {}", e);
-            return Err(e.into());
+    let client_config = settings.client_config(worker.get_node_id().to_string());
+    info!("Connecting to aggregator at {}", client_config.endpoints.join(", then "));
+    let tcp_client = TcpClient::with_config(client_config);
+    
+    // The client keeps the connection alive and reconnects until shutdown
+    let shutdown = CancellationToken::new();
+    let client = tcp_client.spawn(shutdown.clone());
+    let mut client_task = client.task;
+    
+    // Answer start commitment and start reveal messages until shutdown
+    let service_config = ServiceConfig {
+        aggregator_keys,
+        ..Default::default()
+    };
+    let service = WorkerService::new(worker, service_config);
+    let service_handle = tokio::spawn(service.run(client.inbound, client.outbound));
+    
+    info!("Press Ctrl+C to shutdown gracefully...");
+    tokio::select! {
+        _ = signal::ctrl_c() => {
+            info!("Received shutdown signal, cleaning up...");
+        }
+        result = &mut client_task => {
+            // The client only stops on its own when it gives up on every aggregator
+            let _ = service_handle.await;
+            return match result? {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    error!("Connection to aggregator failed: {}", e);
+                    Err(e.into())
+                }
+            };
+        }
+    }
+    
+    // Close the connection; the inbound stream ends with it, which stops the
+    // protocol loop. Open rounds stay in the journal for the next start
+    shutdown.cancel();
+    if let Err(e) = client_task.await? {
+        error!("Connection to aggregator failed: {}", e);
+    }
+    service_handle.await??;
+    
+    info!("Worker shutdown complete");
+    
+    Ok(())
+}
+
+/// Export a commitment or reveal bundle for the aggregator to import
+///
+/// The round's secret stays in the journal between the two, so the reveal can be
+/// exported by a later run. Once the reveal bundle is written the secret is deleted.
+fn offline(settings: &WorkerSettings, keystore: &Keystore, command: OfflineCommand) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Running in offline mode - exchanging bundle files instead of connecting to the aggregator");
+    
+    let worker = Worker::from_identity(&load_identity(settings, keystore, &read_passphrase()?)?)
+        .with_entropy(entropy_pool(settings)?);
+    let mut worker = worker.with_journal(SecretJournal::open(&settings.journal_dir)?)?;
+    
+    match command {
+        OfflineCommand::Commit { round_id, out } => {
+            let bundle = worker.export_commitment(round_id)?;
+            let path = out.unwrap_or_else(|| bundle_path(worker.get_node_id(), round_id, "commitment"));
+            if let Err(e) = write_bundle(&path, &bundle) {
+                // Nobody has seen the commitment, so the round can be committed to again
+                worker.finish_round(round_id);
+                return Err(e);
+            }
+            
+            println!("Commitment bundle written to {}", path.display());
+            println!("  Round ID: {}", round_id);
+            println!("  Node ID: {}", bundle.node_id);
+            println!("Keep {} until the reveal is exported", settings.journal_dir.display());
+        }
+        OfflineCommand::Reveal { round_id, out } => {
+            let bundle = worker.export_reveal(round_id)?;
+            let path = out.unwrap_or_else(|| bundle_path(worker.get_node_id(), round_id, "reveal"));
+            write_bundle(&path, &bundle)?;
+            worker.finish_round(round_id);
+            
+            println!("Reveal bundle written to {}", path.display());
+            println!("  Round ID: {}", round_id);
+            println!("  Node ID: {}", bundle.node_id);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Default file name of a bundle
+fn bundle_path(node_id: &str, round_id: u64, kind: &str) -> PathBuf {
+    PathBuf::from(format!("{}-round-{}-{}.json", node_id, round_id, kind))
+}
+
+/// Write a bundle to a new file, refusing to replace an existing one
+fn write_bundle(path: &Path, bundle: &OfflineBundle) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| format!("cannot create bundle file {}: {}", path.display(), e))?;
+    file.write_all(&serde_json::to_vec_pretty(bundle)?)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Register the identity with the first reachable aggregator and print its answer
+async fn register(
+    settings: &WorkerSettings,
+    keystore: &Keystore,
+    metadata: BTreeMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let identity = load_identity(settings, keystore, &read_passphrase()?)?;
+    
+    let client_config = settings.client_config(identity.node_id().to_string());
+    info!("Registering with aggregator at {}", client_config.endpoints.join(", then "));
+    let shutdown = CancellationToken::new();
+    let mut client = TcpClient::with_config(client_config).spawn(shutdown.clone());
+    
+    let result = tokio::select! {
+        result = registration::register(&identity, metadata, &mut client.inbound, &client.outbound) => result,
+        result = &mut client.task => Err(gave_up(result?)),
+    };
+    shutdown.cancel();
+    let result = result?;
+    
+    println!("Node ID: {}", result.node_id);
+    match result.status {
+        RegistrationStatus::Approved => println!("Registration approved, the worker is a committee member"),
+        RegistrationStatus::Pending => println!("Registration received, waiting for an operator to approve it"),
+        RegistrationStatus::Rejected => return Err(format!("registration rejected: {}", result.reason).into()),
+    }
+    
+    Ok(())
+}
+
+/// Rotate to a new key, replacing the keystore once the aggregator accepts the rotation
+///
+/// The new key is kept in a separate keystore until then, so an interrupted rotation
+/// can be retried with the same key.
+async fn rotate_key(settings: &WorkerSettings, keystore: &Keystore) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = read_passphrase()?;
+    let identity = load_identity(settings, keystore, &passphrase)?;
+    
+    let next = Keystore::new(keystore.path().with_extension("next.json"));
+    let new_identity = if next.exists() {
+        let new_identity = next.load(&passphrase)?;
+        if new_identity.node_id() != identity.node_id() {
+            return Err(format!("{} belongs to node {}", next.path().display(), new_identity.node_id()).into());
         }
+        info!("Resuming key rotation with the key in {}", next.path().display());
+        new_identity
+    } else {
+        let new_identity = identity.rotated()?;
+        next.save(&new_identity, &passphrase)?;
+        new_identity
+    };
+    
+    let client_config = settings.client_config(identity.node_id().to_string());
+    info!("Rotating key with aggregator at {}", client_config.endpoints.join(", then "));
+    let shutdown = CancellationToken::new();
+    let mut client = TcpClient::with_config(client_config).spawn(shutdown.clone());
+    
+    let result = tokio::select! {
+        result = registration::rotate_key(&identity, &new_identity, &mut client.inbound, &client.outbound) => result,
+        result = &mut client.task => Err(gave_up(result?)),
+    };
+    shutdown.cancel();
+    let result = result.map_err(|e| format!("{}; run rotate-key again to retry with the same key", e))?;
+    
+    if !result.accepted {
+        std::fs::remove_file(next.path())?;
+        return Err(format!("key rotation rejected: {}", result.reason).into());
     }
+    std::fs::rename(next.path(), keystore.path())?;
     
-    // Simulate receiving a start commitment message
-    // In a real implementation, this would come from the aggregator via network
-    let start_msg = StartCommitmentMsg {
-        round_id: 1,
-        committee: vec![worker.get_node_id().to_string()],
+    println!("Key rotation accepted for node {}", result.node_id);
+    println!("  New public key: {}", hex::encode(new_identity.public_key().serialize()));
+    println!("The new key is used from the aggregator's next round; restart the worker to sign with it");
+    
+    Ok(())
+}
+
+/// Error for a client task that stopped before the exchange finished
+///
+/// The client only stops on its own when it gives up on every aggregator.
+fn gave_up(result: anyhow::Result<()>) -> anyhow::Error {
+    result.err().unwrap_or_else(|| anyhow::Error::msg("Connection to aggregator closed"))
+}
+
+/// Parse a `key=value` metadata entry
+fn parse_metadata(entry: &str) -> Result<(String, String), String> {
+    match entry.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got {}", entry)),
+    }
+}
+
+/// Generate a new identity and store it in the keystore
+fn keygen(keystore: &Keystore, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = read_passphrase()?;
+    let identity = if force {
+        let identity = Identity::generate()?;
+        keystore.save(&identity, &passphrase)?;
+        identity
+    } else {
+        keystore.create(&passphrase)?
     };
     
-    // Handle the start commitment message and generate payload
-    let payload = worker.handle_start_commitment(&start_msg)?;
+    println!("Generated new worker identity in {}", keystore.path().display());
+    println!("  Node ID: {}", identity.node_id());
+    println!("  Public key: {}", hex::encode(identity.public_key().serialize()));
+    
+    Ok(())
+}
+
+/// Print what the worker would run with, without unlocking the keystore
+fn status(settings: &WorkerSettings, keystore: &Keystore, config_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if config_path.exists() {
+        println!("Config: {}", config_path.display());
+    } else {
+        println!("Config: defaults ({} not found)", config_path.display());
+    }
+    println!("  Aggregator endpoints: {}", settings.aggregator.endpoints.join(", "));
+    if settings.aggregator.public_keys.is_empty() {
+        println!("  Aggregator keys: none pinned; `run` needs aggregator.public_keys");
+    } else {
+        println!("  Aggregator keys: {}", settings.aggregator.public_keys.join(", "));
+    }
+    println!("  Log level: {}", settings.log_level);
     
-    // Send the commitment to the aggregator
-    match tcp_client.send_commitment(&payload) {
-        Ok(()) => info!("Successfully sent commitment to aggregator for round {}", payload.round_id),
-        Err(e) => {
-            error!("Failed to send commitment to aggregator: {}", e);
-            return Err(e.into());
+    println!("Keystore: {}", keystore.path().display());
+    if keystore.exists() {
+        let (node_id, public_key) = keystore.read_public()?;
+        println!("  Node ID: {}", node_id);
+        println!("  Public key: {}", hex::encode(public_key.serialize()));
+        if let Some(expected) = settings.node_id.as_ref().filter(|expected| **expected != node_id) {
+            println!("  Warning: the config expects node ID {}", expected);
         }
+    } else {
+        println!("  Not found; run `entropy-worker keygen`");
     }
     
-    info!("Commitment phase completed successfully");
+    println!("Journal: {}", settings.journal_dir.display());
+    let pending: Vec<String> = if settings.journal_dir.exists() {
+        SecretJournal::open(&settings.journal_dir)?
+            .load()?
+            .iter()
+            .map(|entry| entry.round_id.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    println!("  Pending rounds: {}", if pending.is_empty() { "none".to_string() } else { pending.join(", ") });
     
-    // Wait for shutdown signal
-    info!("Press Ctrl+C to shutdown gracefully...");
-    signal::ctrl_c().await?;
-    info!("Received shutdown signal, cleaning up...");
+    let entropy = entropy_pool(settings)?;
+    println!("Entropy sources: {}", entropy.source_names().join(", "));
+    for (source, failure) in entropy.failures() {
+        println!("  {} failed: {}", source, failure);
+    }
     
-    // Perform cleanup - reset worker state
-    worker.reset_state();
+    Ok(())
+}
+
+/// Print the node ID and public key to add to the committee file
+fn show_pubkey(keystore: &Keystore) -> Result<(), Box<dyn std::error::Error>> {
+    let (node_id, public_key) = keystore.read_public()?;
     
-    info!("Worker shutdown complete");
+    println!("Node ID: {}", node_id);
+    println!("Public key: {}", hex::encode(public_key.serialize()));
     
     Ok(())
 }
 
-// Helper function to generate a mock start commitment message for testing
-#[cfg(test)]
-fn create_mock_start_commitment_msg(round_id: u64, node_id: &str) -> StartCommitmentMsg {
-    StartCommitmentMsg {
-        round_id,
-        committee: vec![node_id.to_string()],
+/// Unlock the keystore, checking the identity against the configured node ID
+fn load_identity(settings: &WorkerSettings, keystore: &Keystore, passphrase: &str) -> Result<Identity, Box<dyn std::error::Error>> {
+    let identity = keystore.load(passphrase)?;
+    if let Some(node_id) = &settings.node_id {
+        if identity.node_id() != node_id {
+            return Err(format!(
+                "keystore {} holds node {}, but the config expects {}",
+                keystore.path().display(), identity.node_id(), node_id
+            ).into());
+        }
+    }
+    Ok(identity)
+}
+
+/// The system's entropy sources plus the configured seed file
+fn entropy_pool(settings: &WorkerSettings) -> Result<EntropyPool, Box<dyn std::error::Error>> {
+    let mut entropy = EntropyPool::system();
+    if let Some(path) = &settings.seed_file {
+        entropy = entropy.with_source(Box::new(SeedFile::open(path)?));
     }
-}
\ No newline at end of file
+    Ok(entropy)
+}
@@ -1,300 +1,554 @@
 use anyhow::Result;
-use entropy_types::CommitmentPayload;
+use entropy_types::protocol::{SUPPORTED_CAPABILITIES, SUPPORTED_PROTOCOL_VERSIONS};
+use entropy_types::{
+    ErrorMessage, HelloAckMsg, HelloMsg, NodeId, ProtocolCodec, ProtocolEnvelope, ProtocolMessage,
+};
+use futures::{SinkExt, StreamExt};
 use log::{info, debug, warn, error};
-use std::io::Write;
-use std::net::{TcpStream};
-use std::time::Duration;
-use serde_json;
-use std::thread;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
 
-/// TCP client wrapper for communication with the aggregator
+/// Framed connection to the aggregator
+type Connection = Framed<TcpStream, ProtocolCodec>;
+
+/// Flush and shut down a connection, ignoring errors since it is being dropped anyway
+async fn close(connection: &mut Connection) {
+    let _ = SinkExt::<ProtocolEnvelope>::close(connection).await;
+}
+
+/// Reconnect policy with jittered exponential backoff
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnect attempt
+    pub base_delay: Duration,
+
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+
+    /// Give up after this many consecutive failed attempts (`None` retries forever)
+    pub max_attempts: Option<u32>,
+
+    /// Fraction of each delay that is randomized, between 0.0 and 1.0
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before reconnect attempt `attempt` (starting at 0)
+    ///
+    /// The delay doubles with every attempt up to `max_delay`, then up to `jitter`
+    /// of it is randomly removed so that workers do not reconnect in lockstep.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2_u32.saturating_pow(attempt.min(31)));
+        let capped = backoff.min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+        capped.mul_f64(1.0 - jitter)
+    }
+}
+
+/// Configuration of the connection to the aggregator
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Aggregator addresses in order of preference; the client fails over to
+    /// the next one when a connection is lost or cannot be established
+    pub endpoints: Vec<String>,
+
+    /// Node ID announced in the handshake and used as envelope sender
+    pub node_id: NodeId,
+
+    /// How to retry lost or failed connections
+    pub reconnect: ReconnectPolicy,
+
+    /// Timeout for establishing the TCP connection and completing the handshake
+    pub connect_timeout: Duration,
+
+    /// Capacity of the inbound and outbound message queues
+    pub channel_capacity: usize,
+}
+
+impl ClientConfig {
+    pub fn new(aggregator_addr: &str, node_id: NodeId) -> Self {
+        Self::with_endpoints(vec![aggregator_addr.to_string()], node_id)
+    }
+
+    /// Connect to the first reachable of several aggregators
+    pub fn with_endpoints(endpoints: Vec<String>, node_id: NodeId) -> Self {
+        Self {
+            endpoints,
+            node_id,
+            reconnect: ReconnectPolicy::default(),
+            connect_timeout: Duration::from_secs(10),
+            channel_capacity: 64,
+        }
+    }
+}
+
+/// Connection state of an aggregator endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointState {
+    /// No connection attempt made yet
+    Untried,
+    /// The current connection goes to this endpoint
+    Connected,
+    /// The last attempt failed or the connection was lost; retried after a backoff
+    Failing,
+    /// The endpoint refused the handshake and is not tried again
+    Rejected,
+}
+
+/// Health of one aggregator endpoint as tracked by the client
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    pub addr: String,
+    pub state: EndpointState,
+
+    /// Failed connection attempts since the last successful handshake
+    pub consecutive_failures: u32,
+
+    /// Why the last attempt failed or the last connection ended
+    pub last_error: Option<String>,
+
+    /// When the last handshake with this endpoint succeeded
+    pub last_connected: Option<Instant>,
+
+    /// Earliest time of the next connection attempt
+    next_attempt: Instant,
+}
+
+impl EndpointHealth {
+    fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            state: EndpointState::Untried,
+            consecutive_failures: 0,
+            last_error: None,
+            last_connected: None,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// Whether the endpoint may be tried again under `policy`
+    fn is_usable(&self, policy: &ReconnectPolicy) -> bool {
+        self.state != EndpointState::Rejected
+            && policy.max_attempts.is_none_or(|max_attempts| self.consecutive_failures < max_attempts)
+    }
+}
+
+/// Queue of messages to send to the aggregator, cheap to clone
+///
+/// Messages are numbered as they are queued, so the sender knows the sequence
+/// number the aggregator refers to when it acknowledges one. The numbers keep
+/// growing across reconnects; the handshake of each connection is number 0.
+#[derive(Debug, Clone)]
+pub struct Outbound {
+    queue: mpsc::Sender<(u64, ProtocolMessage)>,
+    next_sequence: Arc<Mutex<u64>>,
+}
+
+impl Outbound {
+    /// A queue holding up to `capacity` messages, and its receiving end
+    pub fn channel(capacity: usize) -> (Self, mpsc::Receiver<(u64, ProtocolMessage)>) {
+        let (queue, receiver) = mpsc::channel(capacity);
+        (Outbound { queue, next_sequence: Arc::new(Mutex::new(1)) }, receiver)
+    }
+
+    /// Queue a message, returning the sequence number it will be sent with
+    pub async fn send(&self, message: ProtocolMessage) -> Result<u64> {
+        // Held while queueing, so the numbers reach the queue in order
+        let mut next_sequence = self.next_sequence.lock().await;
+        let sequence = *next_sequence;
+        self.queue
+            .send((sequence, message))
+            .await
+            .map_err(|_| anyhow::Error::msg("Outbound message channel closed"))?;
+        *next_sequence += 1;
+        Ok(sequence)
+    }
+}
+
+/// Channels to a running client task
+pub struct ClientHandle {
+    /// Messages received from the aggregator; every new connection starts
+    /// with the aggregator's `HelloAck`
+    pub inbound: mpsc::Receiver<ProtocolMessage>,
+
+    /// Messages to send to the aggregator; queued while reconnecting
+    pub outbound: Outbound,
+
+    /// Health of each aggregator endpoint, updated after every connection attempt
+    pub endpoints: watch::Receiver<Vec<EndpointHealth>>,
+
+    /// The client task; finishes on shutdown or when it gives up reconnecting
+    pub task: JoinHandle<Result<()>>,
+}
+
+/// Why a connection attempt failed
+enum ConnectError {
+    /// The aggregator could not be reached; worth retrying
+    Transport(anyhow::Error),
+    /// The aggregator refused the handshake; retrying will not help
+    Rejected(ErrorMessage),
+}
+
+/// How a connection ended
+enum SessionEnd {
+    /// Shutdown was requested or the local side went away
+    Stopped,
+    /// The connection was lost
+    Lost(anyhow::Error),
+}
+
+/// Async client maintaining a persistent connection to one of the aggregators
 pub struct TcpClient {
-    /// The TCP stream connection to the aggregator
-    stream: Option<TcpStream>,
-    
-    /// The aggregator's address
-    aggregator_addr: String,
-    
-    /// Maximum number of retry attempts
-    max_retries: u32,
-    
-    /// Base delay for exponential backoff (in milliseconds)
-    base_delay_ms: u64,
-    
-    /// Maximum delay for exponential backoff (in milliseconds)
-    max_delay_ms: u64,
+    config: ClientConfig,
+
+    /// Health of each endpoint, in the configured order
+    endpoints: Vec<EndpointHealth>,
+
+    /// Index of the endpoint of the current or last connection attempt
+    current: usize,
+
+    /// Handshake answer of the current connection
+    negotiated: Option<HelloAckMsg>,
 }
 
 impl TcpClient {
+    /// Create a new TCP client instance with default settings
+    pub fn new(aggregator_addr: &str, node_id: NodeId) -> Self {
+        Self::with_config(ClientConfig::new(aggregator_addr, node_id))
+    }
+
     /// Create a new TCP client instance
-    pub fn new(aggregator_addr: &str) -> Self {
+    pub fn with_config(config: ClientConfig) -> Self {
         TcpClient {
-            stream: None,
-            aggregator_addr: aggregator_addr.to_string(),
-            max_retries: 5,  // Maximum number of retries
-            base_delay_ms: 100,  // 1 second base delay
-            max_delay_ms: 300,  // 30 seconds max delay
+            endpoints: config.endpoints.iter().map(|addr| EndpointHealth::new(addr)).collect(),
+            current: 0,
+            config,
+            negotiated: None,
         }
     }
-    
-    /// Connect to the aggregator with exponential backoff
-    pub fn connect(&mut self) -> Result<()> {
-        self.connect_with_retry(0)
-    }
-    
-    /// Connect to the aggregator with exponential backoff
-    fn connect_with_retry(&mut self, retry_count: u32) -> Result<()> {
-        if retry_count > 0 {
-            // Calculate delay using exponential backoff: base_delay * 2^(retry_count-1)
-            let delay_ms = std::cmp::min(
-                self.base_delay_ms * 2_u64.pow(retry_count - 1),
-                self.max_delay_ms
-            );
-            
-            debug!("Retrying connection to aggregator in {}ms (attempt {}/{})",
-                   delay_ms, retry_count, self.max_retries);
-            
-            thread::sleep(Duration::from_millis(delay_ms));
-        }
-        
-        debug!("Attempting to connect to aggregator at {}", self.aggregator_addr);
-        
-        // Try to establish connection
-        match TcpStream::connect(&self.aggregator_addr) {
-            Ok(stream) => {
-                // Configure stream options for better reliability
-                stream.set_read_timeout(Some(Duration::from_secs(30)))?;
-                stream.set_write_timeout(Some(Duration::from_secs(30)))?;
-                stream.set_nodelay(true)?;
-                
-                self.stream = Some(stream);
-                if retry_count > 0 {
-                    info!("Successfully connected to aggregator at {} after {} attempts",
-                          self.aggregator_addr, retry_count);
-                } else {
-                    info!("Successfully connected to aggregator at {}", self.aggregator_addr);
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// The parameters negotiated with the aggregator, if connected
+    pub fn negotiated(&self) -> Option<&HelloAckMsg> {
+        self.negotiated.as_ref()
+    }
+
+    /// Health of each aggregator endpoint
+    pub fn endpoints(&self) -> &[EndpointHealth] {
+        &self.endpoints
+    }
+
+    /// Address of the endpoint of the current or last connection attempt
+    pub fn current_endpoint(&self) -> &str {
+        &self.config.endpoints[self.current]
+    }
+
+    /// Start the client in a background task
+    ///
+    /// The task keeps a connection open, reconnecting as needed, until `shutdown`
+    /// is cancelled, the inbound receiver is dropped, or it runs out of attempts.
+    pub fn spawn(self, shutdown: CancellationToken) -> ClientHandle {
+        let (inbound_tx, inbound) = mpsc::channel(self.config.channel_capacity);
+        let (outbound, outbound_rx) = Outbound::channel(self.config.channel_capacity);
+        let (health, endpoints) = watch::channel(self.endpoints.clone());
+        let task = tokio::spawn(self.run(inbound_tx, outbound_rx, health, shutdown));
+
+        ClientHandle { inbound, outbound, endpoints, task }
+    }
+
+    /// Connection loop: connect, relay messages, fail over and reconnect
+    ///
+    /// Endpoints are tried in order, starting after the one whose connection
+    /// was lost. Each endpoint backs off on its own, so a failing aggregator is
+    /// skipped while another one can be tried right away.
+    async fn run(
+        mut self,
+        inbound: mpsc::Sender<ProtocolMessage>,
+        mut outbound: mpsc::Receiver<(u64, ProtocolMessage)>,
+        health: watch::Sender<Vec<EndpointHealth>>,
+        shutdown: CancellationToken,
+    ) -> Result<()> {
+        let mut start = 0;
+        let mut last_error = None;
+
+        loop {
+            let Some(index) = self.next_endpoint(start) else {
+                return Err(self.exhausted(last_error));
+            };
+            if index != self.current && self.endpoints[self.current].state != EndpointState::Untried {
+                info!("Failing over from aggregator at {} to {}", self.current_endpoint(), self.config.endpoints[index]);
+            }
+            self.current = index;
+
+            let delay = self.endpoints[index].next_attempt.saturating_duration_since(Instant::now());
+            if !delay.is_zero() {
+                debug!("Reconnecting to aggregator at {} in {:?}", self.current_endpoint(), delay);
+                tokio::select! {
+                    _ = shutdown.cancelled() => return Ok(()),
+                    _ = tokio::time::sleep(delay) => {}
                 }
-                
-                Ok(())
             }
-            Err(e) => {
-                error!("Failed to connect to aggregator at {} (attempt {} of {}): {}",
-                       self.aggregator_addr, retry_count + 1, self.max_retries, e);
-                
-                if retry_count < self.max_retries {
-                    self.connect_with_retry(retry_count + 1)
-                } else {
-                    error!("Max retries ({}) exceeded for connection to aggregator at {}",
-                           self.max_retries, self.aggregator_addr);
-                    Err(anyhow::Error::msg(format!(
-                        "Failed to connect to aggregator after {} attempts: {}",
-                        self.max_retries, e
-                    )))
+
+            let connected = tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                connected = self.connect() => connected,
+            };
+
+            let endpoint = &mut self.endpoints[index];
+            match connected {
+                Ok(connection) => {
+                    endpoint.state = EndpointState::Connected;
+                    endpoint.consecutive_failures = 0;
+                    endpoint.last_connected = Some(Instant::now());
+                    health.send_replace(self.endpoints.clone());
+
+                    match self.serve(connection, &inbound, &mut outbound, &shutdown).await {
+                        SessionEnd::Stopped => {
+                            debug!("Client for {} stopped", self.current_endpoint());
+                            return Ok(());
+                        }
+                        SessionEnd::Lost(e) => {
+                            warn!("Connection to aggregator at {} lost: {}", self.current_endpoint(), e);
+                            let endpoint = &mut self.endpoints[index];
+                            endpoint.state = EndpointState::Failing;
+                            endpoint.last_error = Some(e.to_string());
+                            endpoint.next_attempt = Instant::now() + self.config.reconnect.delay(0);
+                        }
+                    }
+                }
+                Err(ConnectError::Rejected(e)) => {
+                    error!("Aggregator at {} rejected handshake: {}", endpoint.addr, e.error_message);
+                    endpoint.state = EndpointState::Rejected;
+                    endpoint.last_error = Some(e.error_message.clone());
+                    last_error = Some(anyhow::Error::msg(format!(
+                        "Aggregator rejected handshake (code {}): {}", e.error_code, e.error_message
+                    )));
+                }
+                Err(ConnectError::Transport(e)) => {
+                    endpoint.state = EndpointState::Failing;
+                    endpoint.consecutive_failures += 1;
+                    endpoint.last_error = Some(e.to_string());
+                    endpoint.next_attempt = Instant::now() + self.config.reconnect.delay(endpoint.consecutive_failures - 1);
+                    error!("Failed to connect to aggregator at {} (attempt {}): {}",
+                           endpoint.addr, endpoint.consecutive_failures, e);
+
+                    if let Some(max_attempts) = self.config.reconnect.max_attempts {
+                        if endpoint.consecutive_failures >= max_attempts {
+                            error!("Max retries ({}) exceeded for connection to aggregator at {}",
+                                   max_attempts, endpoint.addr);
+                            last_error = Some(anyhow::Error::msg(format!(
+                                "Failed to connect to aggregator after {} attempts: {}", max_attempts, e
+                            )));
+                        }
+                    }
                 }
             }
+
+            health.send_replace(self.endpoints.clone());
+            start = index + 1;
         }
     }
-    
-    /// Send a commitment payload to the aggregator
-    pub fn send_commitment(&mut self, payload: &CommitmentPayload) -> Result<()> {
-        // Ensure we have an active connection with retry logic
-        if self.stream.is_none() {
-            self.connect()?;
+
+    /// The endpoint to try next, going round from `start`
+    ///
+    /// Picks the first endpoint that may be tried right away, or the one whose
+    /// backoff ends first. `None` once every endpoint was rejected or ran out of
+    /// attempts.
+    fn next_endpoint(&self, start: usize) -> Option<usize> {
+        let count = self.endpoints.len();
+        let now = Instant::now();
+        (0..count)
+            .map(|offset| (start + offset) % count)
+            .filter(|&index| self.endpoints[index].is_usable(&self.config.reconnect))
+            .min_by_key(|&index| self.endpoints[index].next_attempt.max(now))
+    }
+
+    /// The error to stop with once no endpoint is left to try
+    fn exhausted(&self, last_error: Option<anyhow::Error>) -> anyhow::Error {
+        match last_error {
+            Some(e) if self.endpoints.len() == 1 => e,
+            Some(e) => anyhow::Error::msg(format!(
+                "None of the {} aggregator endpoints is reachable, last error: {}", self.endpoints.len(), e
+            )),
+            None => anyhow::Error::msg("No aggregator endpoints configured"),
         }
-        
-        // Try to send the commitment with retry logic
-        self.send_commitment_with_retry(payload, 0)
-    }
-    
-    /// Send a commitment payload to the aggregator with retry logic
-    fn send_commitment_with_retry(&mut self, payload: &CommitmentPayload, retry_count: u32) -> Result<()> {
-        if retry_count > 0 {
-            // Calculate delay using exponential backoff: base_delay * 2^(retry_count-1)
-            let delay_ms = std::cmp::min(
-                self.base_delay_ms * 2_u64.pow(retry_count - 1),
-                self.max_delay_ms
-            );
-            
-            debug!("Retrying to send commitment in {}ms (attempt {}/{})",
-                   delay_ms, retry_count, self.max_retries);
-            
-            thread::sleep(Duration::from_millis(delay_ms));
-            
-            // Reconnect if needed
-            if !self.is_connected() {
-                if let Err(e) = self.connect() {
-                    error!("Failed to reconnect before sending commitment: {}", e);
-                    if retry_count < self.max_retries {
-                        return self.send_commitment_with_retry(payload, retry_count + 1);
-                    } else {
-                        return Err(e);
-                    }
-                }
-            }
+    }
+
+    /// Open a connection and perform the handshake
+    async fn connect(&mut self) -> Result<Connection, ConnectError> {
+        match tokio::time::timeout(self.config.connect_timeout, self.connect_and_handshake()).await {
+            Ok(result) => result,
+            Err(_) => Err(ConnectError::Transport(anyhow::Error::msg("Connection attempt timed out"))),
         }
-        
-        let stream = match self.stream.as_mut() {
-            Some(s) => s,
-            None => {
-                error!("No active connection to aggregator");
-                return Err(anyhow::Error::msg("No active connection to aggregator"));
-            }
+    }
+
+    async fn connect_and_handshake(&mut self) -> Result<Connection, ConnectError> {
+        let addr = self.current_endpoint().to_string();
+        debug!("Attempting to connect to aggregator at {}", addr);
+
+        let stream = TcpStream::connect(&addr)
+            .await
+            .map_err(|e| ConnectError::Transport(e.into()))?;
+        stream.set_nodelay(true).map_err(|e| ConnectError::Transport(e.into()))?;
+        let mut connection = Framed::new(stream, ProtocolCodec::new());
+
+        self.negotiated = None;
+
+        let hello = HelloMsg {
+            node_id: self.config.node_id.clone(),
+            versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+            capabilities: SUPPORTED_CAPABILITIES.to_vec(),
         };
-        
-        debug!("Serializing commitment payload for round {}", payload.round_id);
-        
-        // Serialize the commitment payload to JSON
-        let json_payload = match serde_json::to_string(payload) {
-            Ok(json) => json,
-            Err(e) => {
-                error!("Failed to serialize commitment payload: {}", e);
-                return Err(anyhow::Error::msg(format!("Failed to serialize commitment payload: {}", e)));
-            }
+        let envelope = self.envelope(0, ProtocolMessage::Hello(hello));
+        connection.send(envelope).await.map_err(|e| ConnectError::Transport(e.into()))?;
+
+        let reply = match connection.next().await {
+            Some(Ok(reply)) => reply,
+            Some(Err(e)) => return Err(ConnectError::Transport(e.into())),
+            None => return Err(ConnectError::Transport(anyhow::Error::msg("Connection closed during handshake"))),
         };
-        
-        debug!("Sending commitment payload: {}", json_payload);
-        
-        // Send the length of the message first (4 bytes in big-endian)
-        let msg_bytes = json_payload.as_bytes();
-        let msg_len = msg_bytes.len() as u32;
-        let len_bytes = msg_len.to_be_bytes();
-        
-        match stream.write_all(&len_bytes) {
-            Ok(_) => {},
-            Err(e) => {
-                error!("Failed to write message length for round {} (attempt {} of {}): {}",
-                       payload.round_id, retry_count + 1, self.max_retries, e);
-                if retry_count < self.max_retries {
-                    return self.send_commitment_with_retry(payload, retry_count + 1);
-                } else {
-                    error!("Max retries ({}) exceeded for sending commitment for round {}",
-                           self.max_retries, payload.round_id);
-                    return Err(anyhow::Error::msg(format!("Failed to write message length: {}", e)));
+
+        match reply.message {
+            ProtocolMessage::HelloAck(ack) => {
+                if !SUPPORTED_PROTOCOL_VERSIONS.contains(&ack.version) {
+                    return Err(ConnectError::Rejected(ErrorMessage::new(
+                        entropy_types::protocol::error_codes::INCOMPATIBLE_VERSION,
+                        format!("aggregator selected unsupported protocol version {}", ack.version),
+                    )));
                 }
+                info!("Connected to aggregator {} at {}, protocol version {}",
+                      ack.node_id, addr, ack.version);
+                self.negotiated = Some(ack);
+                Ok(connection)
             }
+            ProtocolMessage::Error(e) => Err(ConnectError::Rejected(e)),
+            other => Err(ConnectError::Transport(anyhow::Error::msg(format!(
+                "Expected hello_ack from aggregator, got {}", other.kind()
+            )))),
         }
-        
-        match stream.write_all(msg_bytes) {
-            Ok(_) => {},
-            Err(e) => {
-                error!("Failed to write message for round {} (attempt {} of {}): {}",
-                       payload.round_id, retry_count + 1, self.max_retries, e);
-                if retry_count < self.max_retries {
-                    return self.send_commitment_with_retry(payload, retry_count + 1);
-                } else {
-                    error!("Max retries ({}) exceeded for sending commitment for round {}",
-                           self.max_retries, payload.round_id);
-                    return Err(anyhow::Error::msg(format!("Failed to write message: {}", e)));
-                }
+    }
+
+    /// Relay messages over an established connection until it ends
+    ///
+    /// The handshake answer is passed on first, so the consumer knows that a new
+    /// session started and can resend what the previous one did not confirm.
+    async fn serve(
+        &mut self,
+        mut connection: Connection,
+        inbound: &mpsc::Sender<ProtocolMessage>,
+        outbound: &mut mpsc::Receiver<(u64, ProtocolMessage)>,
+        shutdown: &CancellationToken,
+    ) -> SessionEnd {
+        if let Some(ack) = self.negotiated.clone() {
+            if inbound.send(ProtocolMessage::HelloAck(ack)).await.is_err() {
+                close(&mut connection).await;
+                return SessionEnd::Stopped;
             }
         }
-        
-        match stream.flush() {
-            Ok(_) => {},
-            Err(e) => {
-                error!("Failed to flush stream for round {} (attempt {} of {}): {}",
-                       payload.round_id, retry_count + 1, self.max_retries, e);
-                if retry_count < self.max_retries {
-                    return self.send_commitment_with_retry(payload, retry_count + 1);
-                } else {
-                    error!("Max retries ({}) exceeded for sending commitment for round {}",
-                           self.max_retries, payload.round_id);
-                    return Err(anyhow::Error::msg(format!("Failed to flush stream: {}", e)));
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    close(&mut connection).await;
+                    return SessionEnd::Stopped;
                 }
-            }
-        }
-        
-        info!("Successfully sent commitment payload for round {}", payload.round_id);
-        
-        Ok(())
-    }
-    
-    /// Check if the client is currently connected
-    pub fn is_connected(&self) -> bool {
-        match &self.stream {
-            Some(stream) => {
-                // Try to peek at the stream to check if it's still active
-                let mut buf = [0; 1];
-                match stream.peek(&mut buf) {
-                    Ok(_) => true,
-                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => true,
-                    Err(_) => false,
+                message = outbound.recv() => {
+                    let Some((sequence, message)) = message else {
+                        close(&mut connection).await;
+                        return SessionEnd::Stopped;
+                    };
+                    debug!("Sending {} to aggregator", message.kind());
+                    let envelope = self.envelope(sequence, message);
+                    if let Err(e) = connection.send(envelope).await {
+                        return SessionEnd::Lost(e.into());
+                    }
+                }
+                frame = connection.next() => {
+                    match frame {
+                        Some(Ok(envelope)) => {
+                            debug!("Received {} from aggregator", envelope.message.kind());
+                            if inbound.send(envelope.message).await.is_err() {
+                                close(&mut connection).await;
+                                return SessionEnd::Stopped;
+                            }
+                        }
+                        Some(Err(e)) => return SessionEnd::Lost(e.into()),
+                        None => return SessionEnd::Lost(anyhow::Error::msg("Connection closed by aggregator")),
+                    }
                 }
             }
-            None => false,
-        }
-    }
-    
-    /// Disconnect from the aggregator
-    pub fn disconnect(&mut self) {
-        if self.stream.is_some() {
-            debug!("Disconnecting from aggregator");
-            self.stream = None;
         }
     }
-    
-    /// Attempt to reconnect if connection is lost
-    pub fn ensure_connection(&mut self) -> Result<()> {
-        if !self.is_connected() {
-            warn!("Connection to aggregator lost, attempting to reconnect...");
-            self.disconnect();
-            self.connect()?;
-        }
-        Ok(())
-    }
-}
 
-impl Drop for TcpClient {
-    /// Ensure the connection is closed when the client is dropped
-    fn drop(&mut self) {
-        self.disconnect();
+    /// Wrap a message in an envelope for the current connection
+    fn envelope(&self, sequence: u64, message: ProtocolMessage) -> ProtocolEnvelope {
+        let mut envelope = ProtocolEnvelope::new(sequence, self.config.node_id.clone(), message);
+        if let Some(ack) = &self.negotiated {
+            envelope.version = ack.version;
+        }
+        envelope
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::net::{TcpListener};
-    use std::time::Duration;
-    
+    use entropy_types::CommitmentPayload;
+
     #[test]
     fn test_tcp_client_creation() {
-        let client = TcpClient::new("localhost:9000");
-        assert_eq!(client.aggregator_addr, "localhost:9000");
-        assert!(!client.is_connected());
+        let client = TcpClient::new("localhost:9000", "test-node".to_string());
+        assert_eq!(client.config().endpoints, vec!["localhost:9000"]);
+        assert_eq!(client.config().node_id, "test-node");
+        assert!(client.negotiated().is_none());
+        assert_eq!(client.current_endpoint(), "localhost:9000");
+        assert_eq!(client.endpoints()[0].state, EndpointState::Untried);
     }
-    
-    #[test]
-    fn test_connection_methods() {
-        // Note: This test assumes an aggregator is running on localhost:9001 for testing
-        // In a real scenario, we would start a mock server for the test
-        let mut client = TcpClient::new("localhost:9001");
-        
-        // The connection should fail since no server is running
-        let result = client.connect();
-        assert!(result.is_err());
-        
-        // is_connected should return false when no connection exists
-        assert!(!client.is_connected());
-    }
-    
+
     #[test]
-    fn test_exponential_backoff_calculation() {
-        // Test that the exponential backoff calculation works correctly
-        let base_delay: u64 = 100; // 100ms base delay from TcpClient
-        let max_delay: u64 = 30000; // 30 seconds max delay from TcpClient
-        
-        // Test the exponential backoff calculation
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(0), max_delay), base_delay); // 1st retry: 100ms
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(1), max_delay), base_delay * 2); // 2nd retry: 200ms
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(2), max_delay), base_delay * 4); // 3rd retry: 400ms
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(3), max_delay), base_delay * 8); // 4th retry: 800ms
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(4), max_delay), base_delay * 16); // 5th retry: 1600ms
-        // Eventually it should cap at max_delay
-        assert_eq!(std::cmp::min(base_delay * 2_u64.pow(10), max_delay), max_delay); // High retry: capped at max
-    }
-    
+    fn test_reconnect_backoff() {
+        let policy = ReconnectPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+            jitter: 0.0,
+        };
+
+        // Without jitter the delay doubles until it is capped
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(1600));
+        assert_eq!(policy.delay(10), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+
+        // With jitter it stays between (1 - jitter) and 1 times the backoff
+        let jittered = ReconnectPolicy { jitter: 0.5, ..policy };
+        for _ in 0..100 {
+            let delay = jittered.delay(2);
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400), "{:?}", delay);
+        }
+    }
+
     #[test]
     fn test_commitment_payload_serialization() {
         let commitment_payload = CommitmentPayload {
@@ -302,10 +556,10 @@ mod tests {
             commitment: [1u8; 32],
             signature: vec![2u8, 3u8, 4u8],
         };
-        
+
         let json = serde_json::to_string(&commitment_payload).unwrap();
         let deserialized: CommitmentPayload = serde_json::from_str(&json).unwrap();
-        
+
         assert_eq!(commitment_payload, deserialized);
     }
 }
@@ -314,43 +568,226 @@ mod tests {
 #[cfg(test)]
 mod integration_tests {
     use super::*;
-    use std::net::TcpListener;
-    use std::thread;
-    use std::io::{Read};
-    
-    #[test]
-    fn test_send_commitment_to_mock_server() {
-        // Start a mock server in a separate thread
-        let server_addr = "127.0.0.1:9002";
-        let listener = TcpListener::bind(server_addr).unwrap();
-        
-        let server_handle = thread::spawn(move || {
-            if let Ok((mut stream, _)) = listener.accept() {
-                let mut buffer = [0; 1024];
-                if let Ok(_) = stream.read(&mut buffer) {
-                    // Server receives the data
-                    return true;
-                }
-            }
-            false
+    use entropy_types::protocol::error_codes;
+    use entropy_types::{HeartbeatMsg, StartCommitmentMsg, PROTOCOL_VERSION};
+    use tokio::net::TcpListener;
+
+    fn test_config(addr: &str) -> ClientConfig {
+        ClientConfig {
+            reconnect: ReconnectPolicy {
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(50),
+                max_attempts: Some(3),
+                jitter: 0.5,
+            },
+            ..ClientConfig::new(addr, "test-node".to_string())
+        }
+    }
+
+    /// Accept one connection and answer the hello like the aggregator does
+    async fn accept_session(listener: &TcpListener) -> Framed<TcpStream, ProtocolCodec> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(stream, ProtocolCodec::new());
+
+        let hello = framed.next().await.unwrap().unwrap();
+        let ProtocolMessage::Hello(hello_msg) = hello.message else {
+            panic!("expected hello, got {}", hello.message.kind());
+        };
+        assert_eq!(hello.sequence, 0);
+        assert_eq!(hello_msg.node_id, "test-node");
+
+        let ack = HelloAckMsg {
+            node_id: "aggregator".to_string(),
+            version: PROTOCOL_VERSION,
+            capabilities: hello_msg.capabilities,
+            challenge: [0u8; 32],
+        };
+        framed.send(ProtocolEnvelope::new(0, "aggregator".to_string(), ProtocolMessage::HelloAck(ack))).await.unwrap();
+        framed
+    }
+
+    fn heartbeat() -> ProtocolMessage {
+        ProtocolMessage::Heartbeat(HeartbeatMsg {
+            node_id: "test-node".to_string(),
+            timestamp: 0,
+            status: "ok".to_string(),
+            version: "0.1.0".to_string(),
+            sent_at_ms: 0,
+            signature: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_bidirectional_streaming() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let shutdown = CancellationToken::new();
+        let mut handle = TcpClient::with_config(test_config(&addr)).spawn(shutdown.clone());
+        let mut server = accept_session(&listener).await;
+        assert!(matches!(handle.inbound.recv().await, Some(ProtocolMessage::HelloAck(_))));
+
+        // Messages pushed by the aggregator arrive on the inbound stream
+        let start = StartCommitmentMsg { round_id: 5, committee: vec!["test-node".to_string()], signature: Vec::new() };
+        server.send(ProtocolEnvelope::new(1, "aggregator".to_string(), ProtocolMessage::StartCommitment(start.clone())))
+            .await
+            .unwrap();
+        assert_eq!(handle.inbound.recv().await, Some(ProtocolMessage::StartCommitment(start)));
+
+        // Outbound messages are wrapped in envelopes with the sequence number they were queued with
+        assert_eq!(handle.outbound.send(heartbeat()).await.unwrap(), 1);
+        let received = server.next().await.unwrap().unwrap();
+        assert_eq!(received.sequence, 1);
+        assert_eq!(received.sender, "test-node");
+        assert_eq!(received.message, heartbeat());
+
+        shutdown.cancel();
+        handle.task.await.unwrap().unwrap();
+        assert!(server.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejected_by_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(stream, ProtocolCodec::new());
+            framed.next().await.unwrap().unwrap();
+
+            let error = ErrorMessage::new(error_codes::INCOMPATIBLE_VERSION, "no common protocol version");
+            framed.send(ProtocolEnvelope::new(0, "aggregator".to_string(), ProtocolMessage::Error(error))).await.unwrap();
         });
-        
-        // Give the server a moment to start
-        thread::sleep(Duration::from_millis(100));
-        
-        // Create a client and try to send data
-        let mut client = TcpClient::new(server_addr);
-        let commitment_payload = CommitmentPayload {
-            round_id: 1,
-            commitment: [1u8; 32],
-            signature: vec![2u8, 3u8, 4u8],
+
+        let handle = TcpClient::with_config(test_config(&addr)).spawn(CancellationToken::new());
+        let err = handle.task.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("no common protocol version"));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_connection_loss() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let shutdown = CancellationToken::new();
+        let mut handle = TcpClient::with_config(test_config(&addr)).spawn(shutdown.clone());
+
+        // Drop the first session; the client must come back with a fresh handshake
+        drop(accept_session(&listener).await);
+
+        // The new connection relays messages as before
+        let mut server = accept_session(&listener).await;
+        assert!(matches!(handle.inbound.recv().await, Some(ProtocolMessage::HelloAck(_))));
+        assert!(matches!(handle.inbound.recv().await, Some(ProtocolMessage::HelloAck(_))));
+        handle.outbound.send(heartbeat()).await.unwrap();
+        assert_eq!(server.next().await.unwrap().unwrap().message, heartbeat());
+
+        // Sequence numbers keep growing on the new connection
+        assert_eq!(handle.outbound.send(heartbeat()).await.unwrap(), 2);
+        assert_eq!(server.next().await.unwrap().unwrap().sequence, 2);
+
+        shutdown.cancel();
+        handle.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        // Bind and drop a listener to get a port nobody listens on
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+
+        let handle = TcpClient::with_config(test_config(&addr)).spawn(CancellationToken::new());
+        let err = handle.task.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("after 3 attempts"));
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_next_endpoint() {
+        let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoints = vec![
+            dead.clone(),
+            first.local_addr().unwrap().to_string(),
+            second.local_addr().unwrap().to_string(),
+        ];
+        let config = ClientConfig {
+            endpoints,
+            ..test_config(&dead)
         };
-        
-        let result = client.send_commitment(&commitment_payload);
-        assert!(result.is_ok());
-        
-        // Wait for the server thread to complete
-        let received_data = server_handle.join().unwrap();
-        assert!(received_data);
-    }
-}
\ No newline at end of file
+
+        let shutdown = CancellationToken::new();
+        let mut handle = TcpClient::with_config(config).spawn(shutdown.clone());
+
+        // The unreachable endpoint is skipped
+        let session = accept_session(&first).await;
+        assert!(matches!(handle.inbound.recv().await, Some(ProtocolMessage::HelloAck(_))));
+        {
+            let endpoints = handle.endpoints.borrow();
+            assert_eq!(endpoints[0].state, EndpointState::Failing);
+            assert_eq!(endpoints[0].consecutive_failures, 1);
+            assert_eq!(endpoints[1].state, EndpointState::Connected);
+            assert_eq!(endpoints[2].state, EndpointState::Untried);
+        }
+
+        // Losing the connection moves on to the next endpoint rather than retrying the same one
+        drop(session);
+        let mut server = accept_session(&second).await;
+        assert!(matches!(handle.inbound.recv().await, Some(ProtocolMessage::HelloAck(_))));
+        handle.outbound.send(heartbeat()).await.unwrap();
+        assert_eq!(server.next().await.unwrap().unwrap().message, heartbeat());
+
+        handle.endpoints.wait_for(|endpoints| endpoints[2].state == EndpointState::Connected).await.unwrap();
+        {
+            let endpoints = handle.endpoints.borrow();
+            assert_eq!(endpoints[1].state, EndpointState::Failing);
+            assert_eq!(endpoints[1].consecutive_failures, 0);
+            assert!(endpoints[1].last_error.is_some());
+            assert!(endpoints[2].last_connected.is_some());
+        }
+
+        shutdown.cancel();
+        handle.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_no_endpoint_is_reachable() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let config = ClientConfig {
+            endpoints: vec![first.clone(), second],
+            ..test_config(&first)
+        };
+
+        // Every endpoint gets its own attempts before the client gives up
+        let handle = TcpClient::with_config(config).spawn(CancellationToken::new());
+        let err = handle.task.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("None of the 2 aggregator endpoints"), "{}", err);
+        let endpoints = handle.endpoints.borrow();
+        assert!(endpoints.iter().all(|endpoint| endpoint.consecutive_failures == 3));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_reconnect() {
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let config = ClientConfig {
+            reconnect: ReconnectPolicy {
+                base_delay: Duration::from_secs(60),
+                max_delay: Duration::from_secs(60),
+                max_attempts: None,
+                jitter: 0.0,
+            },
+            ..test_config(&addr)
+        };
+
+        let shutdown = CancellationToken::new();
+        let mut handle = TcpClient::with_config(config).spawn(shutdown.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The client is sleeping before its next attempt; cancelling must not wait for it
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(1), &mut handle.task).await.unwrap().unwrap().unwrap();
+        assert_eq!(handle.inbound.recv().await, None);
+    }
+}
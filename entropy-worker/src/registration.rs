@@ -0,0 +1,221 @@
+//! Registration of the worker's key with an aggregator.
+//!
+//! The worker proves it holds its key by signing a challenge the aggregator
+//! issues on the connection. Whether the key is then admitted to the committee,
+//! queued for an operator or refused is up to the aggregator.
+//!
+//! A registered worker replaces its key with a rotation signed by both the old
+//! and the new key. The new key takes over at the aggregator's next round.
+
+use anyhow::Result;
+use entropy_types::crypto::{sign_key_rotation, sign_registration};
+use entropy_types::protocol::RegisterRequestMsg;
+use entropy_types::{
+    Capability, HelloAckMsg, KeyRotationMsg, KeyRotationResultMsg, ProtocolMessage, RegisterMsg, RegisterResultMsg,
+};
+use log::{info, debug};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::keystore::Identity;
+use crate::network::Outbound;
+
+/// How long to wait for each answer from the aggregator
+pub const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Build the registration answering a challenge issued by `aggregator_id`
+pub fn registration_message(
+    identity: &Identity,
+    aggregator_id: &str,
+    nonce: [u8; 32],
+    metadata: BTreeMap<String, String>,
+) -> RegisterMsg {
+    let mut registration = RegisterMsg {
+        node_id: identity.node_id().to_string(),
+        public_key: identity.public_key().serialize().to_vec(),
+        nonce,
+        metadata,
+        signature: Vec::new(),
+    };
+    registration.signature = sign_registration(identity.secret_key(), aggregator_id, &registration);
+    registration
+}
+
+/// Register the identity over a client connection's message channels
+///
+/// Waits for the handshake, asks for a challenge, answers it and returns the
+/// aggregator's verdict. Round messages arriving in between are ignored.
+pub async fn register(
+    identity: &Identity,
+    metadata: BTreeMap<String, String>,
+    inbound: &mut mpsc::Receiver<ProtocolMessage>,
+    outbound: &Outbound,
+) -> Result<RegisterResultMsg> {
+    let ack = handshake(inbound, Capability::Registration).await?;
+
+    let request = RegisterRequestMsg { node_id: identity.node_id().to_string() };
+    outbound.send(ProtocolMessage::RegisterRequest(request)).await?;
+    let challenge = receive(inbound, "registration challenge", |message| match message {
+        ProtocolMessage::RegisterChallenge(challenge) => Some(Ok(challenge)),
+        // A closed registration is refused before any challenge is issued
+        ProtocolMessage::RegisterResult(result) => Some(Err(result)),
+        _ => None,
+    })
+    .await?;
+    let challenge = match challenge {
+        Ok(challenge) => challenge,
+        Err(result) => return Ok(result),
+    };
+
+    debug!("Answering registration challenge from {}", ack.node_id);
+    let registration = registration_message(identity, &ack.node_id, challenge.nonce, metadata);
+    outbound.send(ProtocolMessage::Register(registration)).await?;
+    let result = receive(inbound, "registration result", |message| match message {
+        ProtocolMessage::RegisterResult(result) => Some(result),
+        _ => None,
+    })
+    .await?;
+
+    info!("Registration with aggregator {}: {:?}", ack.node_id, result.status);
+    Ok(result)
+}
+
+/// Build a rotation from `old` to `new` for `aggregator_id`, signed by both keys
+pub fn key_rotation_message(old: &Identity, new: &Identity, aggregator_id: &str) -> KeyRotationMsg {
+    let mut rotation = KeyRotationMsg {
+        node_id: old.node_id().to_string(),
+        old_public_key: old.public_key().serialize().to_vec(),
+        new_public_key: new.public_key().serialize().to_vec(),
+        old_signature: Vec::new(),
+        new_signature: Vec::new(),
+    };
+    sign_key_rotation(old.secret_key(), new.secret_key(), aggregator_id, &mut rotation);
+    rotation
+}
+
+/// Rotate from `old` to `new` over a client connection's message channels
+///
+/// Waits for the handshake, sends the rotation signed for the aggregator that
+/// answered it and returns the aggregator's verdict.
+pub async fn rotate_key(
+    old: &Identity,
+    new: &Identity,
+    inbound: &mut mpsc::Receiver<ProtocolMessage>,
+    outbound: &Outbound,
+) -> Result<KeyRotationResultMsg> {
+    let ack = handshake(inbound, Capability::KeyRotation).await?;
+
+    let rotation = key_rotation_message(old, new, &ack.node_id);
+    outbound.send(ProtocolMessage::KeyRotation(rotation)).await?;
+    let result = receive(inbound, "key rotation result", |message| match message {
+        ProtocolMessage::KeyRotationResult(result) => Some(result),
+        _ => None,
+    })
+    .await?;
+
+    info!("Key rotation with aggregator {}: accepted {}", ack.node_id, result.accepted);
+    Ok(result)
+}
+
+/// Wait for the handshake and check that the aggregator supports `capability`
+async fn handshake(inbound: &mut mpsc::Receiver<ProtocolMessage>, capability: Capability) -> Result<HelloAckMsg> {
+    let ack = receive(inbound, "handshake", |message| match message {
+        ProtocolMessage::HelloAck(ack) => Some(ack),
+        _ => None,
+    })
+    .await?;
+    if !ack.capabilities.contains(&capability) {
+        return Err(anyhow::Error::msg(format!("Aggregator {} does not support the {:?} capability", ack.node_id, capability)));
+    }
+    Ok(ack)
+}
+
+/// Wait for the message `select` picks, skipping others and failing on errors
+async fn receive<T>(
+    inbound: &mut mpsc::Receiver<ProtocolMessage>,
+    expected: &str,
+    select: impl Fn(ProtocolMessage) -> Option<T>,
+) -> Result<T> {
+    let deadline = tokio::time::Instant::now() + REGISTRATION_TIMEOUT;
+    loop {
+        let message = tokio::time::timeout_at(deadline, inbound.recv())
+            .await
+            .map_err(|_| anyhow::Error::msg(format!("Timed out waiting for the {}", expected)))?
+            .ok_or_else(|| anyhow::Error::msg(format!("Connection closed while waiting for the {}", expected)))?;
+
+        if let ProtocolMessage::Error(error) = &message {
+            return Err(anyhow::Error::msg(format!(
+                "Aggregator reported error {}: {}",
+                error.error_code, error.error_message
+            )));
+        }
+        let kind = message.kind();
+        match select(message) {
+            Some(value) => return Ok(value),
+            None => debug!("Ignoring {} while waiting for the {}", kind, expected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entropy_types::crypto::{verify_key_rotation, verify_registration_signature};
+    use entropy_types::protocol::RegisterChallengeMsg;
+    use entropy_types::{RegistrationStatus, PROTOCOL_VERSION};
+
+    #[tokio::test]
+    async fn test_register_answers_challenge() {
+        let identity = Identity::generate().unwrap();
+        let (inbound_tx, mut inbound) = mpsc::channel(8);
+        let (outbound, mut outbound_rx) = Outbound::channel(8);
+
+        let aggregator = tokio::spawn(async move {
+            inbound_tx
+                .send(ProtocolMessage::HelloAck(HelloAckMsg {
+                    node_id: "aggregator".to_string(),
+                    version: PROTOCOL_VERSION,
+                    capabilities: vec![Capability::CommitReveal, Capability::Registration],
+                    challenge: [0u8; 32],
+                }))
+                .await
+                .unwrap();
+
+            let Some((_, ProtocolMessage::RegisterRequest(request))) = outbound_rx.recv().await else { panic!() };
+            inbound_tx
+                .send(ProtocolMessage::RegisterChallenge(RegisterChallengeMsg { nonce: [5u8; 32] }))
+                .await
+                .unwrap();
+
+            let Some((_, ProtocolMessage::Register(registration))) = outbound_rx.recv().await else { panic!() };
+            assert_eq!(registration.node_id, request.node_id);
+            assert_eq!(registration.nonce, [5u8; 32]);
+            assert_eq!(verify_registration_signature("aggregator", &registration), Ok(true));
+            inbound_tx
+                .send(ProtocolMessage::RegisterResult(RegisterResultMsg {
+                    node_id: registration.node_id,
+                    status: RegistrationStatus::Pending,
+                    reason: String::new(),
+                }))
+                .await
+                .unwrap();
+        });
+
+        let result = register(&identity, BTreeMap::new(), &mut inbound, &outbound).await.unwrap();
+        assert_eq!(result.status, RegistrationStatus::Pending);
+        assert_eq!(result.node_id, identity.node_id());
+        aggregator.await.unwrap();
+    }
+
+    #[test]
+    fn test_key_rotation_message() {
+        let old = Identity::generate().unwrap();
+        let new = old.rotated().unwrap();
+
+        let rotation = key_rotation_message(&old, &new, "aggregator");
+        assert_eq!(rotation.node_id, old.node_id());
+        assert_eq!(rotation.new_public_key, new.public_key().serialize().to_vec());
+        assert_eq!(verify_key_rotation("aggregator", &rotation), Ok(true));
+    }
+}
@@ -0,0 +1,760 @@
+use anyhow::Result;
+use entropy_types::crypto::ControlMessage;
+use entropy_types::{CommitmentMsg, ProtocolMessage, RevealMsg, StartCommitmentMsg, StartRevealMsg};
+use log::{info, debug, warn, error};
+use secp256k1::PublicKey;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::network::Outbound;
+use crate::worker::Worker;
+
+/// Configuration of the worker protocol loop
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// How often open rounds are checked against the worker's retention period
+    pub expiry_check_interval: Duration,
+
+    /// Interval between signed heartbeats; also keeps the connection open, so it
+    /// must stay below the aggregator's 30 second read timeout
+    pub heartbeat_interval: Duration,
+
+    /// Keys the aggregators sign their round control messages with; control
+    /// messages not signed by one of them are dropped
+    pub aggregator_keys: Vec<PublicKey>,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            expiry_check_interval: Duration::from_secs(1),
+            heartbeat_interval: Duration::from_secs(10),
+            aggregator_keys: Vec::new(),
+        }
+    }
+}
+
+/// What the worker sent for a round it still takes part in
+#[derive(Debug, Default)]
+struct SentRound {
+    commitment: Option<CommitmentMsg>,
+    reveal: Option<RevealMsg>,
+
+    /// Messages for this round the aggregator has not acknowledged yet
+    unacknowledged: usize,
+}
+
+impl SentRound {
+    /// The latest message sent for the round, to send again
+    fn latest(&self) -> Option<ProtocolMessage> {
+        match (&self.commitment, &self.reveal) {
+            (_, Some(_)) => self.resend_reveal().map(ProtocolMessage::Reveal),
+            (Some(_), None) => self.resend_commitment().map(ProtocolMessage::Commitment),
+            (None, None) => None,
+        }
+    }
+
+    /// The commitment sent for the round, stamped with the current time
+    ///
+    /// Only the payload is signed, so the timestamp can be renewed. Otherwise
+    /// the aggregator would refuse a commitment resent after a long outage as
+    /// stale.
+    fn resend_commitment(&self) -> Option<CommitmentMsg> {
+        let commitment = self.commitment.clone()?;
+        Some(CommitmentMsg { timestamp: unix_timestamp(), ..commitment })
+    }
+
+    /// The reveal sent for the round, stamped with the current time
+    fn resend_reveal(&self) -> Option<RevealMsg> {
+        let reveal = self.reveal.clone()?;
+        Some(RevealMsg { timestamp: unix_timestamp(), ..reveal })
+    }
+}
+
+/// Long-running protocol loop answering the aggregator's round messages
+///
+/// The service consumes inbound protocol messages and produces the replies to
+/// send back, so it is independent of how messages reach the aggregator.
+///
+/// Commitments and reveals are matched to the aggregator's acknowledgements by
+/// the sequence number they were sent with. An error naming a sequence number
+/// refuses that message like a negative acknowledgement; other errors leave the
+/// rounds alone.
+///
+/// A round stays open until the aggregator accepts its reveal or announces its
+/// completion. When the connection is replaced, possibly by one to another
+/// aggregator, unacknowledged commitments and reveals are sent again, and
+/// repeated start messages are answered with what was sent before. Either way
+/// the message gets a new timestamp, so it is not refused as stale.
+///
+/// Start and completion messages must be signed with a pinned aggregator key.
+/// The service remembers the latest round started, so a recorded start message
+/// cannot be replayed to make the worker commit to a round again.
+pub struct WorkerService {
+    worker: Worker,
+    config: ServiceConfig,
+
+    /// Rounds of the commitments and reveals sent but not yet acknowledged, by
+    /// the sequence number they were sent with
+    awaiting_ack: BTreeMap<u64, u64>,
+
+    /// Commitments and reveals sent for open rounds
+    sent: BTreeMap<u64, SentRound>,
+
+    /// Latest round the aggregator started
+    last_round_id: Option<u64>,
+}
+
+impl WorkerService {
+    pub fn new(worker: Worker, config: ServiceConfig) -> Self {
+        // Rounds restored from the journal were started before the restart
+        let last_round_id = worker.participating_rounds().into_iter().max();
+        WorkerService {
+            worker,
+            config,
+            awaiting_ack: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            last_round_id,
+        }
+    }
+
+    /// The worker driven by this service
+    pub fn worker(&self) -> &Worker {
+        &self.worker
+    }
+
+    /// Run until the inbound channel closes, sending replies on `outbound`
+    ///
+    /// Open rounds are kept when the loop stops, so a journaled worker can
+    /// still reveal them after reconnecting or restarting.
+    pub async fn run(
+        mut self,
+        mut inbound: mpsc::Receiver<ProtocolMessage>,
+        outbound: Outbound,
+    ) -> Result<()> {
+        info!("Worker {} protocol loop started", self.worker.get_node_id());
+        let mut expiry_check = tokio::time::interval(self.config.expiry_check_interval);
+        let mut heartbeat = tokio::time::interval(self.config.heartbeat_interval);
+        heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                message = inbound.recv() => {
+                    let Some(message) = message else {
+                        info!("Inbound message stream closed, stopping protocol loop");
+                        return Ok(());
+                    };
+
+                    let replies: Vec<ProtocolMessage> = match message {
+                        ProtocolMessage::HelloAck(ack) => {
+                            info!("Session with aggregator {} started", ack.node_id);
+                            // The aggregator pushes round messages once the session is proven ours
+                            let proof = self.worker.create_session_proof(&ack.node_id, &ack.challenge);
+                            std::iter::once(ProtocolMessage::SessionProof(proof)).chain(self.resume()).collect()
+                        }
+                        message => self.handle_message(message).into_iter().collect(),
+                    };
+                    for reply in replies {
+                        let sequence = outbound.send(reply.clone()).await?;
+                        self.sent(sequence, &reply);
+                    }
+                }
+                _ = expiry_check.tick() => {
+                    self.worker.expire_rounds();
+                    let worker = &self.worker;
+                    self.sent.retain(|round_id, _| worker.is_participating_in(*round_id));
+                }
+                _ = heartbeat.tick() => {
+                    outbound.send(ProtocolMessage::Heartbeat(self.worker.create_heartbeat())).await?;
+                }
+            }
+        }
+    }
+
+    /// Handle one message from the aggregator, returning the reply to send, if any
+    ///
+    /// Once the reply is sent, pass its sequence number to [`Self::sent`].
+    pub fn handle_message(&mut self, message: ProtocolMessage) -> Option<ProtocolMessage> {
+        if let Err(reason) = self.authenticate(&message) {
+            warn!("Dropping {} message: {}", message.kind(), reason);
+            return None;
+        }
+
+        match message {
+            ProtocolMessage::StartCommitment(msg) => self.on_start_commitment(&msg),
+            ProtocolMessage::StartReveal(msg) => self.on_start_reveal(&msg),
+            ProtocolMessage::RoundCompletion(msg) => {
+                self.sent.remove(&msg.round_id);
+                if self.worker.finish_round(msg.round_id) {
+                    info!("Round {} completed with {} participants", msg.round_id, msg.participants.len());
+                }
+                None
+            }
+            ProtocolMessage::Ack(ack) => {
+                let Some(round_id) = self.awaiting_ack.remove(&ack.sequence) else {
+                    debug!("Ignoring acknowledgement of message {}", ack.sequence);
+                    return None;
+                };
+                if ack.accepted {
+                    self.confirm(round_id);
+                } else {
+                    self.abort_round(round_id, "aggregator rejected our message");
+                }
+                None
+            }
+            ProtocolMessage::Error(e) => {
+                warn!("Aggregator reported error {}: {}", e.error_code, e.error_message);
+                if let Some(round_id) = e.sequence.and_then(|sequence| self.awaiting_ack.remove(&sequence)) {
+                    self.abort_round(round_id, "aggregator refused our message");
+                }
+                None
+            }
+            other => {
+                debug!("Ignoring {} message from aggregator", other.kind());
+                None
+            }
+        }
+    }
+
+    /// Note that `message` was sent with `sequence`
+    ///
+    /// Commitments and reveals then wait for the acknowledgement referring to it.
+    pub fn sent(&mut self, sequence: u64, message: &ProtocolMessage) {
+        let round_id = match message {
+            ProtocolMessage::Commitment(msg) => msg.round_id,
+            ProtocolMessage::Reveal(msg) => msg.round_id,
+            _ => return,
+        };
+        self.awaiting_ack.insert(sequence, round_id);
+        self.sent.entry(round_id).or_default().unacknowledged += 1;
+    }
+
+    /// Start over with a new connection, returning the messages to send again
+    ///
+    /// Replies to what was sent on the previous connection will not arrive, so
+    /// the latest unacknowledged message of every open round is repeated. An
+    /// aggregator that already has it acknowledges it again. As with replies,
+    /// pass the sequence number of each resent message to [`Self::sent`].
+    pub fn resume(&mut self) -> Vec<ProtocolMessage> {
+        self.awaiting_ack.clear();
+
+        let mut resent = Vec::new();
+        for (round_id, round) in self.sent.iter_mut() {
+            if round.unacknowledged == 0 || !self.worker.is_participating_in(*round_id) {
+                continue;
+            }
+            if let Some(message) = round.latest() {
+                info!("Resending {} for round {}", message.kind(), round_id);
+                round.unacknowledged = 0;
+                resent.push(message);
+            }
+        }
+        resent
+    }
+
+    /// Check that a control message is signed by the aggregator and is not a replay
+    ///
+    /// A start commitment must be for a later round than any started before, or
+    /// repeat one the worker is still taking part in. Reveals and completions can
+    /// only be for rounds that were started. Other messages pass unchecked.
+    fn authenticate(&mut self, message: &ProtocolMessage) -> Result<(), String> {
+        let (round_id, control): (u64, &dyn ControlMessage) = match message {
+            ProtocolMessage::StartCommitment(msg) => (msg.round_id, msg),
+            ProtocolMessage::StartReveal(msg) => (msg.round_id, msg),
+            ProtocolMessage::RoundCompletion(msg) => (msg.round_id, msg),
+            _ => return Ok(()),
+        };
+
+        if control.signature().is_empty() {
+            return Err("not signed by the aggregator".to_string());
+        }
+        let signed = self
+            .config
+            .aggregator_keys
+            .iter()
+            .any(|key| control.verify(&key.serialize()).unwrap_or(false));
+        if !signed {
+            return Err("not signed by a pinned aggregator key".to_string());
+        }
+
+        let started = self.last_round_id.is_some_and(|last| round_id <= last);
+        match message {
+            ProtocolMessage::StartCommitment(_) => {
+                if started && !self.worker.is_participating_in(round_id) {
+                    return Err(format!("round {} was already started", round_id));
+                }
+                self.last_round_id = Some(self.last_round_id.map_or(round_id, |last| last.max(round_id)));
+            }
+            _ if !started => return Err(format!("round {} was never started", round_id)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_start_commitment(&mut self, msg: &StartCommitmentMsg) -> Option<ProtocolMessage> {
+        if !msg.committee.iter().any(|id| id == self.worker.get_node_id()) {
+            debug!("Not in the committee for round {}, skipping", msg.round_id);
+            return None;
+        }
+
+        // An aggregator taking over the round asks again; answer with the same commitment
+        if let Some(commitment) = self.sent.get(&msg.round_id).and_then(SentRound::resend_commitment) {
+            debug!("Repeating commitment for round {}", msg.round_id);
+            return Some(ProtocolMessage::Commitment(commitment));
+        }
+
+        match self.worker.handle_start_commitment(msg) {
+            Ok(payload) => {
+                let commitment = CommitmentMsg {
+                    round_id: payload.round_id,
+                    payload,
+                    node_id: self.worker.get_node_id().to_string(),
+                    timestamp: unix_timestamp(),
+                };
+                self.sent.entry(msg.round_id).or_default().commitment = Some(commitment.clone());
+                Some(ProtocolMessage::Commitment(commitment))
+            }
+            Err(e) => {
+                // Leaves any round already committed to untouched
+                warn!("Not committing to round {}: {}", msg.round_id, e);
+                None
+            }
+        }
+    }
+
+    fn on_start_reveal(&mut self, msg: &StartRevealMsg) -> Option<ProtocolMessage> {
+        if !self.worker.is_participating_in(msg.round_id) {
+            warn!("Received start reveal for round {} without a commitment, ignoring", msg.round_id);
+            return None;
+        }
+
+        if let Some(reveal) = self.sent.get(&msg.round_id).and_then(SentRound::resend_reveal) {
+            debug!("Repeating reveal for round {}", msg.round_id);
+            return Some(ProtocolMessage::Reveal(reveal));
+        }
+
+        match self.worker.create_reveal_message(msg.round_id) {
+            Ok(reveal) => {
+                info!("Revealing secret for round {}", msg.round_id);
+                // Kept until the aggregator accepts it, in case it has to be sent again
+                self.sent.entry(msg.round_id).or_default().reveal = Some(reveal.clone());
+                Some(ProtocolMessage::Reveal(reveal))
+            }
+            Err(e) => {
+                error!("Failed to create reveal for round {}: {}", msg.round_id, e);
+                self.abort_round(msg.round_id, "reveal failed");
+                None
+            }
+        }
+    }
+
+    /// The aggregator accepted a message for `round_id`; an accepted reveal ends the round
+    fn confirm(&mut self, round_id: u64) {
+        let Some(round) = self.sent.get_mut(&round_id) else { return };
+
+        round.unacknowledged = round.unacknowledged.saturating_sub(1);
+        if round.unacknowledged == 0 && round.reveal.is_some() {
+            // Nothing is left to do for this round once the secret is out
+            self.sent.remove(&round_id);
+            self.worker.finish_round(round_id);
+        }
+    }
+
+    fn abort_round(&mut self, round_id: u64, reason: &str) {
+        self.sent.remove(&round_id);
+        if self.worker.finish_round(round_id) {
+            warn!("Aborting round {}: {}", round_id, reason);
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::WorkerConfig;
+    use entropy_types::protocol::error_codes;
+    use entropy_types::{AckMsg, ErrorMessage, HelloAckMsg, RoundCompletionMsg, PROTOCOL_VERSION};
+    use entropy_types::crypto::{verify_reveal, verify_session_proof};
+    use secp256k1::{Secp256k1, SecretKey};
+    use std::ops::Deref;
+
+    fn aggregator_key() -> SecretKey {
+        SecretKey::from_slice(&[9u8; 32]).unwrap()
+    }
+
+    fn service_config() -> ServiceConfig {
+        ServiceConfig {
+            aggregator_keys: vec![PublicKey::from_secret_key(&Secp256k1::new(), &aggregator_key())],
+            ..Default::default()
+        }
+    }
+
+    /// A service whose replies are numbered like the client does
+    struct Session {
+        service: WorkerService,
+        next_sequence: u64,
+    }
+
+    impl Session {
+        fn handle(&mut self, message: ProtocolMessage) -> Option<ProtocolMessage> {
+            let reply = self.service.handle_message(message);
+            if let Some(reply) = &reply {
+                self.send(reply);
+            }
+            reply
+        }
+
+        fn resume(&mut self) -> Vec<ProtocolMessage> {
+            let resent = self.service.resume();
+            for message in &resent {
+                self.send(message);
+            }
+            resent
+        }
+
+        fn send(&mut self, message: &ProtocolMessage) {
+            self.service.sent(self.next_sequence, message);
+            self.next_sequence += 1;
+        }
+    }
+
+    impl Deref for Session {
+        type Target = WorkerService;
+
+        fn deref(&self) -> &WorkerService {
+            &self.service
+        }
+    }
+
+    fn service(node_id: &str) -> Session {
+        let service = WorkerService::new(Worker::new(node_id.to_string()).unwrap(), service_config());
+        Session { service, next_sequence: 1 }
+    }
+
+    /// The messages with their timestamps cleared, as resent messages are stamped afresh
+    fn unstamped(messages: impl IntoIterator<Item = ProtocolMessage>) -> Vec<ProtocolMessage> {
+        messages
+            .into_iter()
+            .map(|message| match message {
+                ProtocolMessage::Commitment(msg) => ProtocolMessage::Commitment(CommitmentMsg { timestamp: 0, ..msg }),
+                ProtocolMessage::Reveal(msg) => ProtocolMessage::Reveal(RevealMsg { timestamp: 0, ..msg }),
+                message => message,
+            })
+            .collect()
+    }
+
+    fn start_commitment(round_id: u64, committee: &[&str]) -> ProtocolMessage {
+        let mut msg = StartCommitmentMsg {
+            round_id,
+            committee: committee.iter().map(|id| id.to_string()).collect(),
+            signature: Vec::new(),
+        };
+        msg.sign(&aggregator_key());
+        ProtocolMessage::StartCommitment(msg)
+    }
+
+    fn start_reveal(round_id: u64) -> ProtocolMessage {
+        let mut msg = StartRevealMsg { round_id, signature: Vec::new() };
+        msg.sign(&aggregator_key());
+        ProtocolMessage::StartReveal(msg)
+    }
+
+    fn accept(sequence: u64) -> ProtocolMessage {
+        ProtocolMessage::Ack(AckMsg { sequence, accepted: true })
+    }
+
+    #[test]
+    fn test_commit_reveal_round() {
+        let mut service = service("node-1");
+
+        let Some(ProtocolMessage::Commitment(commitment)) = service.handle(start_commitment(1, &["node-1"])) else {
+            panic!("expected commitment");
+        };
+        assert_eq!(commitment.round_id, 1);
+        assert_eq!(commitment.node_id, "node-1");
+
+        let reply = service.handle(start_reveal(1));
+        let Some(ProtocolMessage::Reveal(reveal)) = reply else { panic!("expected reveal") };
+        assert!(verify_reveal(1, reveal.payload.secret.expose(), &commitment.payload.commitment));
+
+        // The round ends once the aggregator accepted the reveal
+        service.handle(accept(1));
+        assert!(service.worker().is_participating());
+        service.handle(accept(2));
+        assert!(!service.worker().is_participating());
+    }
+
+    #[test]
+    fn test_consecutive_rounds() {
+        let mut service = service("node-1");
+
+        for round_id in 1..=3 {
+            assert!(service.handle(start_commitment(round_id, &["node-1"])).is_some());
+            let reply = service.handle(start_reveal(round_id));
+            assert!(matches!(reply, Some(ProtocolMessage::Reveal(r)) if r.round_id == round_id));
+        }
+    }
+
+    #[test]
+    fn test_skips_rounds_outside_committee() {
+        let mut service = service("node-1");
+
+        assert!(service.handle(start_commitment(1, &["node-2"])).is_none());
+        assert!(service.handle(start_reveal(1)).is_none());
+    }
+
+    #[test]
+    fn test_overlapping_rounds() {
+        let mut service = service("node-1");
+        service.handle(start_commitment(1, &["node-1"]));
+        service.handle(start_commitment(2, &["node-1"]));
+        assert_eq!(service.worker().participating_rounds(), vec![1, 2]);
+
+        // A repeated start is answered with the commitment already sent
+        let first = service.sent[&1].commitment.clone().unwrap();
+        assert_eq!(unstamped(service.handle(start_commitment(1, &["node-1"]))), unstamped([ProtocolMessage::Commitment(first)]));
+
+        assert!(matches!(service.handle(start_reveal(1)), Some(ProtocolMessage::Reveal(r)) if r.round_id == 1));
+        assert!(matches!(service.handle(start_reveal(2)), Some(ProtocolMessage::Reveal(r)) if r.round_id == 2));
+        for sequence in 1..=5 {
+            service.handle(accept(sequence));
+        }
+        assert!(!service.worker().is_participating());
+    }
+
+    #[test]
+    fn test_resumes_rounds_on_new_session() {
+        let mut service = service("node-1");
+
+        // The commitment for round 1 is lost with the connection, round 2's is acknowledged
+        let commitment = service.handle(start_commitment(1, &["node-1"])).unwrap();
+        service.handle(start_commitment(2, &["node-1"]));
+        let resent = unstamped([commitment.clone(), service.sent[&2].latest().unwrap()]);
+        assert_eq!(unstamped(service.resume()), resent);
+        service.handle(accept(3));
+        service.handle(accept(4));
+        assert!(service.resume().is_empty());
+
+        // The aggregator now running round 2 asks for the reveal, but never gets it
+        let Some(ProtocolMessage::Reveal(reveal)) = service.handle(start_reveal(2)) else {
+            panic!("expected reveal");
+        };
+        assert_eq!(unstamped(service.resume()), unstamped([ProtocolMessage::Reveal(reveal.clone())]));
+
+        // Asking again yields the same reveal; acknowledging it ends the round
+        assert_eq!(unstamped(service.handle(start_reveal(2))), unstamped([ProtocolMessage::Reveal(reveal)]));
+        service.handle(accept(6));
+        service.handle(accept(7));
+        assert_eq!(service.worker().participating_rounds(), vec![1]);
+        assert!(service.resume().is_empty());
+    }
+
+    #[test]
+    fn test_resumes_after_freshness_window() {
+        let mut service = service("node-1");
+        service.handle(start_commitment(1, &["node-1"]));
+        service.handle(start_commitment(2, &["node-1"]));
+        service.handle(accept(2));
+        service.handle(start_reveal(2));
+
+        // The connection stays down for longer than the aggregator's freshness window
+        let outage = 600;
+        service.service.sent.get_mut(&1).unwrap().commitment.as_mut().unwrap().timestamp -= outage;
+        service.service.sent.get_mut(&2).unwrap().reveal.as_mut().unwrap().timestamp -= outage;
+        let before = unstamped([
+            ProtocolMessage::Commitment(service.sent[&1].commitment.clone().unwrap()),
+            ProtocolMessage::Reveal(service.sent[&2].reveal.clone().unwrap()),
+        ]);
+
+        // Both are resent as of now, with their signed payloads unchanged
+        let resent = service.resume();
+        let now = unix_timestamp();
+        for message in &resent {
+            let timestamp = match message {
+                ProtocolMessage::Commitment(msg) => msg.timestamp,
+                ProtocolMessage::Reveal(msg) => msg.timestamp,
+                other => panic!("unexpected {}", other.kind()),
+            };
+            assert!(now - timestamp <= 1, "resent {} is {} seconds old", message.kind(), now - timestamp);
+        }
+        assert_eq!(unstamped(resent), before);
+    }
+
+    #[test]
+    fn test_rejection_aborts_its_round() {
+        let mut service = service("node-1");
+
+        service.handle(start_commitment(1, &["node-1"]));
+        service.handle(start_commitment(2, &["node-1"]));
+        service.handle(start_commitment(3, &["node-1"]));
+
+        // Replies refer to the sequence number of the message they answer
+        service.handle(ProtocolMessage::Ack(AckMsg { sequence: 3, accepted: false }));
+        service.handle(ProtocolMessage::Ack(AckMsg { sequence: 1, accepted: true }));
+        assert_eq!(service.worker().participating_rounds(), vec![1, 2]);
+        service.handle(ProtocolMessage::Ack(AckMsg { sequence: 2, accepted: false }));
+        assert_eq!(service.worker().participating_rounds(), vec![1]);
+
+        // Acknowledgements of anything else are ignored
+        service.handle(ProtocolMessage::Ack(AckMsg { sequence: 9, accepted: false }));
+        assert_eq!(service.worker().participating_rounds(), vec![1]);
+
+        let mut completion = RoundCompletionMsg {
+            round_id: 1,
+            entropy: [0u8; 32],
+            participants: vec![],
+            timestamp: 0,
+            signature: Vec::new(),
+        };
+        completion.sign(&aggregator_key());
+        service.handle(ProtocolMessage::RoundCompletion(completion));
+        assert!(!service.worker().is_participating());
+    }
+
+    #[test]
+    fn test_unrelated_error_leaves_rounds_alone() {
+        let mut service = service("node-1");
+
+        service.handle(start_commitment(1, &["node-1"]));
+        service.handle(start_commitment(2, &["node-1"]));
+
+        // An error about another message, e.g. a refused heartbeat, arrives first
+        service.handle(ProtocolMessage::Error(ErrorMessage::new(10, "replayed")));
+        assert_eq!(service.worker().participating_rounds(), vec![1, 2]);
+
+        // The acknowledgements still settle the rounds they refer to
+        service.handle(accept(1));
+        service.handle(ProtocolMessage::Ack(AckMsg { sequence: 2, accepted: false }));
+        assert_eq!(service.worker().participating_rounds(), vec![1]);
+        let Some(ProtocolMessage::Reveal(_)) = service.handle(start_reveal(1)) else { panic!("expected reveal") };
+        service.handle(accept(3));
+        assert!(!service.worker().is_participating());
+    }
+
+    #[test]
+    fn test_error_naming_a_message_aborts_its_round() {
+        let mut service = service("node-1");
+
+        service.handle(start_commitment(1, &["node-1"]));
+        service.handle(start_commitment(2, &["node-1"]));
+
+        // The commitment for round 2 arrived too late
+        let error = ErrorMessage::new(error_codes::STALE_MESSAGE, "message is stale").for_sequence(2);
+        service.handle(ProtocolMessage::Error(error));
+        assert_eq!(service.worker().participating_rounds(), vec![1]);
+    }
+
+    #[test]
+    fn test_rejects_unauthenticated_control_messages() {
+        let mut service = service("node-1");
+
+        // Unsigned, or signed by anyone but the pinned aggregator key
+        let unsigned = ProtocolMessage::StartCommitment(StartCommitmentMsg {
+            round_id: 1,
+            committee: vec!["node-1".to_string()],
+            signature: Vec::new(),
+        });
+        assert!(service.handle(unsigned).is_none());
+        let mut forged = StartCommitmentMsg { round_id: 1, committee: vec!["node-1".to_string()], signature: Vec::new() };
+        forged.sign(&SecretKey::from_slice(&[1u8; 32]).unwrap());
+        assert!(service.handle(ProtocolMessage::StartCommitment(forged)).is_none());
+        assert!(!service.worker().is_participating());
+
+        // A reveal cannot be asked for before its round started
+        assert!(service.handle(start_reveal(1)).is_none());
+    }
+
+    #[test]
+    fn test_rejects_replayed_start_messages() {
+        let mut service = service("node-1");
+
+        let old_start = start_commitment(1, &["node-1"]);
+        assert!(service.handle(old_start.clone()).is_some());
+        assert!(service.handle(start_reveal(1)).is_some());
+        service.handle(accept(1));
+        service.handle(accept(2));
+        assert!(!service.worker().is_participating());
+
+        // Replaying the finished round's start does not make the worker commit again
+        assert!(service.handle(old_start).is_none());
+        assert!(service.handle(start_commitment(3, &["node-1"])).is_some());
+        assert!(service.handle(start_commitment(2, &["node-1"])).is_none());
+        assert_eq!(service.worker().participating_rounds(), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_run_expires_rounds() {
+        let config = WorkerConfig {
+            round_retention: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let worker = Worker::new("node-1".to_string()).unwrap().with_config(config);
+        let config = ServiceConfig {
+            expiry_check_interval: Duration::from_millis(5),
+            heartbeat_interval: Duration::from_secs(60),
+            ..service_config()
+        };
+        let service = WorkerService::new(worker, config);
+
+        let (inbound_tx, inbound_rx) = mpsc::channel(8);
+        let (outbound_tx, mut outbound_rx) = Outbound::channel(8);
+        let handle = tokio::spawn(service.run(inbound_rx, outbound_tx));
+        assert!(matches!(outbound_rx.recv().await, Some((_, ProtocolMessage::Heartbeat(_)))));
+
+        inbound_tx.send(start_commitment(1, &["node-1"])).await.unwrap();
+        assert!(matches!(outbound_rx.recv().await, Some((_, ProtocolMessage::Commitment(_)))));
+
+        // The round expires before the aggregator asks for the reveal
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        inbound_tx.send(start_reveal(1)).await.unwrap();
+        drop(inbound_tx);
+        handle.await.unwrap().unwrap();
+        assert!(outbound_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_answers_inbound_messages() {
+        let (inbound_tx, inbound_rx) = mpsc::channel(8);
+        let (outbound_tx, mut outbound_rx) = Outbound::channel(8);
+        let session = service("node-1");
+        let public_key = session.worker().get_public_key().serialize();
+        let handle = tokio::spawn(session.service.run(inbound_rx, outbound_tx));
+
+        // The worker announces itself with a heartbeat as soon as it starts
+        let Some((_, ProtocolMessage::Heartbeat(heartbeat))) = outbound_rx.recv().await else {
+            panic!("expected heartbeat");
+        };
+        assert_eq!(heartbeat.node_id, "node-1");
+
+        // A new session is answered with the signed challenge
+        let ack = HelloAckMsg {
+            node_id: "aggregator".to_string(),
+            version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+            challenge: [3u8; 32],
+        };
+        inbound_tx.send(ProtocolMessage::HelloAck(ack)).await.unwrap();
+        let Some((_, ProtocolMessage::SessionProof(proof))) = outbound_rx.recv().await else {
+            panic!("expected session proof");
+        };
+        assert_eq!(verify_session_proof(&public_key, "aggregator", &[3u8; 32], &proof), Ok(true));
+
+        inbound_tx.send(start_commitment(7, &["node-1"])).await.unwrap();
+        assert!(matches!(outbound_rx.recv().await, Some((_, ProtocolMessage::Commitment(c))) if c.round_id == 7));
+
+        inbound_tx.send(start_reveal(7)).await.unwrap();
+        assert!(matches!(outbound_rx.recv().await, Some((_, ProtocolMessage::Reveal(r))) if r.round_id == 7));
+
+        // Closing the inbound stream stops the loop cleanly
+        drop(inbound_tx);
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -8,29 +8,28 @@ mod commitment_integration_tests {
     fn test_full_commitment_flow() {
         // 1. Generate a secret
         let secret = generate_secret().unwrap();
-        assert_eq!(secret.len(), 32);
+        assert_eq!(secret.expose().len(), 32);
 
         // 2. Generate keypair for the worker
         let (secret_key, public_key) = generate_keypair().unwrap();
 
         // 3. Compute commitment from secret
-        let commitment = compute_commitment(&secret);
+        let commitment = compute_commitment(42, secret.expose());
         assert_eq!(commitment.len(), 32);
 
         // 4. Sign the commitment
-        let signature = sign_commitment(&secret_key, &commitment).unwrap();
+        let signature = sign_commitment(&secret_key, 42, &commitment).unwrap();
         assert_eq!(signature.len(), 65); // 64 bytes for signature + 1 byte for recovery ID
 
         // 5. Create commitment payload
         let round_id = 42;
-        let payload = create_commitment_payload(round_id, &secret, &secret_key).unwrap();
+        let payload = create_commitment_payload(round_id, secret.expose(), &secret_key).unwrap();
         
         assert_eq!(payload.round_id, round_id);
         assert_eq!(payload.commitment, commitment);
         assert_eq!(payload.signature.len(), 65);
 
         println!("Full commitment flow test passed:");
-        println!("  Secret: {}", hex::encode(&secret));
         println!("  Commitment: {}", hex::encode(&commitment));
         println!(" Signature: {}", hex::encode(&signature));
         println!("  Round ID: {}", payload.round_id);
@@ -45,6 +44,7 @@ mod commitment_integration_tests {
         let start_msg = StartCommitmentMsg {
             round_id: 123,
             committee: vec!["test-worker-1".to_string()],
+            signature: Vec::new(),
         };
         
         // Handle the start commitment message
@@ -52,8 +52,8 @@ mod commitment_integration_tests {
         
         // Verify the payload
         assert_eq!(payload.round_id, 123);
-        assert_eq!(worker.get_current_round_id(), Some(123));
-        assert!(worker.get_current_secret().is_some());
+        assert_eq!(worker.participating_rounds(), vec![123]);
+        assert!(worker.get_secret(123).is_some());
         assert!(worker.is_participating());
         
         println!("Worker commitment generation test passed:");
@@ -72,6 +72,7 @@ mod commitment_integration_tests {
         let start_msg = StartCommitmentMsg {
             round_id: 456,
             committee: vec!["test-worker-1".to_string(), "test-worker-2".to_string()],
+            signature: Vec::new(),
         };
         
         // Both workers generate commitments
@@ -97,17 +98,15 @@ mod commitment_integration_tests {
         let (secret_key, _) = generate_keypair().unwrap();
         
         // Compute commitment
-        let commitment = compute_commitment(&known_secret);
+        let commitment = compute_commitment(789, &known_secret);
         
-        // Expected commitment using a known SHA256 implementation
-        let mut hasher = sha2::Sha256::new();
-        hasher.update(&known_secret);
-        let expected_commitment = hasher.finalize();
+        // Expected commitment using the shared protocol construction
+        let expected_commitment = entropy_types::crypto::compute_commitment(789, &known_secret);
         
-        assert_eq!(commitment, expected_commitment.as_slice());
+        assert_eq!(commitment, expected_commitment);
         
         // Sign the commitment
-        let signature = sign_commitment(&secret_key, &commitment).unwrap();
+        let signature = sign_commitment(&secret_key, 789, &commitment).unwrap();
         assert_eq!(signature.len(), 65);
         
         // Create payload
@@ -132,6 +131,7 @@ mod commitment_integration_tests {
         let start_msg = StartCommitmentMsg {
             round_id: 999,
             committee: vec!["end-to-end-worker".to_string(), "other-worker-1".to_string(), "other-worker-2".to_string()],
+            signature: Vec::new(),
         };
         
         // 3. Process the message and generate commitment
@@ -139,9 +139,9 @@ mod commitment_integration_tests {
         
         // 4. Verify all components of the payload
         assert_eq!(payload.round_id, 999);
-        assert_eq!(worker.get_current_round_id(), Some(999));
-        assert!(worker.get_current_secret().is_some());
-        assert_eq!(payload.commitment, compute_commitment(&worker.get_current_secret().unwrap()));
+        assert_eq!(worker.participating_rounds(), vec![999]);
+        assert!(worker.get_secret(999).is_some());
+        assert_eq!(payload.commitment, compute_commitment(999, worker.get_secret(999).unwrap().expose()));
         
         // 5. Verify signature length
         assert_eq!(payload.signature.len(), 65);
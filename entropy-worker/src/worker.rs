@@ -1,10 +1,51 @@
 use anyhow::Result;
-use entropy_types::{CommitmentPayload, StartCommitmentMsg, NodeId, RevealMsg, RevealPayload};
+use entropy_types::{CommitmentMsg, CommitmentPayload, HeartbeatMsg, StartCommitmentMsg, NodeId, RevealMsg, RevealPayload, RoundSecret, SessionProofMsg};
+use entropy_types::bundle::{BundleContent, OfflineBundle};
+use entropy_types::crypto::{sign_heartbeat, sign_reveal, sign_session_proof};
 use secp256k1::{SecretKey, PublicKey};
+use std::collections::BTreeMap;
 use std::net::TcpStream;
-use log::{info, debug};
+use std::time::{Duration, Instant};
+use log::{info, debug, warn, error};
 
-use crate::crypto::{generate_secret, compute_commitment, generate_keypair, create_commitment_payload};
+use crate::entropy::EntropyPool;
+use crate::journal::{JournalEntry, SecretJournal};
+use crate::keystore::Identity;
+use crate::crypto::{compute_commitment, generate_keypair, create_commitment_payload};
+
+/// Software version reported in heartbeats
+pub const WORKER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Limits on the rounds a worker keeps state for
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// How many rounds the worker may have committed to without revealing yet
+    pub max_open_rounds: usize,
+    
+    /// How long an open round is kept before its secret is discarded
+    pub round_retention: Duration,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            max_open_rounds: 8,
+            round_retention: Duration::from_secs(120),
+        }
+    }
+}
+
+/// State of a round the worker has committed to
+struct RoundState {
+    /// The secret generated for the round (kept in memory for reveal phase)
+    secret: RoundSecret,
+    
+    /// The commitment sent for the round
+    commitment: [u8; 32],
+    
+    /// When the worker committed to the round
+    opened_at: Instant,
+}
 
 /// Worker node state and configuration
 pub struct Worker {
@@ -17,36 +58,94 @@ pub struct Worker {
     /// Public key for verification
     public_key: PublicKey,
     
-    /// Current round ID the worker is participating in
-    current_round_id: Option<u64>,
-    
-    /// The secret generated for the current round (kept in memory for reveal phase)
-    current_secret: Option<[u8; 32]>,
+    /// Bounds on open rounds
+    config: WorkerConfig,
     
-    /// The commitment for the current round
-    current_commitment: Option<[u8; 32]>,
+    /// Rounds the worker has committed to and not yet finished, by round ID
+    rounds: BTreeMap<u64, RoundState>,
     
     /// Connection to the aggregator
     aggregator_connection: Option<TcpStream>,
+    
+    /// Write-ahead journal keeping round secrets across restarts
+    journal: Option<SecretJournal>,
+    
+    /// Health-tested sources the round secrets are drawn from
+    entropy: EntropyPool,
 }
 
 impl Worker {
     /// Create a new worker instance with generated keypair
+    ///
+    /// The key only lives as long as the process; use `from_identity` for a worker
+    /// that can be registered with an aggregator.
     pub fn new(node_id: NodeId) -> Result<Self> {
         let (secret_key, public_key) = generate_keypair()?;
         
-        Ok(Worker {
+        Ok(Self::with_keys(node_id, secret_key, public_key))
+    }
+    
+    /// Create a worker using a persistent identity from the keystore
+    pub fn from_identity(identity: &Identity) -> Self {
+        Self::with_keys(identity.node_id().to_string(), *identity.secret_key(), *identity.public_key())
+    }
+    
+    fn with_keys(node_id: NodeId, secret_key: SecretKey, public_key: PublicKey) -> Self {
+        Worker {
             node_id,
             secret_key,
             public_key,
-            current_round_id: None,
-            current_secret: None,
-            current_commitment: None,
+            config: WorkerConfig::default(),
+            rounds: BTreeMap::new(),
             aggregator_connection: None,
-        })
+            journal: None,
+            entropy: EntropyPool::system(),
+        }
+    }
+    
+    /// Use different bounds on open rounds
+    pub fn with_config(mut self, config: WorkerConfig) -> Self {
+        self.config = config;
+        self
+    }
+    
+    /// Draw round secrets from a different set of entropy sources
+    pub fn with_entropy(mut self, entropy: EntropyPool) -> Self {
+        self.entropy = entropy;
+        self
+    }
+    
+    /// Journal round secrets, restoring rounds left open by a previous run
+    ///
+    /// Restored rounds start a fresh retention period. If the journal holds more
+    /// rounds than may be open at once, the oldest ones are deleted.
+    pub fn with_journal(mut self, journal: SecretJournal) -> Result<Self> {
+        let mut entries = journal.load()?;
+        let excess = entries.len().saturating_sub(self.config.max_open_rounds);
+        
+        for stale in entries.drain(..excess) {
+            warn!("Discarding journaled secret for round {}: too many open rounds", stale.round_id);
+            journal.remove(stale.round_id)?;
+        }
+        
+        for entry in entries {
+            info!("Restored pending round {} from the journal", entry.round_id);
+            self.rounds.insert(entry.round_id, RoundState {
+                secret: entry.secret,
+                commitment: entry.commitment,
+                opened_at: Instant::now(),
+            });
+        }
+        
+        self.journal = Some(journal);
+        Ok(self)
     }
     
     /// Handle the start commitment message from the aggregator
+    ///
+    /// Fails if the worker is not in the committee, has already committed to the
+    /// round, already has the maximum number of rounds open, or an entropy source
+    /// failed its health tests.
     pub fn handle_start_commitment(&mut self, msg: &StartCommitmentMsg) -> Result<CommitmentPayload> {
         info!("Worker {} received start commitment for round {}", self.node_id, msg.round_id);
         
@@ -59,46 +158,122 @@ impl Worker {
             )));
         }
         
+        // A second commitment would replace the secret behind the first one
+        if self.rounds.contains_key(&msg.round_id) {
+            return Err(anyhow::Error::msg(format!(
+                "Worker {} has already committed to round {}",
+                self.node_id,
+                msg.round_id
+            )));
+        }
+        
+        self.expire_rounds();
+        if self.rounds.len() >= self.config.max_open_rounds {
+            return Err(anyhow::Error::msg(format!(
+                "Worker {} already has {} open rounds, refusing round {}",
+                self.node_id,
+                self.rounds.len(),
+                msg.round_id
+            )));
+        }
+        
         // Generate a new secret for this round
-        let secret = generate_secret()?;
-        debug!("Generated secret for round {}: {}", msg.round_id, hex::encode(&secret));
+        let secret = self.entropy.generate_secret()?;
         
         // Compute commitment from the secret
-        let commitment = compute_commitment(&secret);
-        debug!("Computed commitment for round {}: {}", msg.round_id, hex::encode(&commitment));
+        let commitment = compute_commitment(msg.round_id, secret.expose());
+        debug!("Computed commitment for round {}: {}", msg.round_id, hex::encode(commitment));
         
         // Create the commitment payload
-        let payload = create_commitment_payload(msg.round_id, &secret, &self.secret_key)?;
+        let payload = create_commitment_payload(msg.round_id, secret.expose(), &self.secret_key)?;
+        
+        // The secret must be on disk before anyone sees the commitment, otherwise a
+        // crash would leave a commitment that can never be opened
+        if let Some(journal) = &self.journal {
+            journal.record(&JournalEntry {
+                round_id: msg.round_id,
+                secret: secret.clone(),
+                commitment,
+            })?;
+        }
         
         // Store state for later use (reveal phase)
-        self.current_round_id = Some(msg.round_id);
-        self.current_secret = Some(secret);
-        self.current_commitment = Some(commitment);
+        self.rounds.insert(msg.round_id, RoundState {
+            secret,
+            commitment,
+            opened_at: Instant::now(),
+        });
         
         info!("Successfully created commitment payload for round {}", msg.round_id);
         Ok(payload)
     }
     
-    /// Get the current secret (for reveal phase)
-    pub fn get_current_secret(&self) -> Option<[u8; 32]> {
-        self.current_secret
+    /// Get the secret for a round (for reveal phase)
+    pub fn get_secret(&self, round_id: u64) -> Option<&RoundSecret> {
+        self.rounds.get(&round_id).map(|round| &round.secret)
+    }
+    
+    /// Get the commitment sent for a round
+    pub fn get_commitment(&self, round_id: u64) -> Option<[u8; 32]> {
+        self.rounds.get(&round_id).map(|round| round.commitment)
+    }
+    
+    /// Rounds the worker has committed to and not yet finished, in ascending order
+    pub fn participating_rounds(&self) -> Vec<u64> {
+        self.rounds.keys().copied().collect()
     }
     
-    /// Get the current round ID
-    pub fn get_current_round_id(&self) -> Option<u64> {
-        self.current_round_id
+    /// Check if the worker is participating in the given round
+    pub fn is_participating_in(&self, round_id: u64) -> bool {
+        self.rounds.contains_key(&round_id)
     }
     
-    /// Check if the worker is participating in a round
+    /// Check if the worker is participating in any round
     pub fn is_participating(&self) -> bool {
-        self.current_round_id.is_some()
+        !self.rounds.is_empty()
+    }
+    
+    /// Drop a round's state once it is revealed, completed or abandoned
+    ///
+    /// The round's journal entry is deleted, since its secret is no longer needed.
+    /// Returns whether the worker was participating in the round.
+    pub fn finish_round(&mut self, round_id: u64) -> bool {
+        if self.rounds.remove(&round_id).is_none() {
+            return false;
+        }
+        
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.remove(round_id) {
+                error!("Failed to remove journal entry for round {}: {}", round_id, e);
+            }
+        }
+        true
+    }
+    
+    /// Drop rounds that have been open longer than the retention period
+    ///
+    /// Returns the IDs of the dropped rounds.
+    pub fn expire_rounds(&mut self) -> Vec<u64> {
+        let retention = self.config.round_retention;
+        let expired: Vec<u64> = self
+            .rounds
+            .iter()
+            .filter(|(_, round)| round.opened_at.elapsed() > retention)
+            .map(|(round_id, _)| *round_id)
+            .collect();
+        
+        for round_id in &expired {
+            warn!("Round {} expired before it was revealed", round_id);
+            self.finish_round(*round_id);
+        }
+        expired
     }
     
-    /// Reset the worker's state for a new round
+    /// Drop the state of every open round
     pub fn reset_state(&mut self) {
-        self.current_round_id = None;
-        self.current_secret = None;
-        self.current_commitment = None;
+        for round_id in self.participating_rounds() {
+            self.finish_round(round_id);
+        }
     }
     
     /// Get the worker's public key
@@ -111,14 +286,66 @@ impl Worker {
         &self.node_id
     }
     
-    /// Create a reveal message for the current round
-    pub fn create_reveal_message(&self) -> Result<RevealMsg> {
-        if let (Some(round_id), Some(secret)) = (self.current_round_id, self.current_secret) {
+    /// Get the bounds on open rounds
+    pub fn config(&self) -> &WorkerConfig {
+        &self.config
+    }
+    
+    /// Get the entropy sources round secrets are drawn from
+    pub fn entropy(&self) -> &EntropyPool {
+        &self.entropy
+    }
+    
+    /// Create a signed heartbeat reporting whether the worker has rounds open
+    ///
+    /// If an entropy source failed, the status names it instead, since the worker
+    /// will not commit to any round until it is restarted.
+    pub fn create_heartbeat(&self) -> HeartbeatMsg {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let failures = self.entropy.failures();
+        let status = if !failures.is_empty() {
+            let failed: Vec<String> = failures
+                .iter()
+                .map(|(source, failure)| format!("{} ({})", source, failure))
+                .collect();
+            format!("entropy-failure: {}", failed.join(", "))
+        } else if self.is_participating() {
+            "participating".to_string()
+        } else {
+            "idle".to_string()
+        };
+        
+        let mut heartbeat = HeartbeatMsg {
+            node_id: self.node_id.clone(),
+            timestamp: now.as_secs(),
+            status,
+            version: WORKER_VERSION.to_string(),
+            sent_at_ms: now.as_millis() as u64,
+            signature: Vec::new(),
+        };
+        heartbeat.signature = sign_heartbeat(&self.secret_key, &heartbeat);
+        heartbeat
+    }
+
+    /// Sign the challenge of a connection to `aggregator_id`, so it is bound to this node
+    pub fn create_session_proof(&self, aggregator_id: &str, challenge: &[u8; 32]) -> SessionProofMsg {
+        SessionProofMsg {
+            node_id: self.node_id.clone(),
+            signature: sign_session_proof(&self.secret_key, aggregator_id, &self.node_id, challenge),
+        }
+    }
+    
+    /// Create a signed reveal message for a round the worker has committed to
+    pub fn create_reveal_message(&self, round_id: u64) -> Result<RevealMsg> {
+        if let Some(secret) = self.get_secret(round_id) {
             Ok(RevealMsg {
                 round_id,
                 payload: RevealPayload {
                     round_id,
-                    secret,
+                    secret: secret.clone(),
+                    signature: sign_reveal(&self.secret_key, &self.node_id, round_id, secret.expose()),
                 },
                 node_id: self.node_id.clone(),
                 timestamp: std::time::SystemTime::now()
@@ -127,9 +354,48 @@ impl Worker {
                     .as_secs(),
             })
         } else {
-            Err(anyhow::Error::msg("Worker is not participating in a round"))
+            Err(anyhow::Error::msg(format!("Worker is not participating in round {}", round_id)))
         }
     }
+    
+    /// Commit to a round without an aggregator, returning the signed commitment bundle
+    ///
+    /// The round stays open like one started by the aggregator, so with a journal
+    /// its secret survives until the reveal is exported.
+    pub fn export_commitment(&mut self, round_id: u64) -> Result<OfflineBundle> {
+        let msg = StartCommitmentMsg {
+            round_id,
+            committee: vec![self.node_id.clone()],
+            signature: Vec::new(),
+        };
+        let payload = self.handle_start_commitment(&msg)?;
+        let commitment = CommitmentMsg {
+            round_id,
+            payload,
+            node_id: self.node_id.clone(),
+            timestamp: unix_timestamp(),
+        };
+        Ok(OfflineBundle::sign(BundleContent::Commitment(commitment), unix_timestamp(), &self.secret_key))
+    }
+    
+    /// Create the signed reveal bundle for a round the worker has committed to
+    pub fn export_reveal(&self, round_id: u64) -> Result<OfflineBundle> {
+        let reveal = self.create_reveal_message(round_id)?;
+        Ok(OfflineBundle::sign(BundleContent::Reveal(reveal), unix_timestamp(), &self.secret_key))
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.secret_key.non_secure_erase();
+    }
 }
 
 #[cfg(test)]
@@ -143,8 +409,16 @@ mod tests {
         
         assert_eq!(worker.get_node_id(), "test-node-1");
         assert!(!worker.is_participating());
-        assert!(worker.get_current_secret().is_none());
-        assert!(worker.get_current_round_id().is_none());
+        assert!(worker.participating_rounds().is_empty());
+    }
+
+    #[test]
+    fn test_worker_from_identity() {
+        let identity = Identity::generate().unwrap();
+        let worker = Worker::from_identity(&identity);
+        
+        assert_eq!(worker.get_node_id(), identity.node_id());
+        assert_eq!(worker.get_public_key(), identity.public_key());
     }
 
     #[test]
@@ -154,14 +428,16 @@ mod tests {
         let start_msg = StartCommitmentMsg {
             round_id: 1,
             committee: vec!["test-node-2".to_string(), "test-node-3".to_string()],
+            signature: Vec::new(),
         };
         
         let payload = worker.handle_start_commitment(&start_msg).unwrap();
         
         assert_eq!(payload.round_id, 1);
-        assert_eq!(worker.get_current_round_id(), Some(1));
-        assert!(worker.get_current_secret().is_some());
-        assert!(worker.is_participating());
+        assert_eq!(worker.participating_rounds(), vec![1]);
+        assert_eq!(worker.get_commitment(1), Some(payload.commitment));
+        assert!(worker.get_secret(1).is_some());
+        assert!(worker.is_participating_in(1));
     }
 
     #[test]
@@ -171,12 +447,67 @@ mod tests {
         let start_msg = StartCommitmentMsg {
             round_id: 1,
             committee: vec!["test-node-2".to_string(), "test-node-3".to_string()],
+            signature: Vec::new(),
         };
         
         let result = worker.handle_start_commitment(&start_msg);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_pending_round_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("worker-journal-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let identity = Identity::generate().unwrap();
+        
+        let start_msg = StartCommitmentMsg {
+            round_id: 7,
+            committee: vec![identity.node_id().to_string()],
+            signature: Vec::new(),
+        };
+        let payload = {
+            let mut worker = Worker::from_identity(&identity).with_journal(SecretJournal::open(&dir).unwrap()).unwrap();
+            worker.handle_start_commitment(&start_msg).unwrap()
+            // Dropped without revealing, as if the process had crashed
+        };
+        
+        let mut worker = Worker::from_identity(&identity).with_journal(SecretJournal::open(&dir).unwrap()).unwrap();
+        assert_eq!(worker.participating_rounds(), vec![7]);
+        let reveal = worker.create_reveal_message(7).unwrap();
+        assert!(entropy_types::crypto::verify_reveal(7, reveal.payload.secret.expose(), &payload.commitment));
+        
+        // Once the round is over the secret is gone for good
+        assert!(worker.finish_round(7));
+        assert!(SecretJournal::open(&dir).unwrap().load().unwrap().is_empty());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_offline_bundles() {
+        let dir = std::env::temp_dir().join(format!("worker-offline-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let identity = Identity::generate().unwrap();
+        let public_key = identity.public_key().serialize();
+        
+        // Commit and reveal happen in separate runs of the air-gapped worker
+        let commitment = {
+            let mut worker = Worker::from_identity(&identity).with_journal(SecretJournal::open(&dir).unwrap()).unwrap();
+            worker.export_commitment(3).unwrap()
+        };
+        commitment.verify(&public_key).unwrap();
+        let BundleContent::Commitment(commitment) = commitment.content else { panic!("expected commitment") };
+        
+        let worker = Worker::from_identity(&identity).with_journal(SecretJournal::open(&dir).unwrap()).unwrap();
+        let reveal = worker.export_reveal(3).unwrap();
+        reveal.verify(&public_key).unwrap();
+        let BundleContent::Reveal(reveal) = reveal.content else { panic!("expected reveal") };
+        assert!(entropy_types::crypto::verify_reveal(3, reveal.payload.secret.expose(), &commitment.payload.commitment));
+        
+        assert!(worker.export_reveal(4).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_worker_state_reset() {
         let mut worker = Worker::new("test-node-5".to_string()).unwrap();
@@ -184,6 +515,7 @@ mod tests {
         let start_msg = StartCommitmentMsg {
             round_id: 1,
             committee: vec!["test-node-5".to_string()],
+            signature: Vec::new(),
         };
         
         worker.handle_start_commitment(&start_msg).unwrap();
@@ -191,7 +523,130 @@ mod tests {
         
         worker.reset_state();
         assert!(!worker.is_participating());
-        assert!(worker.get_current_secret().is_none());
-        assert!(worker.get_current_round_id().is_none());
+        assert!(worker.get_secret(1).is_none());
+        assert!(worker.participating_rounds().is_empty());
+    }
+
+    #[test]
+    fn test_signed_heartbeat() {
+        let mut worker = Worker::new("test-node-9".to_string()).unwrap();
+        let public_key = worker.get_public_key().serialize();
+        
+        let heartbeat = worker.create_heartbeat();
+        assert_eq!(heartbeat.node_id, "test-node-9");
+        assert_eq!(heartbeat.status, "idle");
+        assert_eq!(heartbeat.version, WORKER_VERSION);
+        assert_eq!(entropy_types::crypto::verify_heartbeat_signature(&public_key, &heartbeat), Ok(true));
+        
+        worker.handle_start_commitment(&StartCommitmentMsg { round_id: 1, committee: vec!["test-node-9".to_string()], signature: Vec::new() }).unwrap();
+        assert_eq!(worker.create_heartbeat().status, "participating");
+    }
+
+    #[test]
+    fn test_signed_reveal() {
+        let mut worker = Worker::new("test-node-10".to_string()).unwrap();
+        let public_key = worker.get_public_key().serialize();
+        
+        let start_msg = StartCommitmentMsg { round_id: 3, committee: vec!["test-node-10".to_string()], signature: Vec::new() };
+        let commitment = worker.handle_start_commitment(&start_msg).unwrap();
+        let reveal = worker.create_reveal_message(3).unwrap();
+        assert_eq!(
+            entropy_types::crypto::verify_reveal_signature(
+                &public_key,
+                "test-node-10",
+                3,
+                &commitment.commitment,
+                reveal.payload.secret.expose(),
+                &reveal.payload.signature,
+            ),
+            Ok(true)
+        );
+    }
+
+    /// A source whose device has gone away
+    struct Unplugged;
+
+    impl crate::entropy::EntropySource for Unplugged {
+        fn name(&self) -> &str {
+            "unplugged"
+        }
+
+        fn fill(&mut self, _buf: &mut [u8]) -> Result<()> {
+            Err(anyhow::Error::msg("device removed"))
+        }
+    }
+
+    #[test]
+    fn test_failed_entropy_source_blocks_commitments() {
+        let entropy = EntropyPool::system().with_source(Box::new(Unplugged));
+        let mut worker = Worker::new("test-node-10".to_string()).unwrap().with_entropy(entropy);
+        
+        let msg = StartCommitmentMsg { round_id: 1, committee: vec!["test-node-10".to_string()], signature: Vec::new() };
+        assert!(worker.handle_start_commitment(&msg).is_err());
+        assert!(!worker.is_participating());
+        
+        let status = worker.create_heartbeat().status;
+        assert!(status.starts_with("entropy-failure: unplugged"), "{}", status);
+    }
+
+    #[test]
+    fn test_concurrent_rounds() {
+        let mut worker = Worker::new("test-node-6".to_string()).unwrap();
+        let committee = vec!["test-node-6".to_string()];
+        
+        let first = worker.handle_start_commitment(&StartCommitmentMsg { round_id: 1, committee: committee.clone(), signature: Vec::new() }).unwrap();
+        let second = worker.handle_start_commitment(&StartCommitmentMsg { round_id: 2, committee, signature: Vec::new() }).unwrap();
+        assert_eq!(worker.participating_rounds(), vec![1, 2]);
+        
+        // Each round reveals its own secret, in any order
+        let reveal = worker.create_reveal_message(2).unwrap();
+        assert!(entropy_types::crypto::verify_reveal(2, reveal.payload.secret.expose(), &second.commitment));
+        assert!(worker.finish_round(2));
+        
+        let reveal = worker.create_reveal_message(1).unwrap();
+        assert!(entropy_types::crypto::verify_reveal(1, reveal.payload.secret.expose(), &first.commitment));
+        assert!(worker.finish_round(1));
+        assert!(!worker.finish_round(1));
+        assert!(worker.create_reveal_message(1).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_start_is_rejected() {
+        let mut worker = Worker::new("test-node-7".to_string()).unwrap();
+        let start_msg = StartCommitmentMsg {
+            round_id: 1,
+            committee: vec!["test-node-7".to_string()],
+            signature: Vec::new(),
+        };
+        
+        let payload = worker.handle_start_commitment(&start_msg).unwrap();
+        assert!(worker.handle_start_commitment(&start_msg).is_err());
+        
+        // The original secret is untouched
+        assert_eq!(worker.get_commitment(1), Some(payload.commitment));
+    }
+
+    #[test]
+    fn test_open_round_bounds() {
+        let config = WorkerConfig {
+            max_open_rounds: 2,
+            round_retention: Duration::from_millis(200),
+        };
+        let mut worker = Worker::new("test-node-8".to_string()).unwrap().with_config(config);
+        let start = |round_id| StartCommitmentMsg { round_id, committee: vec!["test-node-8".to_string()], signature: Vec::new() };
+        
+        worker.handle_start_commitment(&start(1)).unwrap();
+        worker.handle_start_commitment(&start(2)).unwrap();
+        assert!(worker.handle_start_commitment(&start(3)).is_err());
+        assert_eq!(worker.participating_rounds(), vec![1, 2]);
+        
+        // Expired rounds make room for new ones
+        std::thread::sleep(Duration::from_millis(250));
+        worker.handle_start_commitment(&start(3)).unwrap();
+        assert_eq!(worker.participating_rounds(), vec![3]);
+        
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(worker.expire_rounds(), vec![3]);
+        assert!(!worker.is_participating());
     }
 }
\ No newline at end of file
@@ -0,0 +1,246 @@
+//! Contributions carried between an air-gapped worker and the aggregator as files.
+//!
+//! A worker whose key never touches the network exports its commitment, and
+//! later its reveal, as an [`OfflineBundle`]. The bundle wraps the same
+//! message the worker would have sent over the network and is signed with the
+//! worker's key, so the aggregator can check who produced the file before
+//! processing its content.
+
+use serde::{Deserialize, Serialize};
+use secp256k1::SecretKey;
+use std::fmt;
+
+use crate::crypto::{sign_digest, tagged_hash, verify_digest_signature, BUNDLE_DOMAIN};
+use crate::{CommitmentMsg, NodeId, RevealMsg};
+
+/// Version of the bundle format written by this build
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// The message carried by a bundle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "message", rename_all = "snake_case")]
+pub enum BundleContent {
+    Commitment(CommitmentMsg),
+    Reveal(RevealMsg),
+}
+
+impl BundleContent {
+    /// Name of the message type, as used in the serialized form
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BundleContent::Commitment(_) => "commitment",
+            BundleContent::Reveal(_) => "reveal",
+        }
+    }
+
+    /// The round the message belongs to
+    pub fn round_id(&self) -> u64 {
+        match self {
+            BundleContent::Commitment(msg) => msg.round_id,
+            BundleContent::Reveal(msg) => msg.round_id,
+        }
+    }
+
+    /// The node the message claims to come from
+    pub fn node_id(&self) -> &str {
+        match self {
+            BundleContent::Commitment(msg) => &msg.node_id,
+            BundleContent::Reveal(msg) => &msg.node_id,
+        }
+    }
+}
+
+/// A signed commitment or reveal exported to a file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OfflineBundle {
+    pub format_version: u32,
+    pub node_id: NodeId,
+    pub round_id: u64,
+
+    /// Unix time in seconds when the bundle was exported
+    pub created_at: u64,
+
+    #[serde(flatten)]
+    pub content: BundleContent,
+
+    /// Recoverable signature by the worker's key over [`OfflineBundle::signing_digest`]
+    pub signature: Vec<u8>,
+}
+
+/// Why a bundle was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleError {
+    /// The bundle was written in a format this build does not read
+    UnsupportedVersion(u32),
+    /// The header disagrees with the message it carries
+    Inconsistent { message: String },
+    /// The signature does not verify against the node's key
+    InvalidSignature { message: String },
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::UnsupportedVersion(version) => write!(f, "Unsupported bundle format version {}", version),
+            BundleError::Inconsistent { message } => write!(f, "Inconsistent bundle: {}", message),
+            BundleError::InvalidSignature { message } => write!(f, "Invalid bundle signature: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl OfflineBundle {
+    /// Wrap a message and sign it with the worker's key
+    pub fn sign(content: BundleContent, created_at: u64, secret_key: &SecretKey) -> Self {
+        let mut bundle = OfflineBundle {
+            format_version: BUNDLE_FORMAT_VERSION,
+            node_id: content.node_id().to_string(),
+            round_id: content.round_id(),
+            created_at,
+            content,
+            signature: Vec::new(),
+        };
+        bundle.signature = sign_digest(secret_key, &bundle.signing_digest());
+        bundle
+    }
+
+    /// Compute the digest the worker signs
+    ///
+    /// Covers the format version (4 bytes BE), the node ID as
+    /// `len as u32 BE || bytes`, the round ID and export time (8 bytes BE), a
+    /// type byte (0 for commitments, 1 for reveals) and the message: the
+    /// commitment, its signature as `len as u32 BE || bytes` and its timestamp,
+    /// or the secret and its timestamp.
+    pub fn signing_digest(&self) -> [u8; 32] {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&self.format_version.to_be_bytes());
+        encoded.extend_from_slice(&(self.node_id.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(self.node_id.as_bytes());
+        encoded.extend_from_slice(&self.round_id.to_be_bytes());
+        encoded.extend_from_slice(&self.created_at.to_be_bytes());
+        match &self.content {
+            BundleContent::Commitment(msg) => {
+                encoded.push(0);
+                encoded.extend_from_slice(&msg.payload.commitment);
+                encoded.extend_from_slice(&(msg.payload.signature.len() as u32).to_be_bytes());
+                encoded.extend_from_slice(&msg.payload.signature);
+                encoded.extend_from_slice(&msg.timestamp.to_be_bytes());
+            }
+            BundleContent::Reveal(msg) => {
+                encoded.push(1);
+                encoded.extend_from_slice(msg.payload.secret.expose());
+                encoded.extend_from_slice(&msg.timestamp.to_be_bytes());
+            }
+        }
+        tagged_hash(BUNDLE_DOMAIN, &[&encoded])
+    }
+
+    /// Check the bundle's format and consistency and its signature against the node's key
+    pub fn verify(&self, public_key_bytes: &[u8]) -> Result<(), BundleError> {
+        if self.format_version != BUNDLE_FORMAT_VERSION {
+            return Err(BundleError::UnsupportedVersion(self.format_version));
+        }
+
+        let payload_round = match &self.content {
+            BundleContent::Commitment(msg) => msg.payload.round_id,
+            BundleContent::Reveal(msg) => msg.payload.round_id,
+        };
+        if self.content.node_id() != self.node_id {
+            return Err(BundleError::Inconsistent {
+                message: format!("bundle from {} carries a {} from {}", self.node_id, self.content.kind(), self.content.node_id()),
+            });
+        }
+        if self.content.round_id() != self.round_id || payload_round != self.round_id {
+            return Err(BundleError::Inconsistent {
+                message: format!("bundle for round {} carries a {} for another round", self.round_id, self.content.kind()),
+            });
+        }
+
+        match verify_digest_signature(public_key_bytes, &self.signing_digest(), &self.signature) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BundleError::InvalidSignature {
+                message: format!("not signed by the key registered for {}", self.node_id),
+            }),
+            Err(e) => Err(BundleError::InvalidSignature { message: e.to_string() }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{compute_commitment, sign_commitment, sign_reveal};
+    use crate::{CommitmentPayload, RevealPayload};
+    use secp256k1::{PublicKey, Secp256k1};
+
+    fn keys(seed: u8) -> (SecretKey, Vec<u8>) {
+        let secret_key = SecretKey::from_slice(&[seed; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        (secret_key, public_key.serialize().to_vec())
+    }
+
+    fn commitment(secret_key: &SecretKey) -> BundleContent {
+        let commitment = compute_commitment(4, &[9u8; 32]);
+        BundleContent::Commitment(CommitmentMsg {
+            round_id: 4,
+            payload: CommitmentPayload { round_id: 4, commitment, signature: sign_commitment(secret_key, 4, &commitment) },
+            node_id: "worker-1".to_string(),
+            timestamp: 100,
+        })
+    }
+
+    #[test]
+    fn test_signed_bundles_verify() {
+        let (secret_key, public_key) = keys(1);
+
+        let bundle = OfflineBundle::sign(commitment(&secret_key), 200, &secret_key);
+        assert_eq!(bundle.node_id, "worker-1");
+        assert_eq!(bundle.round_id, 4);
+        bundle.verify(&public_key).unwrap();
+
+        let reveal = BundleContent::Reveal(RevealMsg {
+            round_id: 4,
+            payload: RevealPayload {
+                round_id: 4,
+                secret: [9u8; 32].into(),
+                signature: sign_reveal(&secret_key, "worker-1", 4, &[9u8; 32]),
+            },
+            node_id: "worker-1".to_string(),
+            timestamp: 300,
+        });
+        let bundle = OfflineBundle::sign(reveal, 300, &secret_key);
+        bundle.verify(&public_key).unwrap();
+
+        // The file format is the tagged message next to the header
+        let json = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(json["type"], "reveal");
+        assert_eq!(json["message"]["round_id"], 4);
+        assert_eq!(serde_json::from_value::<OfflineBundle>(json).unwrap(), bundle);
+    }
+
+    #[test]
+    fn test_tampered_bundles_are_refused() {
+        let (secret_key, public_key) = keys(1);
+        let (_, other_key) = keys(2);
+        let bundle = OfflineBundle::sign(commitment(&secret_key), 200, &secret_key);
+
+        assert!(matches!(bundle.verify(&other_key), Err(BundleError::InvalidSignature { .. })));
+
+        let mut later = bundle.clone();
+        later.created_at += 1;
+        assert!(matches!(later.verify(&public_key), Err(BundleError::InvalidSignature { .. })));
+
+        let mut moved = bundle.clone();
+        moved.round_id = 5;
+        assert!(matches!(moved.verify(&public_key), Err(BundleError::Inconsistent { .. })));
+
+        let mut relabelled = bundle.clone();
+        relabelled.node_id = "worker-2".to_string();
+        assert!(matches!(relabelled.verify(&public_key), Err(BundleError::Inconsistent { .. })));
+
+        let mut future = bundle;
+        future.format_version = 2;
+        assert_eq!(future.verify(&public_key), Err(BundleError::UnsupportedVersion(2)));
+    }
+}
@@ -0,0 +1,255 @@
+//! Length-delimited framing shared by workers and the aggregator.
+//!
+//! Every frame is a 4-byte big-endian length followed by that many bytes of
+//! JSON-encoded [`ProtocolEnvelope`]. Frames larger than the configured maximum
+//! are rejected before any buffer is allocated for them.
+//!
+//! [`ProtocolCodec`] plugs into `tokio_util::codec::Framed` for async streams;
+//! [`read_envelope`] and [`write_envelope`] apply the same framing to blocking
+//! `std::io` streams.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::ProtocolEnvelope;
+
+/// Size of the length prefix in bytes
+pub const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Default upper bound for a frame body (1 MiB)
+pub const MAX_FRAME_LENGTH: usize = 1024 * 1024;
+
+/// Errors raised while framing protocol envelopes
+#[derive(Debug)]
+pub enum CodecError {
+    /// The underlying stream failed
+    Io(io::Error),
+    /// The frame announces or needs more bytes than allowed
+    FrameTooLarge { length: usize, max: usize },
+    /// The frame body is not a valid envelope; the stream is still in sync
+    Malformed(serde_json::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Io(e) => write!(f, "I/O error: {}", e),
+            CodecError::FrameTooLarge { length, max } => {
+                write!(f, "Frame of {} bytes exceeds limit of {} bytes", length, max)
+            }
+            CodecError::Malformed(e) => write!(f, "Malformed envelope: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<io::Error> for CodecError {
+    fn from(e: io::Error) -> Self {
+        CodecError::Io(e)
+    }
+}
+
+/// Codec turning a byte stream into [`ProtocolEnvelope`]s and back
+#[derive(Debug, Clone)]
+pub struct ProtocolCodec {
+    max_frame_length: usize,
+}
+
+impl ProtocolCodec {
+    /// Create a codec with the default maximum frame length
+    pub fn new() -> Self {
+        Self::with_max_frame_length(MAX_FRAME_LENGTH)
+    }
+
+    /// Create a codec with a custom maximum frame length
+    pub fn with_max_frame_length(max_frame_length: usize) -> Self {
+        Self { max_frame_length }
+    }
+
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), CodecError> {
+        if length > self.max_frame_length {
+            return Err(CodecError::FrameTooLarge { length, max: self.max_frame_length });
+        }
+        Ok(())
+    }
+}
+
+impl Default for ProtocolCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for ProtocolCodec {
+    type Item = ProtocolEnvelope;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let mut prefix = [0u8; LENGTH_PREFIX_SIZE];
+        prefix.copy_from_slice(&src[..LENGTH_PREFIX_SIZE]);
+        let length = u32::from_be_bytes(prefix) as usize;
+        self.check_length(length)?;
+
+        if src.len() < LENGTH_PREFIX_SIZE + length {
+            src.reserve(LENGTH_PREFIX_SIZE + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_PREFIX_SIZE);
+        let frame = src.split_to(length);
+        serde_json::from_slice(&frame).map(Some).map_err(CodecError::Malformed)
+    }
+}
+
+impl Encoder<&ProtocolEnvelope> for ProtocolCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: &ProtocolEnvelope, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let body = serde_json::to_vec(item).map_err(CodecError::Malformed)?;
+        self.check_length(body.len())?;
+
+        dst.reserve(LENGTH_PREFIX_SIZE + body.len());
+        dst.put_u32(body.len() as u32);
+        dst.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+impl Encoder<ProtocolEnvelope> for ProtocolCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: ProtocolEnvelope, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
+
+/// Write one framed envelope to a blocking stream
+pub fn write_envelope<W: Write>(
+    writer: &mut W,
+    codec: &mut ProtocolCodec,
+    envelope: &ProtocolEnvelope,
+) -> Result<(), CodecError> {
+    let mut buffer = BytesMut::new();
+    codec.encode(envelope, &mut buffer)?;
+    writer.write_all(&buffer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one framed envelope from a blocking stream
+pub fn read_envelope<R: Read>(reader: &mut R, codec: &mut ProtocolCodec) -> Result<ProtocolEnvelope, CodecError> {
+    let mut prefix = [0u8; LENGTH_PREFIX_SIZE];
+    reader.read_exact(&mut prefix)?;
+    let length = u32::from_be_bytes(prefix) as usize;
+    codec.check_length(length)?;
+
+    let mut buffer = BytesMut::with_capacity(LENGTH_PREFIX_SIZE + length);
+    buffer.extend_from_slice(&prefix);
+    buffer.resize(LENGTH_PREFIX_SIZE + length, 0);
+    reader.read_exact(&mut buffer[LENGTH_PREFIX_SIZE..])?;
+
+    match codec.decode(&mut buffer)? {
+        Some(envelope) => Ok(envelope),
+        None => Err(CodecError::Io(io::ErrorKind::UnexpectedEof.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeartbeatMsg, ProtocolMessage};
+
+    fn heartbeat(sequence: u64) -> ProtocolEnvelope {
+        ProtocolEnvelope::new(
+            sequence,
+            "worker-1".to_string(),
+            ProtocolMessage::Heartbeat(HeartbeatMsg {
+                node_id: "worker-1".to_string(),
+                timestamp: 1234567890,
+                status: "ok".to_string(),
+                version: "0.1.0".to_string(),
+                sent_at_ms: 1234567890000,
+                signature: vec![0u8; 65],
+            }),
+        )
+    }
+
+    #[test]
+    fn test_roundtrip_multiple_frames() {
+        let mut codec = ProtocolCodec::new();
+        let mut buffer = BytesMut::new();
+        codec.encode(&heartbeat(0), &mut buffer).unwrap();
+        codec.encode(&heartbeat(1), &mut buffer).unwrap();
+
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(heartbeat(0)));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(heartbeat(1)));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    }
+
+    #[test]
+    fn test_partial_frame_waits_for_more_data() {
+        let mut codec = ProtocolCodec::new();
+        let mut encoded = BytesMut::new();
+        codec.encode(&heartbeat(0), &mut encoded).unwrap();
+
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&encoded[..2]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(&encoded[2..10]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(&encoded[10..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(heartbeat(0)));
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected() {
+        let mut codec = ProtocolCodec::with_max_frame_length(16);
+
+        let mut buffer = BytesMut::new();
+        buffer.put_u32(17);
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(CodecError::FrameTooLarge { length: 17, max: 16 })
+        ));
+
+        let mut output = BytesMut::new();
+        assert!(matches!(codec.encode(&heartbeat(0), &mut output), Err(CodecError::FrameTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_malformed_frame_keeps_stream_in_sync() {
+        let mut codec = ProtocolCodec::new();
+        let mut buffer = BytesMut::new();
+        buffer.put_u32(8);
+        buffer.extend_from_slice(b"not json");
+        codec.encode(&heartbeat(1), &mut buffer).unwrap();
+
+        assert!(matches!(codec.decode(&mut buffer), Err(CodecError::Malformed(_))));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(heartbeat(1)));
+    }
+
+    #[test]
+    fn test_blocking_helpers() {
+        let mut codec = ProtocolCodec::new();
+        let mut wire = Vec::new();
+        write_envelope(&mut wire, &mut codec, &heartbeat(0)).unwrap();
+        write_envelope(&mut wire, &mut codec, &heartbeat(1)).unwrap();
+
+        let mut reader = io::Cursor::new(wire);
+        assert_eq!(read_envelope(&mut reader, &mut codec).unwrap(), heartbeat(0));
+        assert_eq!(read_envelope(&mut reader, &mut codec).unwrap(), heartbeat(1));
+        assert!(matches!(read_envelope(&mut reader, &mut codec), Err(CodecError::Io(_))));
+    }
+}
@@ -0,0 +1,741 @@
+//! Canonical protocol cryptography shared by workers, the aggregator and clients.
+//!
+//! Every hash in the protocol is a domain-separated SHA-256:
+//!
+//! ```text
+//! tagged_hash(domain, parts) = SHA256(domain || 0x00 || parts[0] || parts[1] || ...)
+//! ```
+//!
+//! Domains never contain a NUL byte, so the terminator keeps them prefix-free.
+//! Round IDs are always encoded as 8-byte big-endian integers. Signatures are
+//! 65-byte recoverable secp256k1 ECDSA signatures (64-byte compact signature
+//! followed by the recovery ID) over a 32-byte digest.
+//!
+//! The JSON vectors in `types/test-vectors/protocol_crypto.json` pin these
+//! encodings; the Rust crates and the TypeScript SDK all run against them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use sha2::{Digest, Sha256};
+
+use crate::protocol::{KeyRotationMsg, RegisterMsg, SessionProofMsg};
+use crate::{HeartbeatMsg, NodeId, RoundCompletionMsg, StartCommitmentMsg, StartRevealMsg};
+
+pub use secp256k1;
+
+/// Domain for the commitment to a round secret
+pub const COMMITMENT_DOMAIN: &[u8] = b"alea-entropy/v1/commitment";
+
+/// Domain for the digest a worker signs over its commitment
+pub const COMMITMENT_SIGNATURE_DOMAIN: &[u8] = b"alea-entropy/v1/commitment-signature";
+
+/// Domain for the digest a worker signs over its reveal
+pub const REVEAL_SIGNATURE_DOMAIN: &[u8] = b"alea-entropy/v1/reveal-signature";
+
+/// Domain for the final beacon output derived from the revealed secrets
+pub const OUTPUT_DOMAIN: &[u8] = b"alea-entropy/v1/output";
+
+/// Domain for the digest a worker signs over its heartbeat
+pub const HEARTBEAT_DOMAIN: &[u8] = b"alea-entropy/v1/heartbeat";
+
+/// Domain for the digest a worker signs over an offline contribution bundle
+pub const BUNDLE_DOMAIN: &[u8] = b"alea-entropy/v1/bundle";
+
+/// Domain for the digest a worker signs to register its public key
+pub const REGISTRATION_DOMAIN: &[u8] = b"alea-entropy/v1/registration";
+
+/// Domain for the digest a worker signs to bind a connection to its key
+pub const SESSION_DOMAIN: &[u8] = b"alea-entropy/v1/session";
+
+/// Domain for the digest both keys sign to rotate a worker's key
+pub const KEY_ROTATION_DOMAIN: &[u8] = b"alea-entropy/v1/key-rotation";
+
+/// Domain for the digest the aggregator signs over its control messages
+pub const CONTROL_DOMAIN: &[u8] = b"alea-entropy/v1/control";
+
+/// Domain for deriving a node's ID from its public key
+pub const NODE_ID_DOMAIN: &[u8] = b"alea-entropy/v1/node-id";
+
+/// Prefix of node IDs derived from public keys
+pub const NODE_ID_PREFIX: &str = "worker-";
+
+/// Length of a serialized recoverable signature (64-byte compact signature + recovery ID)
+pub const SIGNATURE_LENGTH: usize = 65;
+
+/// Errors raised when decoding keys or signatures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// Public key bytes are not a valid secp256k1 point
+    InvalidPublicKey,
+    /// Signature has the wrong length or cannot be parsed
+    InvalidSignature { message: String },
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidPublicKey => write!(f, "Invalid public key bytes"),
+            CryptoError::InvalidSignature { message } => write!(f, "Invalid signature: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// Hash `parts` under `domain` using the protocol's tagged-hash construction
+pub fn tagged_hash(domain: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(domain);
+    hasher.update([0u8]);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Compute the commitment a worker publishes for its round secret
+pub fn compute_commitment(round_id: u64, secret: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(COMMITMENT_DOMAIN, &[&round_id.to_be_bytes(), secret])
+}
+
+/// Compute the digest a worker signs to authenticate its commitment
+pub fn commitment_signing_digest(round_id: u64, commitment: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(COMMITMENT_SIGNATURE_DOMAIN, &[&round_id.to_be_bytes(), commitment])
+}
+
+/// Check that a revealed secret opens the given commitment for the round
+pub fn verify_reveal(round_id: u64, secret: &[u8; 32], commitment: &[u8; 32]) -> bool {
+    compute_commitment(round_id, secret) == *commitment
+}
+
+/// Derive the beacon output for a round from the revealed secrets
+///
+/// Reveals are ordered by NodeId (byte-wise) so the result does not depend on
+/// arrival order. Each entry is encoded as `len(node_id) as u32 BE || node_id || secret`,
+/// preceded by the round ID and the number of entries.
+pub fn derive_output<K: AsRef<str>, S: AsRef<[u8]>>(round_id: u64, reveals: &BTreeMap<K, S>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(OUTPUT_DOMAIN);
+    hasher.update([0u8]);
+    hasher.update(round_id.to_be_bytes());
+    hasher.update((reveals.len() as u32).to_be_bytes());
+    for (node_id, secret) in reveals {
+        let node_id = node_id.as_ref();
+        hasher.update((node_id.len() as u32).to_be_bytes());
+        hasher.update(node_id.as_bytes());
+        hasher.update(secret.as_ref());
+    }
+    hasher.finalize().into()
+}
+
+/// Derive the stable node ID for a public key
+///
+/// The ID is `worker-` followed by the first 16 bytes, hex encoded, of the tagged
+/// hash of the compressed public key, so it survives restarts and can be checked
+/// by anyone holding the key.
+pub fn node_id_from_public_key(public_key: &PublicKey) -> NodeId {
+    let digest = tagged_hash(NODE_ID_DOMAIN, &[&public_key.serialize()]);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", NODE_ID_PREFIX, hex)
+}
+
+/// Sign a 32-byte digest, returning the 65-byte recoverable signature
+pub fn sign_digest(secret_key: &SecretKey, digest: &[u8; 32]) -> Vec<u8> {
+    let secp = Secp256k1::signing_only();
+    let message = Message::from_digest(*digest);
+    let signature = secp.sign_ecdsa_recoverable(&message, secret_key);
+    let (recovery_id, compact) = signature.serialize_compact();
+
+    let mut signature_data = Vec::with_capacity(SIGNATURE_LENGTH);
+    signature_data.extend_from_slice(&compact);
+    signature_data.push(recovery_id.to_i32() as u8);
+    signature_data
+}
+
+/// Verify a 65-byte recoverable signature over a digest against a serialized public key
+///
+/// Returns `Ok(false)` for a well-formed signature that does not verify, and an
+/// error when the key or signature bytes cannot be decoded.
+pub fn verify_digest_signature(
+    public_key_bytes: &[u8],
+    digest: &[u8; 32],
+    signature_bytes: &[u8],
+) -> Result<bool, CryptoError> {
+    let public_key = PublicKey::from_slice(public_key_bytes).map_err(|_| CryptoError::InvalidPublicKey)?;
+
+    if signature_bytes.len() != SIGNATURE_LENGTH {
+        return Err(CryptoError::InvalidSignature {
+            message: format!("expected {} bytes, got {}", SIGNATURE_LENGTH, signature_bytes.len()),
+        });
+    }
+
+    let recovery_id = RecoveryId::from_i32(i32::from(signature_bytes[64])).map_err(|_| CryptoError::InvalidSignature {
+        message: "invalid recovery ID".to_string(),
+    })?;
+    let signature = RecoverableSignature::from_compact(&signature_bytes[..64], recovery_id)
+        .map_err(|e| CryptoError::InvalidSignature { message: e.to_string() })?
+        .to_standard();
+
+    let secp = Secp256k1::verification_only();
+    let message = Message::from_digest(*digest);
+    Ok(secp.verify_ecdsa(&message, &signature, &public_key).is_ok())
+}
+
+/// Sign a commitment for the given round
+pub fn sign_commitment(secret_key: &SecretKey, round_id: u64, commitment: &[u8; 32]) -> Vec<u8> {
+    sign_digest(secret_key, &commitment_signing_digest(round_id, commitment))
+}
+
+/// Verify a worker's signature over its commitment for the given round
+pub fn verify_commitment_signature(
+    public_key_bytes: &[u8],
+    round_id: u64,
+    commitment: &[u8; 32],
+    signature_bytes: &[u8],
+) -> Result<bool, CryptoError> {
+    verify_digest_signature(public_key_bytes, &commitment_signing_digest(round_id, commitment), signature_bytes)
+}
+
+/// Compute the digest a worker signs over its reveal
+///
+/// Binds the revealed secret to the node, the round and the commitment it opens.
+/// The node ID is encoded as `len as u32 BE || bytes` and the round ID as an
+/// 8-byte big-endian integer, followed by the commitment and the secret.
+pub fn reveal_signing_digest(node_id: &str, round_id: u64, commitment: &[u8; 32], secret: &[u8; 32]) -> [u8; 32] {
+    let mut encoded = Vec::new();
+    encoded.extend_from_slice(&(node_id.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(node_id.as_bytes());
+    tagged_hash(REVEAL_SIGNATURE_DOMAIN, &[&encoded, &round_id.to_be_bytes(), commitment, secret])
+}
+
+/// Sign a reveal of `secret` for the given round, returning the 65-byte recoverable signature
+pub fn sign_reveal(secret_key: &SecretKey, node_id: &str, round_id: u64, secret: &[u8; 32]) -> Vec<u8> {
+    let commitment = compute_commitment(round_id, secret);
+    sign_digest(secret_key, &reveal_signing_digest(node_id, round_id, &commitment, secret))
+}
+
+/// Verify a worker's signature over its reveal of the secret opening `commitment`
+pub fn verify_reveal_signature(
+    public_key_bytes: &[u8],
+    node_id: &str,
+    round_id: u64,
+    commitment: &[u8; 32],
+    secret: &[u8; 32],
+    signature_bytes: &[u8],
+) -> Result<bool, CryptoError> {
+    verify_digest_signature(
+        public_key_bytes,
+        &reveal_signing_digest(node_id, round_id, commitment, secret),
+        signature_bytes,
+    )
+}
+
+/// Compute the digest a worker signs to authenticate a heartbeat
+///
+/// Covers every field but the signature: the node ID, status and version as
+/// `len as u32 BE || bytes`, and the timestamps as 8-byte big-endian integers.
+pub fn heartbeat_signing_digest(heartbeat: &HeartbeatMsg) -> [u8; 32] {
+    let mut encoded = Vec::new();
+    for field in [&heartbeat.node_id, &heartbeat.status, &heartbeat.version] {
+        encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(field.as_bytes());
+    }
+    tagged_hash(
+        HEARTBEAT_DOMAIN,
+        &[&encoded, &heartbeat.timestamp.to_be_bytes(), &heartbeat.sent_at_ms.to_be_bytes()],
+    )
+}
+
+/// Sign a heartbeat, returning the 65-byte recoverable signature
+pub fn sign_heartbeat(secret_key: &SecretKey, heartbeat: &HeartbeatMsg) -> Vec<u8> {
+    sign_digest(secret_key, &heartbeat_signing_digest(heartbeat))
+}
+
+/// Verify the signature carried in a heartbeat
+pub fn verify_heartbeat_signature(public_key_bytes: &[u8], heartbeat: &HeartbeatMsg) -> Result<bool, CryptoError> {
+    verify_digest_signature(public_key_bytes, &heartbeat_signing_digest(heartbeat), &heartbeat.signature)
+}
+
+/// Compute the digest a worker signs to register its key with an aggregator
+///
+/// Binds the registration to the aggregator that issued the challenge. The
+/// aggregator ID, node ID, public key and each metadata key and value are encoded
+/// as `len as u32 BE || bytes`, the metadata in key order after its entry count,
+/// followed by the 32-byte challenge nonce.
+pub fn registration_signing_digest(aggregator_id: &str, registration: &RegisterMsg) -> [u8; 32] {
+    fn push(encoded: &mut Vec<u8>, field: &[u8]) {
+        encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(field);
+    }
+
+    let mut encoded = Vec::new();
+    push(&mut encoded, aggregator_id.as_bytes());
+    push(&mut encoded, registration.node_id.as_bytes());
+    push(&mut encoded, &registration.public_key);
+    encoded.extend_from_slice(&(registration.metadata.len() as u32).to_be_bytes());
+    for (key, value) in &registration.metadata {
+        push(&mut encoded, key.as_bytes());
+        push(&mut encoded, value.as_bytes());
+    }
+    tagged_hash(REGISTRATION_DOMAIN, &[&encoded, &registration.nonce])
+}
+
+/// Sign a registration for the given aggregator, returning the 65-byte recoverable signature
+pub fn sign_registration(secret_key: &SecretKey, aggregator_id: &str, registration: &RegisterMsg) -> Vec<u8> {
+    sign_digest(secret_key, &registration_signing_digest(aggregator_id, registration))
+}
+
+/// Verify that a registration is signed by the key it registers
+pub fn verify_registration_signature(aggregator_id: &str, registration: &RegisterMsg) -> Result<bool, CryptoError> {
+    verify_digest_signature(
+        &registration.public_key,
+        &registration_signing_digest(aggregator_id, registration),
+        &registration.signature,
+    )
+}
+
+/// Compute the digest a worker signs to prove a connection is its own
+///
+/// Binds the proof to the aggregator and the challenge of the connection's
+/// handshake. The aggregator ID and node ID are encoded as `len as u32 BE ||
+/// bytes`, followed by the 32-byte challenge nonce.
+pub fn session_signing_digest(aggregator_id: &str, node_id: &str, challenge: &[u8; 32]) -> [u8; 32] {
+    let mut encoded = Vec::new();
+    for field in [aggregator_id, node_id] {
+        encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(field.as_bytes());
+    }
+    tagged_hash(SESSION_DOMAIN, &[&encoded, challenge])
+}
+
+/// Sign the challenge of a connection to `aggregator_id`, returning the 65-byte recoverable signature
+pub fn sign_session_proof(secret_key: &SecretKey, aggregator_id: &str, node_id: &str, challenge: &[u8; 32]) -> Vec<u8> {
+    sign_digest(secret_key, &session_signing_digest(aggregator_id, node_id, challenge))
+}
+
+/// Verify that a session proof answers `challenge` and is signed with the given key
+pub fn verify_session_proof(
+    public_key_bytes: &[u8],
+    aggregator_id: &str,
+    challenge: &[u8; 32],
+    proof: &SessionProofMsg,
+) -> Result<bool, CryptoError> {
+    verify_digest_signature(
+        public_key_bytes,
+        &session_signing_digest(aggregator_id, &proof.node_id, challenge),
+        &proof.signature,
+    )
+}
+
+/// Compute the digest the old and the new key both sign to rotate a node's key
+///
+/// Binds the rotation to the aggregator it is sent to. The aggregator ID, node
+/// ID and both keys are encoded as `len as u32 BE || bytes`.
+pub fn key_rotation_signing_digest(aggregator_id: &str, rotation: &KeyRotationMsg) -> [u8; 32] {
+    let mut encoded = Vec::new();
+    let fields = [aggregator_id.as_bytes(), rotation.node_id.as_bytes(), &rotation.old_public_key, &rotation.new_public_key];
+    for field in fields {
+        encoded.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(field);
+    }
+    tagged_hash(KEY_ROTATION_DOMAIN, &[&encoded])
+}
+
+/// Sign a key rotation for the given aggregator with the old and the new secret key
+pub fn sign_key_rotation(
+    old_secret_key: &SecretKey,
+    new_secret_key: &SecretKey,
+    aggregator_id: &str,
+    rotation: &mut KeyRotationMsg,
+) {
+    let digest = key_rotation_signing_digest(aggregator_id, rotation);
+    rotation.old_signature = sign_digest(old_secret_key, &digest);
+    rotation.new_signature = sign_digest(new_secret_key, &digest);
+}
+
+/// Verify that a key rotation for `aggregator_id` is signed by both of its keys
+pub fn verify_key_rotation(aggregator_id: &str, rotation: &KeyRotationMsg) -> Result<bool, CryptoError> {
+    let digest = key_rotation_signing_digest(aggregator_id, rotation);
+    Ok(verify_digest_signature(&rotation.old_public_key, &digest, &rotation.old_signature)?
+        && verify_digest_signature(&rotation.new_public_key, &digest, &rotation.new_signature)?)
+}
+
+/// A round control message the aggregator signs with its identity key
+///
+/// The signed digest is `tagged_hash(CONTROL_DOMAIN, kind || fields)`, where the
+/// kind and every string are encoded as `len as u32 BE || bytes`, lists are
+/// preceded by their entry count as `u32 BE`, and integers are big-endian.
+/// Every field but the signature is covered.
+pub trait ControlMessage {
+    /// Digest the aggregator signs
+    fn signing_digest(&self) -> [u8; 32];
+
+    fn signature(&self) -> &[u8];
+
+    fn set_signature(&mut self, signature: Vec<u8>);
+
+    /// Sign the message with the aggregator's secret key
+    fn sign(&mut self, secret_key: &SecretKey) {
+        let signature = sign_digest(secret_key, &self.signing_digest());
+        self.set_signature(signature);
+    }
+
+    /// Verify the message's signature against the aggregator's public key
+    fn verify(&self, public_key_bytes: &[u8]) -> Result<bool, CryptoError> {
+        verify_digest_signature(public_key_bytes, &self.signing_digest(), self.signature())
+    }
+}
+
+/// Encoder for the fields of a control message digest
+struct ControlEncoder(Vec<u8>);
+
+impl ControlEncoder {
+    fn new(kind: &str, round_id: u64) -> Self {
+        let mut encoder = ControlEncoder(Vec::new());
+        encoder.bytes(kind.as_bytes());
+        encoder.0.extend_from_slice(&round_id.to_be_bytes());
+        encoder
+    }
+
+    fn bytes(&mut self, field: &[u8]) {
+        self.0.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(field);
+    }
+
+    fn nodes(&mut self, nodes: &[NodeId]) {
+        self.0.extend_from_slice(&(nodes.len() as u32).to_be_bytes());
+        for node_id in nodes {
+            self.bytes(node_id.as_bytes());
+        }
+    }
+
+    fn finish(self) -> [u8; 32] {
+        tagged_hash(CONTROL_DOMAIN, &[&self.0])
+    }
+}
+
+impl ControlMessage for StartCommitmentMsg {
+    fn signing_digest(&self) -> [u8; 32] {
+        let mut encoder = ControlEncoder::new("start_commitment", self.round_id);
+        encoder.nodes(&self.committee);
+        encoder.finish()
+    }
+
+    fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    fn set_signature(&mut self, signature: Vec<u8>) {
+        self.signature = signature;
+    }
+}
+
+impl ControlMessage for StartRevealMsg {
+    fn signing_digest(&self) -> [u8; 32] {
+        ControlEncoder::new("start_reveal", self.round_id).finish()
+    }
+
+    fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    fn set_signature(&mut self, signature: Vec<u8>) {
+        self.signature = signature;
+    }
+}
+
+impl ControlMessage for RoundCompletionMsg {
+    fn signing_digest(&self) -> [u8; 32] {
+        let mut encoder = ControlEncoder::new("round_completion", self.round_id);
+        encoder.0.extend_from_slice(&self.entropy);
+        encoder.nodes(&self.participants);
+        encoder.0.extend_from_slice(&self.timestamp.to_be_bytes());
+        encoder.finish()
+    }
+
+    fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    fn set_signature(&mut self, signature: Vec<u8>) {
+        self.signature = signature;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Vectors {
+        commitments: Vec<CommitmentVector>,
+        outputs: Vec<OutputVector>,
+    }
+
+    #[derive(Deserialize)]
+    struct CommitmentVector {
+        round_id: u64,
+        secret: String,
+        commitment: String,
+        signing_digest: String,
+        secret_key: String,
+        public_key: String,
+        signature: String,
+    }
+
+    #[derive(Deserialize)]
+    struct OutputVector {
+        round_id: u64,
+        reveals: Vec<RevealVector>,
+        output: String,
+    }
+
+    #[derive(Deserialize)]
+    struct RevealVector {
+        node_id: String,
+        secret: String,
+    }
+
+    fn vectors() -> Vectors {
+        serde_json::from_str(include_str!("../test-vectors/protocol_crypto.json")).unwrap()
+    }
+
+    fn bytes32(hex_str: &str) -> [u8; 32] {
+        hex::decode(hex_str).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_commitment_vectors() {
+        for vector in vectors().commitments {
+            let secret = bytes32(&vector.secret);
+            let commitment = compute_commitment(vector.round_id, &secret);
+
+            assert_eq!(hex::encode(commitment), vector.commitment);
+            assert!(verify_reveal(vector.round_id, &secret, &commitment));
+            assert_eq!(
+                hex::encode(commitment_signing_digest(vector.round_id, &commitment)),
+                vector.signing_digest
+            );
+        }
+    }
+
+    #[test]
+    fn test_signature_vectors() {
+        for vector in vectors().commitments {
+            let secret_key = SecretKey::from_slice(&hex::decode(&vector.secret_key).unwrap()).unwrap();
+            let public_key = hex::decode(&vector.public_key).unwrap();
+            let commitment = bytes32(&vector.commitment);
+
+            // RFC 6979 nonces make the signature deterministic
+            let signature = sign_commitment(&secret_key, vector.round_id, &commitment);
+            assert_eq!(hex::encode(&signature), vector.signature);
+            assert!(verify_commitment_signature(&public_key, vector.round_id, &commitment, &signature).unwrap());
+
+            // The signature must not verify for any other round
+            assert!(!verify_commitment_signature(&public_key, vector.round_id + 1, &commitment, &signature).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_output_vectors() {
+        for vector in vectors().outputs {
+            let reveals: BTreeMap<NodeId, [u8; 32]> = vector
+                .reveals
+                .iter()
+                .map(|r| (r.node_id.clone(), bytes32(&r.secret)))
+                .collect();
+
+            assert_eq!(hex::encode(derive_output(vector.round_id, &reveals)), vector.output);
+        }
+    }
+
+    #[test]
+    fn test_reveal_bound_to_round() {
+        let secret = [7u8; 32];
+        let commitment = compute_commitment(1, &secret);
+
+        assert!(verify_reveal(1, &secret, &commitment));
+        assert!(!verify_reveal(2, &secret, &commitment));
+        assert!(!verify_reveal(1, &[8u8; 32], &commitment));
+    }
+
+    #[test]
+    fn test_domains_are_separated() {
+        let data = [3u8; 32];
+        assert_ne!(compute_commitment(1, &data), commitment_signing_digest(1, &data));
+    }
+
+    #[test]
+    fn test_malformed_signature_is_an_error() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize();
+
+        assert!(verify_commitment_signature(&public_key, 1, &[0u8; 32], &[0u8; 64]).is_err());
+        assert_eq!(
+            verify_commitment_signature(&[0u8; 33], 1, &[0u8; 32], &[0u8; 65]),
+            Err(CryptoError::InvalidPublicKey)
+        );
+    }
+
+    #[test]
+    fn test_node_id_is_derived_from_public_key() {
+        let secp = Secp256k1::new();
+        let public_key = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1u8; 32]).unwrap());
+        let other = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2u8; 32]).unwrap());
+
+        let node_id = node_id_from_public_key(&public_key);
+        assert!(node_id.starts_with(NODE_ID_PREFIX));
+        assert_eq!(node_id.len(), NODE_ID_PREFIX.len() + 32);
+        assert_eq!(node_id, node_id_from_public_key(&public_key));
+        assert_ne!(node_id, node_id_from_public_key(&other));
+    }
+
+    #[test]
+    fn test_reveal_signature() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize();
+        let secret = [7u8; 32];
+        let commitment = compute_commitment(3, &secret);
+
+        let signature = sign_reveal(&secret_key, "worker-1", 3, &secret);
+        assert_eq!(verify_reveal_signature(&public_key, "worker-1", 3, &commitment, &secret, &signature), Ok(true));
+
+        // The signature does not carry over to another node, round or commitment
+        assert_eq!(verify_reveal_signature(&public_key, "worker-2", 3, &commitment, &secret, &signature), Ok(false));
+        assert_eq!(
+            verify_reveal_signature(&public_key, "worker-1", 4, &compute_commitment(4, &secret), &secret, &signature),
+            Ok(false)
+        );
+        assert_eq!(verify_reveal_signature(&public_key, "worker-1", 3, &[0u8; 32], &secret, &signature), Ok(false));
+    }
+
+    #[test]
+    fn test_control_message_signature() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize();
+        let other = PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[2u8; 32]).unwrap()).serialize();
+
+        let mut start = StartCommitmentMsg {
+            round_id: 7,
+            committee: vec!["worker-1".to_string(), "worker-2".to_string()],
+            signature: Vec::new(),
+        };
+        assert!(start.verify(&public_key).is_err());
+        start.sign(&secret_key);
+        assert_eq!(start.verify(&public_key), Ok(true));
+        assert_eq!(start.verify(&other), Ok(false));
+
+        // The round and committee are covered
+        let mut tampered = start.clone();
+        tampered.round_id = 8;
+        assert_eq!(tampered.verify(&public_key), Ok(false));
+        let mut tampered = start.clone();
+        tampered.committee.pop();
+        assert_eq!(tampered.verify(&public_key), Ok(false));
+
+        // A signature does not carry over to another kind of message for the same round
+        let reveal = StartRevealMsg { round_id: 7, signature: start.signature.clone() };
+        assert_eq!(reveal.verify(&public_key), Ok(false));
+    }
+
+    #[test]
+    fn test_heartbeat_signature() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize();
+
+        let mut heartbeat = HeartbeatMsg {
+            node_id: "worker-1".to_string(),
+            timestamp: 1_700_000_000,
+            status: "idle".to_string(),
+            version: "0.1.0".to_string(),
+            sent_at_ms: 1_700_000_000_123,
+            signature: Vec::new(),
+        };
+        heartbeat.signature = sign_heartbeat(&secret_key, &heartbeat);
+        assert_eq!(verify_heartbeat_signature(&public_key, &heartbeat), Ok(true));
+
+        // Every signed field is covered
+        let mut tampered = heartbeat.clone();
+        tampered.status = "participating".to_string();
+        assert_eq!(verify_heartbeat_signature(&public_key, &tampered), Ok(false));
+        let mut tampered = heartbeat.clone();
+        tampered.sent_at_ms += 1;
+        assert_eq!(verify_heartbeat_signature(&public_key, &tampered), Ok(false));
+    }
+
+    #[test]
+    fn test_registration_signature() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+
+        let mut registration = RegisterMsg {
+            node_id: node_id_from_public_key(&public_key),
+            public_key: public_key.serialize().to_vec(),
+            nonce: [9u8; 32],
+            metadata: BTreeMap::from([("operator".to_string(), "acme".to_string())]),
+            signature: Vec::new(),
+        };
+        registration.signature = sign_registration(&secret_key, "aggregator", &registration);
+        assert_eq!(verify_registration_signature("aggregator", &registration), Ok(true));
+
+        // The signature is bound to the aggregator, the challenge and the metadata
+        assert_eq!(verify_registration_signature("other-aggregator", &registration), Ok(false));
+        let mut tampered = registration.clone();
+        tampered.nonce = [8u8; 32];
+        assert_eq!(verify_registration_signature("aggregator", &tampered), Ok(false));
+        let mut tampered = registration.clone();
+        tampered.metadata.insert("region".to_string(), "eu".to_string());
+        assert_eq!(verify_registration_signature("aggregator", &tampered), Ok(false));
+    }
+
+    #[test]
+    fn test_session_proof() {
+        let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key).serialize();
+
+        let proof = SessionProofMsg {
+            node_id: "worker-1".to_string(),
+            signature: sign_session_proof(&secret_key, "aggregator", "worker-1", &[9u8; 32]),
+        };
+        assert_eq!(verify_session_proof(&public_key, "aggregator", &[9u8; 32], &proof), Ok(true));
+
+        // The proof is bound to the aggregator, the challenge and the node
+        assert_eq!(verify_session_proof(&public_key, "other-aggregator", &[9u8; 32], &proof), Ok(false));
+        assert_eq!(verify_session_proof(&public_key, "aggregator", &[8u8; 32], &proof), Ok(false));
+        let tampered = SessionProofMsg { node_id: "worker-2".to_string(), ..proof };
+        assert_eq!(verify_session_proof(&public_key, "aggregator", &[9u8; 32], &tampered), Ok(false));
+    }
+
+    #[test]
+    fn test_key_rotation_needs_both_keys() {
+        let secp = Secp256k1::new();
+        let old_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let new_key = SecretKey::from_slice(&[2u8; 32]).unwrap();
+        let other_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+
+        let mut rotation = KeyRotationMsg {
+            node_id: node_id_from_public_key(&PublicKey::from_secret_key(&secp, &old_key)),
+            old_public_key: PublicKey::from_secret_key(&secp, &old_key).serialize().to_vec(),
+            new_public_key: PublicKey::from_secret_key(&secp, &new_key).serialize().to_vec(),
+            old_signature: Vec::new(),
+            new_signature: Vec::new(),
+        };
+        sign_key_rotation(&old_key, &new_key, "aggregator", &mut rotation);
+        assert_eq!(verify_key_rotation("aggregator", &rotation), Ok(true));
+
+        // The rotation is bound to the aggregator it was signed for
+        assert_eq!(verify_key_rotation("other-aggregator", &rotation), Ok(false));
+
+        // A stolen old key alone cannot move the node to an attacker's key, nor the reverse
+        let mut forged = rotation.clone();
+        sign_key_rotation(&old_key, &other_key, "aggregator", &mut forged);
+        assert_eq!(verify_key_rotation("aggregator", &forged), Ok(false));
+        let mut forged = rotation.clone();
+        sign_key_rotation(&other_key, &new_key, "aggregator", &mut forged);
+        assert_eq!(verify_key_rotation("aggregator", &forged), Ok(false));
+    }
+}
@@ -1,5 +1,21 @@
 use serde::{Deserialize, Serialize};
 
+pub mod bundle;
+pub mod codec;
+pub mod crypto;
+pub mod protocol;
+pub mod schedule;
+pub mod secret;
+
+pub use bundle::{BundleContent, OfflineBundle};
+pub use codec::ProtocolCodec;
+pub use protocol::{
+    AckMsg, Capability, HelloAckMsg, HelloMsg, KeyRotationMsg, KeyRotationResultMsg, ProtocolEnvelope, ProtocolMessage,
+    RegisterMsg, RegisterResultMsg, RegistrationStatus, SessionProofMsg,
+};
+pub use schedule::RoundSchedule;
+pub use secret::RoundSecret;
+
 /// Protocol version constant
 pub const PROTOCOL_VERSION: u32 = 1;
 
@@ -14,24 +30,35 @@ pub struct CommitmentPayload {
     pub signature: Vec<u8>,
 }
 
-/// Reveal payload containing round ID and secret
+/// Reveal payload containing round ID, secret, and signature
+///
+/// Signed by the worker over `crypto::reveal_signing_digest`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RevealPayload {
     pub round_id: u64,
-    pub secret: [u8; 32],
+    pub secret: RoundSecret,
+    pub signature: Vec<u8>,
 }
 
 /// Start commitment message to initiate the commitment phase
+///
+/// Signed by the aggregator, see `crypto::ControlMessage`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct StartCommitmentMsg {
     pub round_id: u64,
     pub committee: Vec<NodeId>,
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 /// Start reveal message to initiate the reveal phase
+///
+/// Signed by the aggregator, see `crypto::ControlMessage`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct StartRevealMsg {
     pub round_id: u64,
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 /// Attestation report containing TEE-specific fields
@@ -81,11 +108,21 @@ pub struct EntropyResponse {
 }
 
 /// Heartbeat message for node health monitoring
+///
+/// Signed by the worker over `crypto::heartbeat_signing_digest`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct HeartbeatMsg {
     pub node_id: NodeId,
     pub timestamp: u64,
     pub status: String,
+    /// Software version of the sender
+    #[serde(default)]
+    pub version: String,
+    /// Send time in milliseconds since the Unix epoch, used to estimate latency
+    #[serde(default)]
+    pub sent_at_ms: u64,
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 /// Error message for protocol errors
@@ -94,15 +131,23 @@ pub struct ErrorMessage {
     pub error_code: u32,
     pub error_message: String,
     pub timestamp: u64,
+    /// Sequence number of the envelope the error refuses, like in an ack;
+    /// unset for errors about the connection rather than one message
+    #[serde(default)]
+    pub sequence: Option<u64>,
 }
 
 /// Round completion message
+///
+/// Signed by the aggregator, see `crypto::ControlMessage`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RoundCompletionMsg {
     pub round_id: u64,
     pub entropy: [u8; 32],
     pub participants: Vec<NodeId>,
     pub timestamp: u64,
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 #[cfg(test)]
@@ -127,7 +172,8 @@ mod tests {
     fn test_reveal_serialization() {
         let reveal = RevealPayload {
             round_id: 1,
-            secret: [5u8; 32],
+            secret: [5u8; 32].into(),
+            signature: vec![6u8, 7u8, 8u8],
         };
 
         let json = serde_json::to_string(&reveal).unwrap();
@@ -141,6 +187,7 @@ mod tests {
         let msg = StartCommitmentMsg {
             round_id: 1,
             committee: vec!["node1".to_string(), "node2".to_string()],
+            signature: Vec::new(),
         };
 
         let json = serde_json::to_string(&msg).unwrap();
@@ -153,6 +200,7 @@ mod tests {
     fn test_start_reveal_msg_serialization() {
         let msg = StartRevealMsg {
             round_id: 1,
+            signature: Vec::new(),
         };
 
         let json = serde_json::to_string(&msg).unwrap();
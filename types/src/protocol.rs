@@ -0,0 +1,420 @@
+//! Versioned protocol envelope and connection handshake.
+//!
+//! Every message on the wire is a [`ProtocolEnvelope`] carrying the protocol
+//! version, a per-connection sequence number, the sender and a tagged
+//! [`ProtocolMessage`]. A connection opens with the client sending
+//! [`ProtocolMessage::Hello`]; the server answers with
+//! [`ProtocolMessage::HelloAck`] carrying the negotiated version and
+//! capabilities, or with [`ProtocolMessage::Error`] if the peers are incompatible.
+//! A committee member then signs the hello ack's challenge with its key and
+//! sends [`ProtocolMessage::SessionProof`], after which the aggregator pushes
+//! round control messages to it over the connection.
+//!
+//! A worker whose key the aggregator does not know yet can register it on an
+//! established connection: it asks for a challenge with
+//! [`ProtocolMessage::RegisterRequest`], signs the [`ProtocolMessage::RegisterChallenge`]
+//! nonce with the key it registers and sends [`ProtocolMessage::Register`]. The
+//! aggregator answers with [`ProtocolMessage::RegisterResult`]. A registered
+//! worker replaces its key with [`ProtocolMessage::KeyRotation`], signed by both
+//! the old and the new key.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CommitmentMsg, ErrorMessage, HeartbeatMsg, NodeId, RevealMsg, RoundCompletionMsg, StartCommitmentMsg,
+    StartRevealMsg, PROTOCOL_VERSION,
+};
+
+/// Protocol versions this build can speak
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[PROTOCOL_VERSION];
+
+/// Error codes carried in [`ErrorMessage::error_code`]
+pub mod error_codes {
+    /// The peers share no protocol version
+    pub const INCOMPATIBLE_VERSION: u32 = 1;
+    /// A message other than `Hello` was sent before the handshake completed
+    pub const HANDSHAKE_REQUIRED: u32 = 2;
+    /// The message could not be decoded
+    pub const MALFORMED_MESSAGE: u32 = 3;
+    /// The message is valid but not expected in the current connection state
+    pub const UNEXPECTED_MESSAGE: u32 = 4;
+    /// The peer lacks a capability the other side requires
+    pub const MISSING_CAPABILITY: u32 = 5;
+    /// The message was decoded but rejected by the protocol
+    pub const MESSAGE_REJECTED: u32 = 6;
+    /// The sender is not a member of the committee
+    pub const NOT_IN_COMMITTEE: u32 = 7;
+    /// The message's timestamp is older than the receiver's freshness window
+    pub const STALE_MESSAGE: u32 = 8;
+    /// The message's timestamp is ahead of the receiver's clock by more than the tolerated skew
+    pub const FUTURE_MESSAGE: u32 = 9;
+    /// The envelope's sequence number is not above the last one received on the connection
+    pub const REPLAYED_MESSAGE: u32 = 10;
+}
+
+/// Optional protocol features negotiated during the handshake
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Basic commit-reveal participation
+    CommitReveal,
+    /// Proof-of-possession registration of worker keys
+    Registration,
+    /// Replacement of registered worker keys
+    KeyRotation,
+    /// A capability introduced by a newer peer that this build does not know
+    #[serde(other)]
+    Unknown,
+}
+
+/// Capabilities this build supports
+pub const SUPPORTED_CAPABILITIES: &[Capability] =
+    &[Capability::CommitReveal, Capability::Registration, Capability::KeyRotation];
+
+/// Handshake opening sent by the connecting peer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HelloMsg {
+    pub node_id: NodeId,
+    pub versions: Vec<u32>,
+    pub capabilities: Vec<Capability>,
+}
+
+/// Handshake answer carrying the negotiated parameters
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HelloAckMsg {
+    pub node_id: NodeId,
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+    /// Nonce the worker signs to prove it holds its node's key, see [`SessionProofMsg`]
+    #[serde(default)]
+    pub challenge: [u8; 32],
+}
+
+/// Proof that the peer of a connection holds the committee key of its node
+///
+/// Signed over `crypto::session_signing_digest` for the challenge of the
+/// handshake. The aggregator pushes round control messages for the node over
+/// the connection only once it accepted the proof.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionProofMsg {
+    pub node_id: NodeId,
+    #[serde(default)]
+    pub signature: Vec<u8>,
+}
+
+/// Acknowledgement of a commitment or reveal, referring to the request's sequence number
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AckMsg {
+    pub sequence: u64,
+    pub accepted: bool,
+}
+
+/// Request for a registration challenge
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegisterRequestMsg {
+    pub node_id: NodeId,
+}
+
+/// Nonce the worker must sign to prove it holds the key it registers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegisterChallengeMsg {
+    pub nonce: [u8; 32],
+}
+
+/// Registration of a worker's public key
+///
+/// Signed with the registered key over `crypto::registration_signing_digest`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegisterMsg {
+    /// Must be the node ID derived from `public_key`
+    pub node_id: NodeId,
+    /// SEC1-encoded public key
+    pub public_key: Vec<u8>,
+    /// The challenge nonce being answered
+    pub nonce: [u8; 32],
+    /// Free-form operator information (contact, region, ...)
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub signature: Vec<u8>,
+}
+
+/// Outcome of a registration
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationStatus {
+    /// The key is in the committee registry
+    Approved,
+    /// The registration waits for an operator to approve it
+    Pending,
+    /// The registration was refused; see the reason
+    Rejected,
+}
+
+/// Answer to a registration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegisterResultMsg {
+    pub node_id: NodeId,
+    pub status: RegistrationStatus,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Replacement of a registered key
+///
+/// Signed by both keys over `crypto::key_rotation_signing_digest`, for the
+/// aggregator it is sent to. The node keeps
+/// its ID; the new key takes over at the start of the aggregator's next round.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KeyRotationMsg {
+    pub node_id: NodeId,
+    /// SEC1-encoded key currently registered for the node
+    pub old_public_key: Vec<u8>,
+    /// SEC1-encoded key replacing it
+    pub new_public_key: Vec<u8>,
+    #[serde(default)]
+    pub old_signature: Vec<u8>,
+    #[serde(default)]
+    pub new_signature: Vec<u8>,
+}
+
+/// Answer to a key rotation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KeyRotationResultMsg {
+    pub node_id: NodeId,
+    pub accepted: bool,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Every message type exchanged between workers and the aggregator
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum ProtocolMessage {
+    Hello(HelloMsg),
+    HelloAck(HelloAckMsg),
+    SessionProof(SessionProofMsg),
+    Ack(AckMsg),
+    Commitment(CommitmentMsg),
+    Reveal(RevealMsg),
+    StartCommitment(StartCommitmentMsg),
+    StartReveal(StartRevealMsg),
+    Heartbeat(HeartbeatMsg),
+    Error(ErrorMessage),
+    RoundCompletion(RoundCompletionMsg),
+    RegisterRequest(RegisterRequestMsg),
+    RegisterChallenge(RegisterChallengeMsg),
+    Register(RegisterMsg),
+    RegisterResult(RegisterResultMsg),
+    KeyRotation(KeyRotationMsg),
+    KeyRotationResult(KeyRotationResultMsg),
+}
+
+impl ProtocolMessage {
+    /// Short name of the message type, used in logs and error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProtocolMessage::Hello(_) => "hello",
+            ProtocolMessage::HelloAck(_) => "hello_ack",
+            ProtocolMessage::SessionProof(_) => "session_proof",
+            ProtocolMessage::Ack(_) => "ack",
+            ProtocolMessage::Commitment(_) => "commitment",
+            ProtocolMessage::Reveal(_) => "reveal",
+            ProtocolMessage::StartCommitment(_) => "start_commitment",
+            ProtocolMessage::StartReveal(_) => "start_reveal",
+            ProtocolMessage::Heartbeat(_) => "heartbeat",
+            ProtocolMessage::Error(_) => "error",
+            ProtocolMessage::RoundCompletion(_) => "round_completion",
+            ProtocolMessage::RegisterRequest(_) => "register_request",
+            ProtocolMessage::RegisterChallenge(_) => "register_challenge",
+            ProtocolMessage::Register(_) => "register",
+            ProtocolMessage::RegisterResult(_) => "register_result",
+            ProtocolMessage::KeyRotation(_) => "key_rotation",
+            ProtocolMessage::KeyRotationResult(_) => "key_rotation_result",
+        }
+    }
+}
+
+/// Wire envelope wrapping a protocol message with version, sequence and sender
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProtocolEnvelope {
+    pub version: u32,
+    pub sequence: u64,
+    pub sender: NodeId,
+    pub message: ProtocolMessage,
+}
+
+impl ProtocolEnvelope {
+    /// Wrap a message using the current protocol version
+    pub fn new(sequence: u64, sender: NodeId, message: ProtocolMessage) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            sequence,
+            sender,
+            message,
+        }
+    }
+}
+
+impl ErrorMessage {
+    /// Create an error message stamped with the current time
+    pub fn new(error_code: u32, error_message: impl Into<String>) -> Self {
+        Self {
+            error_code,
+            error_message: error_message.into(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            sequence: None,
+        }
+    }
+
+    /// Refer the error to the envelope with `sequence`, the message it refuses
+    pub fn for_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+}
+
+/// Negotiated parameters of a connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+}
+
+/// Negotiate version and capabilities for a peer's hello
+///
+/// Picks the highest version both sides support and the intersection of known
+/// capabilities. Every capability in `required` must be offered by the peer.
+pub fn negotiate(
+    hello: &HelloMsg,
+    versions: &[u32],
+    capabilities: &[Capability],
+    required: &[Capability],
+) -> Result<Negotiated, ErrorMessage> {
+    let version = hello
+        .versions
+        .iter()
+        .filter(|v| versions.contains(v))
+        .max()
+        .copied()
+        .ok_or_else(|| {
+            ErrorMessage::new(
+                error_codes::INCOMPATIBLE_VERSION,
+                format!("no common protocol version: peer offers {:?}, we support {:?}", hello.versions, versions),
+            )
+        })?;
+
+    if let Some(missing) = required.iter().find(|c| !hello.capabilities.contains(c)) {
+        return Err(ErrorMessage::new(
+            error_codes::MISSING_CAPABILITY,
+            format!("peer does not offer required capability {:?}", missing),
+        ));
+    }
+
+    let mut common: Vec<Capability> = capabilities
+        .iter()
+        .filter(|c| **c != Capability::Unknown && hello.capabilities.contains(c))
+        .copied()
+        .collect();
+    common.dedup();
+
+    Ok(Negotiated { version, capabilities: common })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommitmentPayload;
+
+    fn hello(versions: Vec<u32>, capabilities: Vec<Capability>) -> HelloMsg {
+        HelloMsg {
+            node_id: "worker-1".to_string(),
+            versions,
+            capabilities,
+        }
+    }
+
+    #[test]
+    fn test_envelope_serialization() {
+        let envelope = ProtocolEnvelope::new(
+            7,
+            "worker-1".to_string(),
+            ProtocolMessage::Commitment(CommitmentMsg {
+                round_id: 1,
+                payload: CommitmentPayload {
+                    round_id: 1,
+                    commitment: [1u8; 32],
+                    signature: vec![2u8; 65],
+                },
+                node_id: "worker-1".to_string(),
+                timestamp: 1234567890,
+            }),
+        );
+
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["version"], PROTOCOL_VERSION);
+        assert_eq!(json["sequence"], 7);
+        assert_eq!(json["message"]["type"], "commitment");
+
+        let deserialized: ProtocolEnvelope = serde_json::from_value(json).unwrap();
+        assert_eq!(envelope, deserialized);
+    }
+
+    #[test]
+    fn test_unknown_capability_is_tolerated() {
+        let json = r#"{"node_id":"w","versions":[1],"capabilities":["commit_reveal","teleportation"]}"#;
+        let hello: HelloMsg = serde_json::from_str(json).unwrap();
+
+        assert_eq!(hello.capabilities, vec![Capability::CommitReveal, Capability::Unknown]);
+    }
+
+    #[test]
+    fn test_error_without_sequence() {
+        let json = r#"{"error_code":6,"error_message":"rejected","timestamp":1}"#;
+        let error: ErrorMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(error.sequence, None);
+
+        let error = ErrorMessage::new(error_codes::STALE_MESSAGE, "stale").for_sequence(4);
+        assert_eq!(serde_json::to_value(&error).unwrap()["sequence"], 4);
+    }
+
+    #[test]
+    fn test_negotiate_picks_highest_common_version() {
+        let negotiated = negotiate(
+            &hello(vec![1, 2, 3], vec![Capability::CommitReveal, Capability::Unknown]),
+            &[1, 2],
+            SUPPORTED_CAPABILITIES,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(negotiated.version, 2);
+        assert_eq!(negotiated.capabilities, vec![Capability::CommitReveal]);
+    }
+
+    #[test]
+    fn test_negotiate_rejects_incompatible_version() {
+        let err = negotiate(&hello(vec![99], vec![]), SUPPORTED_PROTOCOL_VERSIONS, SUPPORTED_CAPABILITIES, &[])
+            .unwrap_err();
+
+        assert_eq!(err.error_code, error_codes::INCOMPATIBLE_VERSION);
+    }
+
+    #[test]
+    fn test_negotiate_rejects_missing_capability() {
+        let err = negotiate(
+            &hello(vec![PROTOCOL_VERSION], vec![]),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            SUPPORTED_CAPABILITIES,
+            &[Capability::CommitReveal],
+        )
+        .unwrap_err();
+
+        assert_eq!(err.error_code, error_codes::MISSING_CAPABILITY);
+    }
+}
@@ -0,0 +1,84 @@
+//! Mapping between wall-clock time and round IDs.
+//!
+//! Rounds follow a fixed schedule, as in drand: round 1 starts at the genesis
+//! time and a new round starts every period after it, so round `n` covers
+//! `[genesis + (n - 1) * period, genesis + n * period)`. Anyone who knows the
+//! genesis time and period can tell which round covers a given time without
+//! asking the aggregator. Round 0 covers everything before genesis and is never
+//! run.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Genesis time and period of a round schedule
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundSchedule {
+    /// Start of round 1, in seconds since the Unix epoch
+    pub genesis: u64,
+    /// Seconds between the starts of consecutive rounds
+    pub period_secs: u64,
+}
+
+impl RoundSchedule {
+    /// Create a schedule
+    ///
+    /// Panics if `period` is shorter than a second.
+    pub fn new(genesis: u64, period: Duration) -> Self {
+        assert!(period.as_secs() > 0, "round period must be at least one second");
+        Self {
+            genesis,
+            period_secs: period.as_secs(),
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_secs(self.period_secs)
+    }
+
+    /// The round covering `time`, in seconds since the Unix epoch; 0 before genesis
+    pub fn round_at(&self, time: u64) -> u64 {
+        if time < self.genesis {
+            return 0;
+        }
+        (time - self.genesis) / self.period_secs + 1
+    }
+
+    /// Start time of a round, in seconds since the Unix epoch
+    ///
+    /// Round 0 is reported as starting at genesis, like round 1.
+    pub fn round_start(&self, round_id: u64) -> u64 {
+        self.genesis
+            .saturating_add(round_id.saturating_sub(1).saturating_mul(self.period_secs))
+    }
+
+    /// Start time of the first round starting after `time`
+    pub fn next_round_start(&self, time: u64) -> u64 {
+        self.round_start(self.round_at(time) + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_schedule() {
+        let schedule = RoundSchedule::new(1_700_000_000, Duration::from_secs(30));
+
+        assert_eq!(schedule.round_at(1_699_999_999), 0);
+        assert_eq!(schedule.round_at(1_700_000_000), 1);
+        assert_eq!(schedule.round_at(1_700_000_029), 1);
+        assert_eq!(schedule.round_at(1_700_000_030), 2);
+        assert_eq!(schedule.round_at(1_700_003_000), 101);
+
+        assert_eq!(schedule.round_start(1), 1_700_000_000);
+        assert_eq!(schedule.round_start(101), 1_700_003_000);
+        for round_id in 1..10 {
+            assert_eq!(schedule.round_at(schedule.round_start(round_id)), round_id);
+        }
+
+        assert_eq!(schedule.next_round_start(1_699_999_000), 1_700_000_000);
+        assert_eq!(schedule.next_round_start(1_700_000_000), 1_700_000_030);
+        assert_eq!(schedule.next_round_start(1_700_000_029), 1_700_000_030);
+    }
+}
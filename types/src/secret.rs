@@ -0,0 +1,100 @@
+//! Wrapper for round secrets.
+//!
+//! `RoundSecret` is overwritten with zeros when dropped and never prints its
+//! bytes through `Debug`, so secrets do not linger in freed memory or end up in
+//! logs. It serializes exactly like the `[u8; 32]` it wraps.
+//!
+//! Guarded memory is out of scope: secrets and keys live in ordinary pages,
+//! which are neither locked against swapping (`mlock`) nor excluded from core
+//! dumps (`MADV_DONTDUMP`). Zeroizing only shortens how long they stay in
+//! memory; hosts that must keep them off disk should disable swap and core
+//! dumps for the worker and aggregator.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// A 32-byte secret that is zeroized on drop and redacted in `Debug` output
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct RoundSecret([u8; 32]);
+
+impl RoundSecret {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        RoundSecret(bytes)
+    }
+
+    /// The secret bytes; callers must not log or keep copies of them
+    pub fn expose(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for RoundSecret {
+    fn from(bytes: [u8; 32]) -> Self {
+        RoundSecret(bytes)
+    }
+}
+
+impl AsRef<[u8]> for RoundSecret {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Compares in constant time, so comparisons do not leak where secrets differ
+impl PartialEq for RoundSecret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.iter().zip(other.0.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl Eq for RoundSecret {}
+
+impl fmt::Debug for RoundSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RoundSecret(<redacted>)")
+    }
+}
+
+impl Zeroize for RoundSecret {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for RoundSecret {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let secret = RoundSecret::new([0xab; 32]);
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "RoundSecret(<redacted>)");
+        assert!(!debug.contains("171"));
+    }
+
+    #[test]
+    fn test_serializes_like_an_array() {
+        let secret = RoundSecret::new([5u8; 32]);
+        assert_eq!(serde_json::to_string(&secret).unwrap(), serde_json::to_string(&[5u8; 32]).unwrap());
+
+        let decoded: RoundSecret = serde_json::from_str(&serde_json::to_string(&[5u8; 32]).unwrap()).unwrap();
+        assert_eq!(decoded, secret);
+        assert_ne!(decoded, RoundSecret::new([6u8; 32]));
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut secret = RoundSecret::new([7u8; 32]);
+        secret.zeroize();
+        assert_eq!(secret.expose(), &[0u8; 32]);
+    }
+}